@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use artisan_middleware::timestamp::current_timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::changes::CycleChanges;
+use crate::config::{CanaryAlert, DnssecMode, SiteConfig};
+use crate::doh::DnssecStatus;
+use crate::dual_stack::IpFamily;
+use crate::health::{CheckStatus, HealthCheckResult};
+use crate::site_state::SiteStates;
+use crate::tls::TlsVersion;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "INFO"),
+            Severity::Warning => write!(f, "WARNING"),
+            Severity::Critical => write!(f, "CRITICAL"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub url: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}: {}", self.severity, self.url, self.message)
+    }
+}
+
+// The most severe thing that happened this cycle, a DOWN site is always critical
+// unless its failure is in one of the site's non_alerting_categories
+pub fn cycle_severity(
+    sites: &[SiteConfig],
+    results: &HashMap<String, HealthCheckResult>,
+    alerts: &[Alert],
+) -> Severity {
+    let alert_severity = alerts.iter().map(|alert| alert.severity).max();
+    let status_severity = alerting_results(sites, results)
+        .map(|(_, result)| match result.status {
+            CheckStatus::Up => Severity::Info,
+            CheckStatus::Degraded => Severity::Warning,
+            CheckStatus::Down => Severity::Critical,
+        })
+        .max();
+
+    alert_severity
+        .max(status_severity)
+        .unwrap_or(Severity::Info)
+}
+
+// This cycle's results, leaving out failures the site doesn't alert on
+pub fn alerting_results<'a>(
+    sites: &'a [SiteConfig],
+    results: &'a HashMap<String, HealthCheckResult>,
+) -> impl Iterator<Item = (&'a String, &'a HealthCheckResult)> {
+    results.iter().filter(move |(url, result)| {
+        !sites
+            .iter()
+            .filter(|site| site.resolved_url() == **url)
+            .any(|site| site.is_non_alerting(result))
+    })
+}
+
+// Compare each page's size with its stored baseline, recording a baseline
+// the first time a size is seen
+pub fn check_size_growth(
+    results: &HashMap<String, HealthCheckResult>,
+    site_states: &mut SiteStates,
+    size_alert_bytes: Option<u64>,
+) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+
+    for (url, result) in results {
+        let body_bytes = match result.body_bytes {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+
+        let site = site_states.entry(url);
+        let baseline = match site.baseline_body_bytes {
+            Some(baseline) => baseline,
+            None => {
+                site.baseline_body_bytes = Some(body_bytes);
+                continue;
+            }
+        };
+
+        if let Some(threshold) = size_alert_bytes {
+            let growth = body_bytes.saturating_sub(baseline);
+            if growth > threshold {
+                alerts.push(Alert {
+                    url: url.clone(),
+                    severity: Severity::Warning,
+                    message: format!(
+                        "page size grew by {} bytes ({} -> {}), above the {} byte threshold",
+                        growth, baseline, body_bytes, threshold
+                    ),
+                });
+            }
+        }
+    }
+
+    alerts
+}
+
+// A recovery that isn't confirmed yet still counts against the cycle
+pub fn check_pending_recoveries(changes: &CycleChanges) -> Vec<Alert> {
+    changes
+        .pending_recovery
+        .iter()
+        .map(|pending| Alert {
+            url: pending.url.clone(),
+            severity: Severity::Warning,
+            message: format!(
+                "back up but not yet confirmed recovered ({} of {} cycles)",
+                pending.up_cycles, pending.required_cycles
+            ),
+        })
+        .collect()
+}
+
+// Flag HTTPS sites that negotiated an older protocol than allowed
+pub fn check_tls_versions(
+    results: &HashMap<String, HealthCheckResult>,
+    min_tls_version: Option<TlsVersion>,
+) -> Vec<Alert> {
+    let min_tls_version = match min_tls_version {
+        Some(min_tls_version) => min_tls_version,
+        None => return Vec::new(),
+    };
+
+    results
+        .iter()
+        .filter_map(|(url, result)| {
+            let negotiated = result.tls_version.as_deref()?;
+            match TlsVersion::from_protocol(negotiated) {
+                Some(version) if version >= min_tls_version => None,
+                _ => Some(Alert {
+                    url: url.clone(),
+                    severity: Severity::Warning,
+                    message: format!(
+                        "negotiated {} ({}), below the minimum {}",
+                        negotiated,
+                        result.tls_cipher.as_deref().unwrap_or("unknown cipher"),
+                        min_tls_version
+                    ),
+                }),
+            }
+        })
+        .collect()
+}
+
+// Certificate problems found by tls_audit, one alert per finding
+pub fn check_tls_findings(results: &HashMap<String, HealthCheckResult>) -> Vec<Alert> {
+    results
+        .iter()
+        .flat_map(|(url, result)| {
+            result.tls_findings.iter().map(|finding| Alert {
+                url: url.clone(),
+                severity: Severity::Warning,
+                message: finding.to_string(),
+            })
+        })
+        .collect()
+}
+
+// Alert when a host advertises AAAA records but can't be reached over IPv6, clients
+// preferring IPv6 may then be slow or fail even though the IPv4 check passes
+pub fn check_ipv6(results: &HashMap<String, HealthCheckResult>) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+
+    for (url, result) in results {
+        let ipv6 = result
+            .ip_families
+            .iter()
+            .find(|path| path.family == IpFamily::Ipv6 && !path.ok);
+        if let Some(ipv6) = ipv6 {
+            let ipv4 = match result
+                .ip_families
+                .iter()
+                .find(|path| path.family == IpFamily::Ipv4)
+            {
+                Some(ipv4) if ipv4.ok => String::from("IPv4 works"),
+                Some(_) => String::from("IPv4 fails too"),
+                None => String::from("no IPv4 address"),
+            };
+            alerts.push(Alert {
+                url: url.clone(),
+                severity: Severity::Warning,
+                message: format!(
+                    "AAAA record {} is advertised but IPv6 fails ({}), {}",
+                    ipv6.address,
+                    ipv6.error.as_deref().unwrap_or("no response"),
+                    ipv4
+                ),
+            });
+        }
+    }
+
+    alerts
+}
+
+// Alert when a redirect chain is longer than allowed, each hop adds a round trip
+pub fn check_redirect_hops(
+    sites: &[SiteConfig],
+    results: &HashMap<String, HealthCheckResult>,
+    default_max: Option<usize>,
+) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+
+    for site in sites {
+        let max_hops = match site.max_redirect_hops.or(default_max) {
+            Some(max_hops) => max_hops,
+            None => continue,
+        };
+        let url = site.resolved_url();
+        let result = match results.get(&url) {
+            Some(result) => result,
+            None => continue,
+        };
+        if result.redirects.len() > max_hops {
+            let total_ms: u128 = result.redirects.iter().map(|hop| hop.time_ms).sum();
+            alerts.push(Alert {
+                message: format!(
+                    "{} redirects (max {}), adding {} ms",
+                    result.redirects.len(),
+                    max_hops,
+                    total_ms
+                ),
+                url,
+                severity: Severity::Warning,
+            });
+        }
+    }
+
+    alerts
+}
+
+// Alert when a watched response header differs from the previous cycle's value
+pub fn check_watched_headers(
+    sites: &[SiteConfig],
+    results: &HashMap<String, HealthCheckResult>,
+    site_states: &mut SiteStates,
+    default_header: Option<&str>,
+) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+
+    for site in sites {
+        let header = match site.watch_header.as_deref().or(default_header) {
+            Some(header) => header,
+            None => continue,
+        };
+        let url = site.resolved_url();
+        let value = match results
+            .get(&url)
+            .and_then(|result| result.watched_header.as_ref())
+        {
+            Some(value) => value,
+            None => continue,
+        };
+
+        let previous = site_states
+            .entry(&url)
+            .watched_header
+            .replace(value.clone());
+        if let Some(previous) = previous.filter(|previous| previous != value) {
+            alerts.push(Alert {
+                url,
+                severity: Severity::Warning,
+                message: format!("{} header changed ({} -> {})", header, previous, value),
+            });
+        }
+    }
+
+    alerts
+}
+
+// Alert when a host that validated as secure no longer does, e.g. after an expired
+// signature or a registrar dropping the DS record
+pub fn check_dnssec(
+    sites: &[SiteConfig],
+    results: &HashMap<String, HealthCheckResult>,
+    site_states: &mut SiteStates,
+) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+
+    for site in sites {
+        let mode = match site.dnssec {
+            Some(mode) => mode,
+            None => continue,
+        };
+        let url = site.resolved_url();
+        let status = match results.get(&url).and_then(|result| result.dnssec) {
+            Some(status) => status,
+            None => continue,
+        };
+
+        let previous = site_states.entry(&url).dnssec_status.replace(status);
+        if mode == DnssecMode::Alert
+            && previous == Some(DnssecStatus::Secure)
+            && status != DnssecStatus::Secure
+        {
+            alerts.push(Alert {
+                url,
+                severity: Severity::Critical,
+                message: format!("DNSSEC status changed (secure -> {})", status),
+            });
+        }
+    }
+
+    alerts
+}
+
+// Compare each canary's value with the one remembered from earlier cycles
+pub fn check_canaries(
+    sites: &[SiteConfig],
+    results: &HashMap<String, HealthCheckResult>,
+    site_states: &mut SiteStates,
+) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+    let now = current_timestamp();
+
+    for site in sites {
+        let canary = match &site.canary {
+            Some(canary) => canary,
+            None => continue,
+        };
+        let url = site.resolved_url();
+        let result = match results.get(&url) {
+            Some(result) if !result.status.is_down() => result,
+            _ => continue,
+        };
+        let value = match &result.canary_value {
+            Some(value) => value,
+            None => {
+                alerts.push(Alert {
+                    url,
+                    severity: Severity::Warning,
+                    message: String::from("canary value could not be read from the response"),
+                });
+                continue;
+            }
+        };
+
+        let site_state = site_states.entry(&url);
+        let previous = site_state.canary_value.replace(value.clone());
+        let changed = previous.as_ref().is_some_and(|previous| previous != value);
+        if previous.is_none() || changed {
+            site_state.canary_changed_at = Some(now);
+        }
+
+        match canary.alert_on {
+            CanaryAlert::Changed if changed => alerts.push(Alert {
+                url,
+                severity: Severity::Warning,
+                message: format!(
+                    "canary value changed unexpectedly ({} -> {})",
+                    previous.unwrap_or_default(),
+                    value
+                ),
+            }),
+            CanaryAlert::Unchanged => {
+                let unchanged_for = now.saturating_sub(site_state.canary_changed_at.unwrap_or(now));
+                if unchanged_for > canary.window_seconds {
+                    alerts.push(Alert {
+                        url,
+                        severity: Severity::Warning,
+                        message: format!(
+                            "canary value {} unchanged for {}s (window {}s)",
+                            value, unchanged_for, canary.window_seconds
+                        ),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    alerts
+}
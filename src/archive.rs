@@ -0,0 +1,69 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use chrono::Utc;
+use chrono_tz::Tz;
+use dusa_collection_utils::errors::ErrorArrayItem;
+
+use crate::report::format_timestamp;
+
+// Appends each cycle's report to a local file, rotating it once it grows too large
+pub struct ReportArchive {
+    path: String,
+    max_bytes: u64,
+    keep: usize,
+    timezone: Tz,
+}
+
+impl ReportArchive {
+    pub fn new(path: &str, max_bytes: u64, keep: usize, timezone: Tz) -> Self {
+        ReportArchive {
+            path: path.to_string(),
+            max_bytes,
+            keep,
+            timezone,
+        }
+    }
+
+    pub fn append(&self, report: &str) -> Result<(), ErrorArrayItem> {
+        self.rotate_if_needed()?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(
+            file,
+            "========== {} ==========\n{}",
+            format_timestamp(Utc::now(), self.timezone),
+            report
+        )?;
+        Ok(())
+    }
+
+    // report.txt -> report.txt.1 -> report.txt.2 ..., dropping anything past `keep`
+    fn rotate_if_needed(&self) -> Result<(), ErrorArrayItem> {
+        let size = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return Ok(()),
+        };
+        if size < self.max_bytes {
+            return Ok(());
+        }
+
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+            return Ok(());
+        }
+
+        for index in (1..self.keep).rev() {
+            let from = format!("{}.{}", self.path, index);
+            if Path::new(&from).exists() {
+                fs::rename(&from, format!("{}.{}", self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, format!("{}.1", self.path))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use std::io::{Read, Write};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use dusa_collection_utils::errors::{ErrorArrayItem, Errors};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::config::AtRestConfig;
+
+// Encrypted payloads start with this, followed by the 12 byte nonce
+const ENCRYPTED_MAGIC: &[u8] = b"WMENC1";
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const NONCE_LEN: usize = 12;
+
+// Optional gzip and AES-256-GCM for the files the monitor persists. Decoding
+// recognises each layer by its header, so plaintext written before this was
+// turned on still loads.
+#[derive(Clone, Default)]
+pub struct AtRest {
+    compress: bool,
+    cipher: Option<Aes256Gcm>,
+}
+
+impl AtRest {
+    pub fn new(config: Option<&AtRestConfig>) -> Result<Self, ErrorArrayItem> {
+        let config = match config {
+            Some(config) => config,
+            None => return Ok(Self::default()),
+        };
+
+        let cipher = match &config.key_env {
+            Some(key_env) => {
+                let key = std::env::var(key_env).map_err(|_| {
+                    ErrorArrayItem::new(
+                        Errors::GeneralError,
+                        format!("Encryption key variable {} is not set", key_env),
+                    )
+                })?;
+                let key = hex::decode(key.trim())
+                    .ok()
+                    .filter(|key| key.len() == 32)
+                    .ok_or_else(|| {
+                        ErrorArrayItem::new(
+                            Errors::GeneralError,
+                            format!("{} must hold a 32 byte key as hex", key_env),
+                        )
+                    })?;
+                Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+            }
+            None => None,
+        };
+
+        Ok(AtRest {
+            compress: config.compress,
+            cipher,
+        })
+    }
+
+    // Whether encode changes anything, plaintext callers can skip the line encoding
+    pub fn is_plain(&self) -> bool {
+        !self.compress && self.cipher.is_none()
+    }
+
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>, ErrorArrayItem> {
+        let mut data = data.to_vec();
+        if self.compress {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&data)?;
+            data = encoder.finish()?;
+        }
+        if let Some(cipher) = &self.cipher {
+            let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+            let ciphertext = cipher
+                .encrypt(&nonce, data.as_slice())
+                .map_err(|e| ErrorArrayItem::new(Errors::GeneralError, e.to_string()))?;
+            data = [ENCRYPTED_MAGIC, nonce.as_slice(), &ciphertext].concat();
+        }
+        Ok(data)
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<u8>, ErrorArrayItem> {
+        let mut data = data.to_vec();
+        if let Some(encrypted) = data.strip_prefix(ENCRYPTED_MAGIC) {
+            let cipher = self.cipher.as_ref().ok_or_else(|| {
+                ErrorArrayItem::new(
+                    Errors::GeneralError,
+                    String::from("Data is encrypted but no key is configured"),
+                )
+            })?;
+            if encrypted.len() < NONCE_LEN {
+                return Err(ErrorArrayItem::new(
+                    Errors::GeneralError,
+                    String::from("Encrypted data is truncated"),
+                ));
+            }
+            let (nonce, ciphertext) = encrypted.split_at(NONCE_LEN);
+            data = cipher
+                .decrypt(Nonce::from_slice(nonce), ciphertext)
+                .map_err(|_| {
+                    ErrorArrayItem::new(
+                        Errors::GeneralError,
+                        String::from("Failed to decrypt data, wrong key?"),
+                    )
+                })?;
+        }
+        if data.starts_with(GZIP_MAGIC) {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(data.as_slice()).read_to_end(&mut decompressed)?;
+            data = decompressed;
+        }
+        Ok(data)
+    }
+
+    // Line oriented files (the history) encode each line on its own as base64
+    pub fn encode_line(&self, line: &str) -> Result<String, ErrorArrayItem> {
+        if self.is_plain() {
+            return Ok(line.to_string());
+        }
+        Ok(BASE64.encode(self.encode(line.as_bytes())?))
+    }
+
+    pub fn decode_line(&self, line: &str) -> Result<String, ErrorArrayItem> {
+        if line.starts_with('{') {
+            return Ok(line.to_string());
+        }
+        let data = BASE64
+            .decode(line.trim())
+            .map_err(|e| ErrorArrayItem::new(Errors::GeneralError, e.to_string()))?;
+        Ok(String::from_utf8_lossy(&self.decode(&data)?).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Keys go through the environment, each test uses its own variable
+    fn at_rest(key_env: &str, key: &str, compress: bool) -> AtRest {
+        std::env::set_var(key_env, key);
+        AtRest::new(Some(&AtRestConfig {
+            compress,
+            key_env: Some(key_env.to_string()),
+        }))
+        .expect("valid key")
+    }
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    const OTHER_KEY: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+    #[test]
+    fn encrypted_data_round_trips() {
+        let at_rest = at_rest("WM_TEST_AT_REST_ROUND_TRIP", KEY, false);
+        let encoded = at_rest.encode(b"site state").expect("encodes");
+        assert!(encoded.starts_with(ENCRYPTED_MAGIC));
+        assert_eq!(at_rest.decode(&encoded).expect("decodes"), b"site state");
+    }
+
+    #[test]
+    fn compressed_and_encrypted_data_round_trips() {
+        let at_rest = at_rest("WM_TEST_AT_REST_COMPRESSED", KEY, true);
+        let data = b"{\"status\":\"UP\"}".repeat(100);
+        let encoded = at_rest.encode(&data).expect("encodes");
+        assert_eq!(at_rest.decode(&encoded).expect("decodes"), data);
+    }
+
+    #[test]
+    fn lines_round_trip() {
+        let at_rest = at_rest("WM_TEST_AT_REST_LINES", KEY, true);
+        let line = at_rest
+            .encode_line("{\"url\":\"https://example.com\"}")
+            .expect("encodes");
+        assert!(!line.starts_with('{'));
+        assert_eq!(
+            at_rest.decode_line(&line).expect("decodes"),
+            "{\"url\":\"https://example.com\"}"
+        );
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() {
+        let encoded = at_rest("WM_TEST_AT_REST_WRITER", KEY, false)
+            .encode(b"site state")
+            .expect("encodes");
+        let reader = at_rest("WM_TEST_AT_REST_READER", OTHER_KEY, false);
+        assert!(reader.decode(&encoded).is_err());
+    }
+
+    #[test]
+    fn encrypted_data_without_a_key_is_rejected() {
+        let encoded = at_rest("WM_TEST_AT_REST_NO_KEY", KEY, false)
+            .encode(b"site state")
+            .expect("encodes");
+        assert!(AtRest::default().decode(&encoded).is_err());
+    }
+
+    #[test]
+    fn plaintext_still_loads() {
+        let at_rest = at_rest("WM_TEST_AT_REST_PLAIN", KEY, true);
+        assert_eq!(at_rest.decode(b"{}").expect("decodes"), b"{}");
+    }
+}
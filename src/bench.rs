@@ -0,0 +1,99 @@
+use std::fmt;
+use std::sync::Arc;
+
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+use crate::config::{AppSpecificConfig, SiteConfig};
+use crate::health::check_website_health;
+use crate::latency::percentile;
+use crate::prefetch::ResolvedHosts;
+
+// Outcome of firing a batch of checks at one url
+pub struct BenchSummary {
+    pub requests: usize,
+    pub errors: usize,
+    // Response times of every request that got an answer
+    pub latencies: Vec<u128>,
+    pub elapsed_ms: u128,
+}
+
+// Ad-hoc load test: `requests` checks of the url, at most `concurrency` in flight.
+// Each one is a full check_website_health, so https urls also get their TLS probe.
+pub async fn bench(
+    url: &str,
+    requests: usize,
+    concurrency: usize,
+    app: &AppSpecificConfig,
+) -> BenchSummary {
+    let site = Arc::new(SiteConfig::from_url(url));
+    let app = Arc::new(app.clone());
+    let prefetched = Arc::new(ResolvedHosts::new());
+
+    let mut summary = BenchSummary {
+        requests,
+        errors: 0,
+        latencies: Vec::with_capacity(requests),
+        elapsed_ms: 0,
+    };
+    let start = Instant::now();
+    let mut pending = JoinSet::new();
+    let mut started = 0;
+    loop {
+        while pending.len() < concurrency.max(1) && started < requests {
+            let (site, app, prefetched) = (site.clone(), app.clone(), prefetched.clone());
+            pending.spawn(async move {
+                check_website_health(&site, &app, &prefetched, app.local_address).await
+            });
+            started += 1;
+        }
+
+        match pending.join_next().await {
+            Some(Ok(result)) => {
+                if result.status.is_down() {
+                    summary.errors += 1;
+                }
+                if let Some(response_time) = result.response_time_ms {
+                    summary.latencies.push(response_time);
+                }
+            }
+            Some(Err(_)) => summary.errors += 1,
+            None => break,
+        }
+    }
+    summary.elapsed_ms = start.elapsed().as_millis();
+    summary
+}
+
+impl fmt::Display for BenchSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let error_rate = if self.requests > 0 {
+            self.errors as f64 / self.requests as f64 * 100.0
+        } else {
+            0.0
+        };
+        let per_second = if self.elapsed_ms > 0 {
+            self.requests as f64 / (self.elapsed_ms as f64 / 1000.0)
+        } else {
+            0.0
+        };
+        writeln!(
+            f,
+            "Requests: {} in {} ms ({:.1}/s)",
+            self.requests, self.elapsed_ms, per_second
+        )?;
+        writeln!(f, "Errors: {} ({:.1}%)", self.errors, error_rate)?;
+
+        if self.latencies.is_empty() {
+            return writeln!(f, "No responses, no latency distribution");
+        }
+        let mean = self.latencies.iter().sum::<u128>() / self.latencies.len() as u128;
+        let at = |percent: f64| percentile(&self.latencies, percent).unwrap_or(0);
+        writeln!(f, "Min: {} ms", at(0.0))?;
+        writeln!(f, "Mean: {} ms", mean)?;
+        writeln!(f, "p50: {} ms", at(50.0))?;
+        writeln!(f, "p90: {} ms", at(90.0))?;
+        writeln!(f, "p99: {} ms", at(99.0))?;
+        writeln!(f, "Max: {} ms", at(100.0))
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_yaml::Value;
+
+// blackbox_exporter applies this module when a probe doesn't name one
+const DEFAULT_MODULE: &str = "http_2xx";
+
+// blackbox_exporter's default when valid_status_codes is empty: any 2xx
+const SUCCESS_STATUSES: [u16; 10] = [200, 201, 202, 203, 204, 205, 206, 207, 208, 226];
+
+// Keys of a scrape job that only matter to Prometheus itself
+const SCRAPE_PLUMBING: [&str; 5] = [
+    "metrics_path",
+    "relabel_configs",
+    "scrape_interval",
+    "scrape_timeout",
+    "scheme",
+];
+
+// A blackbox.yml and/or a Prometheus config, both may live in one document
+#[derive(Debug, Default, Deserialize)]
+struct BlackboxDocument {
+    #[serde(default)]
+    modules: BTreeMap<String, Module>,
+    #[serde(default)]
+    scrape_configs: Vec<ScrapeConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Module {
+    #[serde(default)]
+    prober: String,
+    #[serde(default)]
+    timeout: Option<String>,
+    #[serde(default)]
+    http: Option<HttpProbe>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct HttpProbe {
+    #[serde(default)]
+    method: Option<String>,
+    #[serde(default)]
+    valid_status_codes: Vec<u16>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScrapeConfig {
+    #[serde(default)]
+    job_name: String,
+    #[serde(default)]
+    params: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    static_configs: Vec<StaticConfig>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StaticConfig {
+    #[serde(default)]
+    targets: Vec<String>,
+}
+
+// The Config.toml equivalent of blackbox modules and the probe jobs using them,
+// plus a warning for everything that has no counterpart here
+pub fn import(documents: &[String]) -> Result<(String, Vec<String>), String> {
+    let mut modules = BTreeMap::new();
+    let mut scrape_configs = Vec::new();
+    for document in documents {
+        let parsed: BlackboxDocument =
+            serde_yaml::from_str(document).map_err(|e| format!("invalid YAML: {}", e))?;
+        modules.extend(parsed.modules);
+        scrape_configs.extend(parsed.scrape_configs);
+    }
+
+    let mut warnings = Vec::new();
+    let mut sites = Vec::new();
+    for job in &scrape_configs {
+        let is_probe = job.params.contains_key("module")
+            || job.other.get("metrics_path").and_then(Value::as_str) == Some("/probe");
+        if !is_probe {
+            continue;
+        }
+        for key in job.other.keys() {
+            if !SCRAPE_PLUMBING.contains(&key.as_str()) {
+                warnings.push(format!(
+                    "job {}: {} is not imported, only static_configs targets are",
+                    job.job_name, key
+                ));
+            }
+        }
+
+        let module_name = job
+            .params
+            .get("module")
+            .and_then(|names| names.first())
+            .map(|name| name.as_str())
+            .unwrap_or(DEFAULT_MODULE);
+        let module = match modules.get(module_name) {
+            Some(module) => module,
+            None => {
+                warnings.push(format!(
+                    "job {}: module {} is not defined, skipping its targets",
+                    job.job_name, module_name
+                ));
+                continue;
+            }
+        };
+        if module.prober != "http" {
+            warnings.push(format!(
+                "job {}: the {} prober is not supported, skipping its targets",
+                job.job_name, module.prober
+            ));
+            continue;
+        }
+
+        for target in job.static_configs.iter().flat_map(|config| &config.targets) {
+            sites.push(toml::Value::Table(site_table(target, module)));
+        }
+    }
+
+    for (name, module) in &modules {
+        if module.prober != "http" {
+            continue;
+        }
+        for key in module.other.keys() {
+            warnings.push(format!("module {}: {} is not supported", name, key));
+        }
+        let http = match &module.http {
+            Some(http) => http,
+            None => continue,
+        };
+        for key in http.other.keys() {
+            warnings.push(format!("module {}: http.{} is not supported", name, key));
+        }
+        for header in http.headers.keys() {
+            if !header.eq_ignore_ascii_case("host") {
+                warnings.push(format!(
+                    "module {}: header {} is not supported, only Host is",
+                    name, header
+                ));
+            }
+        }
+    }
+
+    let mut websites = toml::Table::new();
+    websites.insert(String::from("urls"), toml::Value::Array(Vec::new()));
+    websites.insert(String::from("sites"), toml::Value::Array(sites));
+    let mut settings = toml::Table::new();
+    settings.insert(String::from("websites"), toml::Value::Table(websites));
+    let mut root = toml::Table::new();
+    root.insert(String::from("settings"), toml::Value::Table(settings));
+
+    let config = toml::to_string(&root).map_err(|e| e.to_string())?;
+    Ok((config, warnings))
+}
+
+// One [[settings.websites.sites]] entry, only what the module actually sets
+fn site_table(target: &str, module: &Module) -> toml::Table {
+    let mut site = toml::Table::new();
+    site.insert(String::from("url"), toml::Value::String(target.to_string()));
+
+    let http = module.http.as_ref();
+    let method = http
+        .and_then(|http| http.method.clone())
+        .unwrap_or_else(|| String::from("GET"))
+        .to_uppercase();
+    if method != "GET" {
+        site.insert(String::from("method"), toml::Value::String(method.clone()));
+    }
+
+    let statuses: Vec<u16> = match http.map(|http| &http.valid_status_codes) {
+        Some(statuses) if !statuses.is_empty() => statuses.clone(),
+        _ => SUCCESS_STATUSES.to_vec(),
+    };
+    let mut expected_status = toml::Table::new();
+    expected_status.insert(
+        method,
+        toml::Value::Array(
+            statuses
+                .into_iter()
+                .map(|status| toml::Value::Integer(status.into()))
+                .collect(),
+        ),
+    );
+    site.insert(
+        String::from("expected_status"),
+        toml::Value::Table(expected_status),
+    );
+
+    // A probe that times out fails, the closest thing here is the DOWN threshold
+    if let Some(timeout_ms) = module.timeout.as_deref().and_then(parse_duration_ms) {
+        site.insert(
+            String::from("max_response_time_ms"),
+            toml::Value::Integer(timeout_ms as i64),
+        );
+    }
+
+    let host = http.and_then(|http| {
+        http.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case("host"))
+            .map(|(_, host)| host.clone())
+    });
+    if let Some(host) = host {
+        site.insert(String::from("host_header"), toml::Value::String(host));
+    }
+    site
+}
+
+// Prometheus durations like "5s", "500ms" or "1m"
+fn parse_duration_ms(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse().ok()?;
+    let factor = match unit {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        _ => return None,
+    };
+    Some(amount * factor)
+}
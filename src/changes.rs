@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use crate::config::{AlertDedupKey, SiteConfig};
+use crate::health::{CheckStatus, HealthCheckResult};
+use crate::site_state::SiteStates;
+
+#[derive(Debug, Clone)]
+pub struct LatencyChange {
+    pub url: String,
+    pub previous_ms: u128,
+    pub current_ms: u128,
+}
+
+impl LatencyChange {
+    pub fn percent(&self) -> f64 {
+        (self.current_ms as f64 - self.previous_ms as f64) / self.previous_ms as f64 * 100.0
+    }
+}
+
+// A DOWN site that's back up but hasn't stayed up long enough to be announced
+#[derive(Debug, Clone)]
+pub struct PendingRecovery {
+    pub url: String,
+    pub up_cycles: u32,
+    pub required_cycles: u32,
+}
+
+// The announced status of a site changed
+#[derive(Debug, Clone)]
+pub struct StatusTransition {
+    pub url: String,
+    pub from: CheckStatus,
+    pub to: CheckStatus,
+    pub error: Option<String>,
+}
+
+// What's different from the previous cycle
+#[derive(Debug, Clone, Default)]
+pub struct CycleChanges {
+    pub newly_down: Vec<String>,
+    pub recovered: Vec<String>,
+    pub latency: Vec<LatencyChange>,
+    pub pending_recovery: Vec<PendingRecovery>,
+    pub transitions: Vec<StatusTransition>,
+}
+
+impl CycleChanges {
+    pub fn is_empty(&self) -> bool {
+        self.newly_down.is_empty() && self.recovered.is_empty() && self.latency.is_empty()
+    }
+}
+
+// Diff this cycle against the last one remembered in the site state, then
+// remember this cycle for next time
+pub fn compute_changes(
+    sites: &[SiteConfig],
+    results: &HashMap<String, HealthCheckResult>,
+    site_states: &mut SiteStates,
+    latency_change_percent: f64,
+    recovery_confirm_cycles: u32,
+    dedup_key: AlertDedupKey,
+) -> CycleChanges {
+    let mut changes = CycleChanges::default();
+
+    for site in sites {
+        let url = site.resolved_url();
+        let result = match results.get(&url) {
+            Some(result) => result,
+            None => continue,
+        };
+        // Failures the site doesn't alert on leave the announced status as it was
+        if site.is_non_alerting(result) {
+            continue;
+        }
+        let site_state = site_states.entry(&url);
+
+        // last_status is the announced status, a recovery only replaces it once confirmed
+        let mut status = result.status;
+        let category = result
+            .error
+            .as_ref()
+            .map(|error| error.category().to_string());
+        if let Some(previous) = site_state.last_status {
+            let was_down = previous.is_down();
+            let is_down = result.status.is_down();
+            // Keyed on the category too, a site failing a different way is alerted again
+            let category_changed = dedup_key == AlertDedupKey::UrlAndCategory
+                && site_state.last_error_category != category;
+            if is_down && (!was_down || category_changed) {
+                changes.newly_down.push(url.clone());
+            } else if was_down && !is_down {
+                let required_cycles = site
+                    .resolve_after_successes
+                    .unwrap_or(recovery_confirm_cycles);
+                let up_cycles = site_state.recovery_up_cycles + 1;
+                if up_cycles >= required_cycles {
+                    changes.recovered.push(url.clone());
+                } else {
+                    changes.pending_recovery.push(PendingRecovery {
+                        url: url.clone(),
+                        up_cycles,
+                        required_cycles,
+                    });
+                    site_state.recovery_up_cycles = up_cycles;
+                    status = previous;
+                }
+            }
+        }
+        if !status.is_down() || result.status.is_down() {
+            site_state.recovery_up_cycles = 0;
+        }
+        if let Some(previous) = site_state
+            .last_status
+            .filter(|previous| *previous != status)
+        {
+            changes.transitions.push(StatusTransition {
+                url: url.clone(),
+                from: previous,
+                to: status,
+                error: result.error.as_ref().map(|error| error.to_string()),
+            });
+        }
+
+        if let (Some(previous_ms), Some(current_ms)) =
+            (site_state.last_response_time_ms, result.response_time_ms)
+        {
+            let change = LatencyChange {
+                url: url.clone(),
+                previous_ms,
+                current_ms,
+            };
+            if previous_ms > 0 && change.percent().abs() > latency_change_percent {
+                changes.latency.push(change);
+            }
+        }
+
+        site_state.last_status = Some(status);
+        // A pending recovery is still announced DOWN and keeps its category
+        if result.status.is_down() {
+            site_state.last_error_category = category;
+        } else if !status.is_down() {
+            site_state.last_error_category = None;
+        }
+        site_state.last_response_time_ms = result.response_time_ms;
+    }
+
+    changes
+}
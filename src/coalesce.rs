@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+use crate::changes::CycleChanges;
+use crate::site_state::SiteStates;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Down,
+    Recovered,
+}
+
+// A state change waiting to be sent as part of a consolidated alert
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingEvent {
+    pub at: u64,
+    pub url: String,
+    pub kind: EventKind,
+}
+
+// Buffer this cycle's DOWN and recovery events
+pub fn queue_events(changes: &CycleChanges, site_states: &mut SiteStates, now: u64) {
+    let events = changes
+        .newly_down
+        .iter()
+        .map(|url| (url, EventKind::Down))
+        .chain(
+            changes
+                .recovered
+                .iter()
+                .map(|url| (url, EventKind::Recovered)),
+        );
+    for (url, kind) in events {
+        site_states.pending_events.push(PendingEvent {
+            at: now,
+            url: url.clone(),
+            kind,
+        });
+    }
+}
+
+// One alert covering everything buffered, once the window since the first event has passed
+pub fn due_alert(site_states: &SiteStates, window_seconds: u64, now: u64) -> Option<String> {
+    let first = site_states.pending_events.first()?;
+    if now.saturating_sub(first.at) < window_seconds {
+        return None;
+    }
+
+    let mut alert = String::new();
+    for (kind, heading) in [
+        (EventKind::Down, "Went DOWN"),
+        (EventKind::Recovered, "Recovered"),
+    ] {
+        let urls: Vec<&str> = site_states
+            .pending_events
+            .iter()
+            .filter(|event| event.kind == kind)
+            .map(|event| event.url.as_str())
+            .collect();
+        if urls.is_empty() {
+            continue;
+        }
+        alert.push_str(&format!("{} ({}):\n", heading, urls.len()));
+        for url in urls {
+            alert.push_str(&format!("  {}\n", url));
+        }
+        alert.push('\n');
+    }
+    Some(alert)
+}
@@ -1,44 +1,2125 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
 
 use artisan_middleware::{
     config::AppConfig,
     log,
     logger::LogLevel
 };
+use chrono::{DateTime, Datelike, FixedOffset, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
 use colored::Colorize;
 use config::{Config, ConfigError, File};
-use serde::Deserialize;
+use jsonschema::Validator;
+use regex::Regex;
+use reqwest::header::HeaderName;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Clone)]
+use crate::alerts::Severity;
+use crate::exit_code::ExitCode;
+use crate::health::{parse_ip_range, HealthCheckResult, ERROR_CATEGORIES, REQUEST_TIMEOUT_SECONDS};
+use crate::pause::PauseMode;
+use crate::prefetch;
+use crate::tls::TlsVersion;
+use crate::unix_socket;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppSpecificConfig {
     pub interval_seconds: u64,
+    // Smallest interval accepted before it gets clamped
+    #[serde(default = "default_min_interval_seconds")]
+    pub min_interval_seconds: u64,
+    // Alert when a page grows by more than this many bytes over its baseline
+    #[serde(default)]
+    pub size_alert_bytes: Option<u64>,
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+    // Push metrics to a StatsD agent after each cycle, off when unset
+    #[serde(default)]
+    pub statsd_addr: Option<String>,
+    #[serde(default = "default_statsd_prefix")]
+    pub statsd_prefix: String,
+    // Use DogStatsD tags for the url instead of folding it into the metric name
+    #[serde(default = "default_true")]
+    pub statsd_dogstatsd: bool,
+    // Send the report as several messages ("part X of Y") of at most this many sites
+    #[serde(default)]
+    pub max_sites_per_email: Option<usize>,
+    // Split the report into sections by site priority
+    #[serde(default)]
+    pub report_group_by_priority: bool,
+    // Plain text, or a Markdown table for issue trackers and wikis
+    #[serde(default)]
+    pub report_format: ReportFormat,
+    // List DOWN sites under each distinct error instead of one section per site, so a
+    // shared outage reads as one problem. JSON output keeps every site's detail.
+    #[serde(default)]
+    pub report_group_errors: ErrorGrouping,
+    // Reports longer than this are emailed as a short summary followed by the report
+    // gzip compressed and base64 encoded, keeping large deployments' emails small.
+    // The relay only carries a text body, so it can't be a real attachment.
+    #[serde(default)]
+    pub email_report_max_bytes: Option<usize>,
+    // IANA timezone for timestamps in reports, JSON output stays in UTC
+    #[serde(default = "default_timezone")]
+    pub report_timezone: Tz,
+    // Keep a local rolling history of every report sent
+    #[serde(default)]
+    pub report_archive_path: Option<String>,
+    // Rotate the archive once it reaches this size
+    #[serde(default = "default_report_archive_max_bytes")]
+    pub report_archive_max_bytes: u64,
+    // Number of rotated archives kept alongside the live one
+    #[serde(default = "default_report_archive_keep")]
+    pub report_archive_keep: usize,
+    #[serde(default)]
+    pub doh: Option<DohConfig>,
+    // Response time swing, in percent, worth calling out in the changes section
+    #[serde(default = "default_latency_change_percent")]
+    pub latency_change_percent: f64,
+    // Number of cycles averaged into the rolling health score
+    #[serde(default = "default_health_score_window")]
+    pub health_score_window: usize,
+    // Never hit the same domain more often than this, whatever the url count or interval
+    #[serde(default)]
+    pub min_interval_per_domain_seconds: u64,
+    // Alert when an HTTPS site negotiates an older protocol than this, e.g. "1.2"
+    #[serde(default)]
+    pub min_tls_version: Option<TlsVersion>,
+    // Check that HTTPS sites serve a complete chain and a certificate covering the host
+    #[serde(default)]
+    pub tls_audit: bool,
+    // Report the negotiated TLS version and cipher of HTTPS sites. Like min_tls_version,
+    // tls_audit and the connect/TLS budgets this costs a second handshake per check.
+    #[serde(default)]
+    pub tls_details: bool,
+    // Exit after this many failed sends in a row, or failed saves in a row, so a
+    // supervisor notices
+    #[serde(default)]
+    pub max_consecutive_self_errors: Option<u32>,
+    // While paused via POST /pause: "stop" checking, or "record" results without sending
+    // or running on_change commands
+    #[serde(default)]
+    pub pause_mode: PauseMode,
+    // Cycles a DOWN site has to stay up before it's announced as recovered
+    #[serde(default = "default_recovery_confirm_cycles")]
+    pub recovery_confirm_cycles: u32,
+    // Send DOWN and recovery events as one consolidated alert per window, none when unset.
+    // Cycle reports are held back while events wait for it, not once it has closed.
+    #[serde(default)]
+    pub alert_coalesce_seconds: Option<u64>,
+    // Shell command run when a site changes status, sites can override it. Not expanded
+    // at load, the shell sees ${WEBSITE_MONITOR_URL} and the others when it runs.
+    #[serde(default)]
+    pub on_change_command: Option<String>,
+    // A hung command is killed after this long
+    #[serde(default = "default_on_change_timeout_seconds")]
+    pub on_change_timeout_seconds: u64,
+    // Alert when a site takes more redirects than this to reach its final url. Sites
+    // can override it.
+    #[serde(default)]
+    pub max_redirect_hops: Option<usize>,
+    // Response header remembered per url, with an alert when its value changes between
+    // cycles, e.g. "Server" to notice a rollback or proxy swap. Sites can override it.
+    #[serde(default)]
+    pub watch_header: Option<String>,
+    // Per-site results as JSON lines, off when unset
+    #[serde(default)]
+    pub history_path: Option<String>,
+    // Record a steady state only every Nth cycle, status changes are always recorded
+    #[serde(default = "default_history_sample_every")]
+    pub history_sample_every: u32,
+    // And at most once per this many seconds
+    #[serde(default)]
+    pub history_min_interval_seconds: u64,
+    // Rewrite this file with the current time every heartbeat_interval_seconds,
+    // for `website_monitor --check-heartbeat <path> <max_age>` from cron
+    #[serde(default)]
+    pub heartbeat_path: Option<String>,
+    #[serde(default = "default_heartbeat_interval_seconds")]
+    pub heartbeat_interval_seconds: u64,
+    // Retry a check answered with 429 or 503 once, after the Retry-After it asked for
+    // (capped at this) plus a random 0 - retry_after_jitter_ms. Off when unset.
+    #[serde(default)]
+    pub retry_after_max_seconds: Option<u64>,
+    #[serde(default = "default_retry_after_jitter_ms")]
+    pub retry_after_jitter_ms: u64,
+    // Put the first this many bytes of a DOWN site's body in the report, 0 is off
+    #[serde(default)]
+    pub error_body_snippet_bytes: usize,
+    // Regexes for secrets (tokens, session ids...) masked in those snippets
+    #[serde(default)]
+    pub error_body_redact_patterns: Vec<String>,
+    // Every result of every cycle as rows of a local SQLite database
+    #[serde(default)]
+    pub sqlite_path: Option<String>,
+    // Every result of every cycle as JSON lines into this named pipe, created when
+    // missing. Results are dropped rather than waited on when no one is reading.
+    #[serde(default)]
+    pub fifo_path: Option<String>,
+    // Successful checks per site kept for percentile limits like p95_max_ms
+    #[serde(default = "default_latency_window")]
+    pub latency_window: usize,
+    // Report a site as STALE when it hasn't answered (any HTTP status) for this long
+    #[serde(default)]
+    pub stale_after_seconds: Option<u64>,
+    // Days of checks the uptime of sites with an sla_target is worked out over
+    #[serde(default = "default_sla_window_days")]
+    pub sla_window_days: u64,
+    // Percentage points above the sla_target a breached site has to get back to
+    // before it counts as recovered
+    #[serde(default = "default_sla_hysteresis_percent")]
+    pub sla_hysteresis_percent: f64,
+    // Days of checks the error budget of sites with a latency_slo covers, and the
+    // recent hours its burn rate is measured over
+    #[serde(default = "default_slo_window_days")]
+    pub slo_window_days: u64,
+    #[serde(default = "default_slo_burn_window_hours")]
+    pub slo_burn_window_hours: u64,
+    // Upper bounds, in ms and ascending, of the /metrics response time histogram buckets
+    #[serde(default = "default_metrics_histogram_buckets")]
+    pub metrics_histogram_buckets: Vec<u64>,
+    // Compress and/or encrypt the site state and history files
+    #[serde(default)]
+    pub at_rest: Option<AtRestConfig>,
+    // Probe DNS, TCP and TLS separately when a site is DOWN to say which one failed
+    #[serde(default)]
+    pub diagnose_down: bool,
+    // Resolve every host at the start of the cycle and reuse the addresses for the checks
+    #[serde(default)]
+    pub dns_prefetch: bool,
+    #[serde(default = "default_dns_prefetch_concurrency")]
+    pub dns_prefetch_concurrency: usize,
+    #[serde(default)]
+    pub alert_dedup_key: AlertDedupKey,
+    // Resolve and connect to this url before each cycle and skip the cycle when that
+    // fails, so losing the local network doesn't report every site DOWN
+    #[serde(default)]
+    pub connectivity_probe_url: Option<String>,
+    // Checks in flight at once, a number or "auto" for the available CPU parallelism
+    // times concurrency_multiplier. 1 checks the sites one after another.
+    #[serde(default)]
+    pub max_concurrent_checks: MaxConcurrentChecks,
+    #[serde(default = "default_concurrency_multiplier")]
+    pub concurrency_multiplier: usize,
+    // Source address checks are sent from unless a site sets its own
+    #[serde(default)]
+    pub local_address: Option<IpAddr>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum MaxConcurrentChecks {
+    Fixed(usize),
+    Auto(AutoKeyword),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoKeyword {
+    Auto,
+}
+
+impl Default for MaxConcurrentChecks {
+    fn default() -> Self {
+        MaxConcurrentChecks::Fixed(1)
+    }
+}
+
+impl MaxConcurrentChecks {
+    // Checks are network bound, so "auto" runs several per core
+    pub fn resolve(self, multiplier: usize) -> usize {
+        match self {
+            MaxConcurrentChecks::Fixed(checks) => checks.max(1),
+            MaxConcurrentChecks::Auto(_) => {
+                let parallelism = std::thread::available_parallelism()
+                    .map(|parallelism| parallelism.get())
+                    .unwrap_or(1);
+                (parallelism * multiplier).max(1)
+            }
+        }
+    }
+}
+
+// What makes a DOWN alert new: the url going down, or also it failing a different way
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertDedupKey {
+    #[default]
+    Url,
+    UrlAndCategory,
+}
+
+// The shape of the report handed to notifiers and the archive
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Markdown,
+}
+
+// target_percent of checks must be UP and answer within threshold_ms, the rest is the
+// error budget
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LatencySlo {
+    pub threshold_ms: u64,
+    pub target_percent: f64,
+}
+
+// What DOWN sites are grouped by in the report
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorGrouping {
+    #[default]
+    Off,
+    Category,
+    Message,
+}
+
+// The AppState file is written by artisan_middleware and isn't covered
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AtRestConfig {
+    #[serde(default)]
+    pub compress: bool,
+    // Environment variable holding a 32 byte AES-256-GCM key as hex, no encryption when unset
+    #[serde(default)]
+    pub key_env: Option<String>,
+}
+
+// Resolve hosts through DNS-over-HTTPS before checking them
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DohConfig {
+    // A resolver speaking the DNS JSON API, e.g. https://cloudflare-dns.com/dns-query
+    pub endpoint: String,
+    // Mark the site DOWN instead of falling back to the system resolver
+    #[serde(default)]
+    pub strict: bool,
+}
+
+// A nightly window where only notifications at or above `min_severity` are sent
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    #[serde(default = "default_timezone")]
+    pub timezone: Tz,
+    // Days the window starts on, every day when empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    #[serde(default = "default_quiet_severity")]
+    pub min_severity: Severity,
+}
+
+// A named alternative site list and interval, e.g. "after_hours". Everything else,
+// alerting and notification settings included, stays as configured in [settings.app].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProfileConfig {
+    // Replaces interval_seconds while the profile is active
+    #[serde(default)]
+    pub interval_seconds: Option<u64>,
+    // Replaces [settings.websites] as a whole while active, sites keep their own
+    // per-site overrides and nothing is merged from the base list
+    #[serde(default)]
+    pub websites: Option<WebsiteConfig>,
+    // Active during this window, unless a profile was picked by name
+    #[serde(default)]
+    pub schedule: Option<ProfileSchedule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProfileSchedule {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    #[serde(default = "default_timezone")]
+    pub timezone: Tz,
+    // Days the window starts on, every day when empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+}
+
+impl ProfileSchedule {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        window_is_active(self.start, self.end, self.timezone, &self.days, now)
+    }
+}
+
+// Anything past a week is almost certainly a typo
+const MAX_INTERVAL_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+fn default_min_interval_seconds() -> u64 {
+    1
+}
+
+fn default_statsd_prefix() -> String {
+    String::from("website_monitor")
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_report_archive_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_report_archive_keep() -> usize {
+    5
+}
+
+fn default_s3_region() -> String {
+    String::from("us-east-1")
+}
+
+fn default_s3_spool_dir() -> String {
+    String::from("/tmp/.website_monitor.s3_spool")
+}
+
+fn default_concurrency_multiplier() -> usize {
+    4
+}
+
+fn default_retry_after_jitter_ms() -> u64 {
+    1000
 }
 
-#[derive(Debug, Deserialize, Clone)]
+fn default_heartbeat_interval_seconds() -> u64 {
+    30
+}
+
+fn default_dns_prefetch_concurrency() -> usize {
+    8
+}
+
+fn default_latency_window() -> usize {
+    20
+}
+
+fn default_sla_window_days() -> u64 {
+    30
+}
+
+fn default_sla_hysteresis_percent() -> f64 {
+    0.05
+}
+
+fn default_slo_window_days() -> u64 {
+    30
+}
+
+fn default_slo_burn_window_hours() -> u64 {
+    1
+}
+
+// Roughly exponential, from CDN edge hits up to sites close to the request timeout
+fn default_metrics_histogram_buckets() -> Vec<u64> {
+    vec![5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000, 30000]
+}
+
+fn default_history_sample_every() -> u32 {
+    1
+}
+
+fn default_on_change_timeout_seconds() -> u64 {
+    30
+}
+
+fn default_validator_timeout_seconds() -> u64 {
+    10
+}
+
+fn default_fast_path_timeout_ms() -> u64 {
+    1_000
+}
+
+fn default_recovery_confirm_cycles() -> u32 {
+    1
+}
+
+fn default_health_score_window() -> usize {
+    720
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_latency_change_percent() -> f64 {
+    50.0
+}
+
+fn default_canary_window_seconds() -> u64 {
+    3600
+}
+
+fn default_method() -> String {
+    String::from("GET")
+}
+
+fn default_timezone() -> Tz {
+    Tz::UTC
+}
+
+fn default_quiet_severity() -> Severity {
+    Severity::Critical
+}
+
+// Whether `now` falls in a daily start-end window, on one of `days` (all when empty)
+fn window_is_active(
+    start: NaiveTime,
+    end: NaiveTime,
+    timezone: Tz,
+    days: &[Weekday],
+    now: DateTime<Utc>,
+) -> bool {
+    let local = now.with_timezone(&timezone);
+    let time = local.time();
+
+    // A window like 22:00-07:00 belongs to the day it started on
+    let started_on = if start <= end {
+        if time < start || time >= end {
+            return false;
+        }
+        local.weekday()
+    } else if time >= start {
+        local.weekday()
+    } else if time < end {
+        local.weekday().pred()
+    } else {
+        return false;
+    };
+
+    days.is_empty() || days.contains(&started_on)
+}
+
+impl QuietHours {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        window_is_active(self.start, self.end, self.timezone, &self.days, now)
+    }
+
+    // Critical alerts always go through, whatever the configured minimum
+    pub fn allows(&self, severity: Severity) -> bool {
+        severity >= self.min_severity || severity == Severity::Critical
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct WebsiteConfig {
+    #[serde(default)]
     pub urls: Vec<String>,
+    // Sites that need more than a bare url
+    #[serde(default)]
+    pub sites: Vec<SiteConfig>,
+    // More urls kept outside the config, one per line or a JSON array. Re-read every cycle.
+    #[serde(default)]
+    pub urls_file: Option<String>,
+    // Same as urls_file but fetched over http
+    #[serde(default)]
+    pub urls_url: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SiteConfig {
+    pub url: String,
+    // Higher priorities are checked first
+    #[serde(default)]
+    pub priority: i32,
+    // Share of the weighted health score this site carries
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    // Query parameters added to the url on every request
+    #[serde(default)]
+    pub query: BTreeMap<String, String>,
+    // Values for `{name}` placeholders in the url, percent-encoded into the path
+    #[serde(default)]
+    pub path_params: BTreeMap<String, String>,
+    // Query and path parameters whose value came from the environment, masked wherever
+    // the url is shown
+    #[serde(skip)]
+    pub templated_params: BTreeSet<String>,
+    #[serde(default)]
+    pub canary: Option<CanaryConfig>,
+    // Request the url twice and require a value to differ between the two responses
+    #[serde(default)]
+    pub nonce: Option<NonceConfig>,
+    // Fetched first with a cookie jar shared with the check, for sites that only answer
+    // once the landing page has set a session cookie
+    #[serde(default)]
+    pub prefetch_url: Option<String>,
+    // Record the DNSSEC status of the host through the DoH resolver, "alert" also
+    // alerts when a secure host turns insecure or bogus
+    #[serde(default)]
+    pub dnssec: Option<DnssecMode>,
+    // POST this GraphQL query instead of sending `method`, DOWN when the response has
+    // errors or lacks the expected data
+    #[serde(default)]
+    pub graphql: Option<GraphqlConfig>,
+    // JSON Schema the body must conform to, inline JSON or a path to a schema file
+    #[serde(default)]
+    pub json_schema: Option<String>,
+    // json_schema compiled when the settings are loaded
+    #[serde(skip)]
+    pub compiled_schema: Option<Arc<Validator>>,
+    // Responses slower than this are reported DEGRADED
+    #[serde(default)]
+    pub degraded_response_time_ms: Option<u64>,
+    // Responses slower than this are reported DOWN, should be above the degraded limit
+    #[serde(default)]
+    pub max_response_time_ms: Option<u64>,
+    // Responses faster than this are reported DEGRADED: a 1 ms answer from a site that
+    // normally does real work is more likely a cached error page than a healthy one
+    #[serde(default)]
+    pub min_expected_response_ms: Option<u64>,
+    // Same limits against the p95 of the recent latency window instead of a single check
+    #[serde(default)]
+    pub p95_degraded_ms: Option<u64>,
+    #[serde(default)]
+    pub p95_max_ms: Option<u64>,
+    // Per-phase budgets, DOWN when any phase takes longer even if the total is fine.
+    // Connect and TLS are timed on a separate connection made for the TLS details.
+    #[serde(default)]
+    pub connect_budget_ms: Option<u64>,
+    #[serde(default)]
+    pub tls_budget_ms: Option<u64>,
+    // Time to the response headers
+    #[serde(default)]
+    pub ttfb_budget_ms: Option<u64>,
+    // Headers plus body
+    #[serde(default)]
+    pub total_budget_ms: Option<u64>,
+    // Send this Range header (e.g. "bytes=0-1023") and require a matching 206
+    #[serde(default)]
+    pub range: Option<String>,
+    // Send one untimed request first so the measured one hits warm caches. This doubles
+    // the requests made to the site, and the timed request usually reuses the warmup's
+    // connection, so its DNS and connect time no longer show up in the response time.
+    #[serde(default)]
+    pub warmup: bool,
+    // Method of the timed request
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default = "default_method")]
+    pub warmup_method: String,
+    // Accepted status codes per method, e.g. { GET = [200], HEAD = [200, 204] }.
+    // A method without an entry accepts any status.
+    #[serde(default)]
+    pub expected_status: BTreeMap<String, Vec<u16>>,
+    // Stop after the headers, for large pages where only the status matters.
+    // Canaries need the body and won't find a value with this off.
+    #[serde(default = "default_true")]
+    pub read_body: bool,
+    // Also request the url over HTTP/3 (QUIC): "try" records whether h3 worked,
+    // "require" makes the site DOWN when it doesn't
+    #[serde(default)]
+    pub http3: Http3Mode,
+    // Character encoding of the body (e.g. "iso-8859-1"), overriding the Content-Type
+    // charset. Bodies are decoded before canary values are read from them.
+    #[serde(default)]
+    pub encoding: Option<String>,
+    // false makes a Content-Length: 0 or an empty body DOWN, except for HEAD
+    #[serde(default = "default_true")]
+    pub allow_empty_body: bool,
+    // Bounds on the size of the body read, outside them the site is DOWN
+    #[serde(default)]
+    pub min_body_bytes: Option<u64>,
+    #[serde(default)]
+    pub max_body_bytes: Option<u64>,
+    // Soft errors served with a 200: the site is DOWN when the body contains any of
+    // these strings or matches any of these regexes
+    #[serde(default)]
+    pub forbidden_body: Vec<String>,
+    #[serde(default)]
+    pub forbidden_body_regex: Vec<String>,
+    // The valid forbidden_body_regex patterns, compiled when the settings are loaded
+    #[serde(skip)]
+    pub compiled_forbidden_regex: Vec<Regex>,
+    // Pipe the body to this command (via sh -c, with the url and status code as $1 and
+    // $2 and in the environment), DOWN with its stderr as the reason unless it exits 0.
+    // Left to the shell to expand, so it can use ${WEBSITE_MONITOR_URL} and the like.
+    #[serde(default)]
+    pub validator_command: Option<String>,
+    // A validator still running after this long is killed and the check is DOWN
+    #[serde(default = "default_validator_timeout_seconds")]
+    pub validator_timeout_seconds: u64,
+    // After following redirects the response must come from an https:// url, e.g. to
+    // verify that a plain http entry point upgrades
+    #[serde(default)]
+    pub require_https_final: bool,
+    // Cache-Control directives (e.g. "public, max-age=86400") that must all be in the
+    // response's Cache-Control, DEGRADED otherwise
+    #[serde(default)]
+    pub expected_cache_control: Option<String>,
+    // DEGRADED unless the response has an ETag or a Last-Modified to revalidate with
+    #[serde(default)]
+    pub require_cache_validator: bool,
+    // Dual-stack audit: request the url once over IPv4 and once over IPv6 (when the
+    // host has AAAA records), alerting when the advertised IPv6 path doesn't work
+    #[serde(default)]
+    pub check_ipv6: bool,
+    // CIDRs (or single addresses) the host must resolve into, DEGRADED and logged as a
+    // possible DNS hijack otherwise. The resolved address is recorded in the result.
+    #[serde(default)]
+    pub allowed_ip_ranges: Vec<String>,
+    // Negative check: UP while the url is unreachable, DOWN once it answers
+    #[serde(default)]
+    pub expect_down: bool,
+    // Overrides the global on_change_command for this site
+    #[serde(default)]
+    pub on_change_command: Option<String>,
+    // Context for the on-call, put at the top of the site's section in the report when
+    // it isn't UP and sent to webhooks as {{alerts}}
+    #[serde(default)]
+    pub runbook_url: Option<String>,
+    #[serde(default)]
+    pub alert_message: Option<String>,
+    // Recipient groups (names under [settings.recipients]) that get this site's results
+    // in a report of their own. "override" keeps the site out of the report sent through
+    // the global channels, "augment" leaves it in there as well.
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    #[serde(default)]
+    pub recipients_mode: RecipientsMode,
+    // Overrides the global watch_header for this site
+    #[serde(default)]
+    pub watch_header: Option<String>,
+    // Overrides the global max_redirect_hops for this site
+    #[serde(default)]
+    pub max_redirect_hops: Option<usize>,
+    // Consecutive UP cycles before a DOWN site is announced as recovered, overriding
+    // the global recovery_confirm_cycles, e.g. to keep a flapping site's incident open
+    #[serde(default)]
+    pub resolve_after_successes: Option<u32>,
+    // Error categories (e.g. "connect") that are only logged: the result keeps its
+    // real status but doesn't change the announced status or page anyone
+    #[serde(default)]
+    pub non_alerting_categories: Vec<String>,
+    // Uptime percentage over sla_window_days, alerting when it drops below and when
+    // it recovers
+    #[serde(default)]
+    pub sla_target: Option<f64>,
+    // Response time objective with an error budget over slo_window_days
+    #[serde(default)]
+    pub latency_slo: Option<LatencySlo>,
+    // Send this Host header instead of the url's host
+    #[serde(default)]
+    pub host_header: Option<String>,
+    // Present this name in the TLS handshake (and as the Host) while still connecting to
+    // the url's host, e.g. an IP before a DNS cutover
+    #[serde(default)]
+    pub sni: Option<String>,
+    // Resolve the host on its own (through DoH when configured) and give up after
+    // this long, so broken DNS fails fast instead of eating the request timeout
+    #[serde(default)]
+    pub dns_timeout_ms: Option<u64>,
+    // Loopback fast path: a fast_path_timeout_ms request timeout instead of the usual one,
+    // no Retry-After retry, DNSSEC lookup or DOWN diagnosis, and no DNS resolution, localhost
+    // names go straight to 127.0.0.1 / ::1. "auto" turns it on for localhost, *.localhost and
+    // loopback addresses.
+    #[serde(default)]
+    pub fast_path: FastPathMode,
+    #[serde(default = "default_fast_path_timeout_ms")]
+    pub fast_path_timeout_ms: u64,
+    // Client certificate and private key presented for mutual TLS, each a PEM file path
+    // or the PEM itself (e.g. "${CLIENT_KEY_PEM}"). PKCS#1 and PKCS#8 keys both work.
+    #[serde(default)]
+    pub client_cert: Option<String>,
+    #[serde(default)]
+    pub client_key: Option<String>,
+    // Sign every request with an HMAC header, for gateways that reject unsigned requests
+    #[serde(default)]
+    pub signing: Option<SigningConfig>,
+    // Send the check from this source address, e.g. one interface of a multi-homed host
+    #[serde(default)]
+    pub local_address: Option<IpAddr>,
+    // Check once from each of these source addresses instead, the site is as healthy
+    // as its worst path and every source is reported
+    #[serde(default)]
+    pub local_addresses: Vec<IpAddr>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RecipientsMode {
+    #[default]
+    Override,
+    Augment,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FastPathMode {
+    #[default]
+    Auto,
+    On,
+    Off,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Http3Mode {
+    #[default]
+    Off,
+    Try,
+    Require,
+}
+
+// Watches a value across cycles for deploy verification
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CanaryConfig {
+    // Dotted path into a JSON body (e.g. "build.version"), whole body when unset
+    #[serde(default)]
+    pub json_path: Option<String>,
+    pub alert_on: CanaryAlert,
+    // How long the value may stay the same when alerting on `unchanged`
+    #[serde(default = "default_canary_window_seconds")]
+    pub window_seconds: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GraphqlConfig {
+    pub query: String,
+    #[serde(default)]
+    pub variables: Option<serde_json::Value>,
+    // Dotted path under `data` (e.g. "health.status") that must be present and not null
+    #[serde(default)]
+    pub data_path: Option<String>,
+    // The value data_path must have, compared as text
+    #[serde(default)]
+    pub expected: Option<String>,
+}
+
+// HMAC request signing. The signed parts, in the order listed, are joined with "\n"
+// into the string to sign and its MAC is sent as `prefix` + hex or base64.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SigningConfig {
+    #[serde(default)]
+    pub algorithm: SigningAlgorithm,
+    // Never shown in reports or logs, masked in /config
+    pub secret: String,
+    #[serde(default = "default_signature_header")]
+    pub header: String,
+    #[serde(default = "default_signed_parts")]
+    pub sign: Vec<SignedPart>,
+    // Unix seconds the signature was made at, sent when the timestamp is signed
+    #[serde(default = "default_timestamp_header")]
+    pub timestamp_header: String,
+    #[serde(default)]
+    pub encoding: SignatureEncoding,
+    // Put before the encoded MAC, e.g. "sha256="
+    #[serde(default)]
+    pub prefix: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SigningAlgorithm {
+    #[default]
+    HmacSha256,
+    HmacSha512,
+}
+
+impl fmt::Display for SigningAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SigningAlgorithm::HmacSha256 => write!(f, "hmac-sha256"),
+            SigningAlgorithm::HmacSha512 => write!(f, "hmac-sha512"),
+        }
+    }
+}
+
+// A part of the request covered by the signature
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignedPart {
+    // GET, POST...
+    Method,
+    // The Host the request is sent with, host:port for non-default ports
+    Host,
+    // The url path, without the query
+    Path,
+    // The query string as sent, `query` parameters included, empty when there is none
+    Query,
+    // The unix seconds also sent in timestamp_header
+    Timestamp,
+    // The request body, empty when there is none
+    Body,
+}
+
+impl fmt::Display for SignedPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SignedPart::Method => "method",
+            SignedPart::Host => "host",
+            SignedPart::Path => "path",
+            SignedPart::Query => "query",
+            SignedPart::Timestamp => "timestamp",
+            SignedPart::Body => "body",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+fn default_signature_header() -> String {
+    String::from("X-Signature")
+}
+
+fn default_timestamp_header() -> String {
+    String::from("X-Timestamp")
+}
+
+fn default_signed_parts() -> Vec<SignedPart> {
+    vec![
+        SignedPart::Method,
+        SignedPart::Path,
+        SignedPart::Timestamp,
+        SignedPart::Body,
+    ]
+}
+
+// A per-request value, e.g. a request id, that stays the same when something caches
+// responses it shouldn't
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NonceConfig {
+    // Response header to compare, takes precedence over json_path
+    #[serde(default)]
+    pub header: Option<String>,
+    // Dotted path into a JSON body, the whole body when neither is set
+    #[serde(default)]
+    pub json_path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DnssecMode {
+    Record,
+    Alert,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CanaryAlert {
+    // Expect a deploy, alert if nothing changed within the window
+    Unchanged,
+    // Expect stability, alert on any change
+    Changed,
+}
+
+impl SiteConfig {
+    pub fn from_url(url: &str) -> Self {
+        SiteConfig {
+            url: url.to_string(),
+            priority: 0,
+            weight: default_weight(),
+            query: BTreeMap::new(),
+            path_params: BTreeMap::new(),
+            templated_params: BTreeSet::new(),
+            canary: None,
+            nonce: None,
+            prefetch_url: None,
+            dnssec: None,
+            graphql: None,
+            json_schema: None,
+            compiled_schema: None,
+            degraded_response_time_ms: None,
+            min_expected_response_ms: None,
+            max_response_time_ms: None,
+            p95_degraded_ms: None,
+            p95_max_ms: None,
+            connect_budget_ms: None,
+            tls_budget_ms: None,
+            ttfb_budget_ms: None,
+            total_budget_ms: None,
+            range: None,
+            warmup: false,
+            method: default_method(),
+            warmup_method: default_method(),
+            expected_status: BTreeMap::new(),
+            read_body: true,
+            http3: Http3Mode::Off,
+            encoding: None,
+            allow_empty_body: true,
+            min_body_bytes: None,
+            max_body_bytes: None,
+            forbidden_body: Vec::new(),
+            forbidden_body_regex: Vec::new(),
+            compiled_forbidden_regex: Vec::new(),
+            validator_command: None,
+            validator_timeout_seconds: default_validator_timeout_seconds(),
+            require_https_final: false,
+            expected_cache_control: None,
+            require_cache_validator: false,
+            check_ipv6: false,
+            allowed_ip_ranges: Vec::new(),
+            expect_down: false,
+            on_change_command: None,
+            runbook_url: None,
+            alert_message: None,
+            recipients: Vec::new(),
+            recipients_mode: RecipientsMode::Override,
+            watch_header: None,
+            max_redirect_hops: None,
+            resolve_after_successes: None,
+            non_alerting_categories: Vec::new(),
+            sla_target: None,
+            latency_slo: None,
+            host_header: None,
+            sni: None,
+            dns_timeout_ms: None,
+            fast_path: FastPathMode::Auto,
+            fast_path_timeout_ms: default_fast_path_timeout_ms(),
+            client_cert: None,
+            client_key: None,
+            signing: None,
+            local_address: None,
+            local_addresses: Vec::new(),
+        }
+    }
+
+    // The url as shown, path and query parameters filled in, those taken from the
+    // environment masked. Results are keyed by this.
+    pub fn resolved_url(&self) -> String {
+        self.build_url(true)
+    }
+
+    // The url actually requested, every parameter with its real value
+    pub fn request_url(&self) -> String {
+        self.build_url(false)
+    }
+
+    fn build_url(&self, mask: bool) -> String {
+        let value = |name: &String, value: &String| {
+            if mask && self.templated_params.contains(name) {
+                REDACTED.to_string()
+            } else {
+                value.clone()
+            }
+        };
+        let mut url = self.url.clone();
+        for (name, path_value) in &self.path_params {
+            let placeholder = format!("{{{}}}", name);
+            url = url.replace(&placeholder, &encode_path_value(&value(name, path_value)));
+        }
+        if self.query.is_empty() {
+            return url;
+        }
+        let query = self
+            .query
+            .iter()
+            .map(|(name, query_value)| (name, value(name, query_value)));
+        match Url::parse_with_params(&url, query) {
+            Ok(url) => url.to_string(),
+            Err(_) => url,
+        }
+    }
+
+    // `{name}` placeholders in the url that no path_params entry fills
+    fn unfilled_placeholders(&self) -> Vec<&str> {
+        let mut placeholders = Vec::new();
+        let mut rest = self.url.as_str();
+        while let Some(start) = rest.find('{') {
+            let after = &rest[start + 1..];
+            match after.find('}') {
+                Some(end) => {
+                    if !self.path_params.contains_key(&after[..end]) {
+                        placeholders.push(&after[..end]);
+                    }
+                    rest = &after[end + 1..];
+                }
+                None => break,
+            }
+        }
+        placeholders
+    }
+
+    // Sites with equal keys make the same request and judge it the same way, so one
+    // check can stand for all of them. Only fields that don't affect the result
+    // (ordering, score weight, change hook, alert filter) are left out.
+    pub fn check_key(&self) -> String {
+        let mut site = self.clone();
+        site.url = self.request_url();
+        site.query.clear();
+        site.path_params.clear();
+        site.priority = 0;
+        site.weight = 0.0;
+        site.on_change_command = None;
+        site.non_alerting_categories.clear();
+        site.recipients.clear();
+        site.recipients_mode = RecipientsMode::Override;
+        serde_json::to_string(&site).unwrap_or(site.url)
+    }
+
+    // Whether the loopback fast path applies, set explicitly or detected from the url
+    pub fn fast_path(&self) -> bool {
+        match self.fast_path {
+            FastPathMode::Auto => prefetch::is_loopback_url(&self.url),
+            FastPathMode::On => true,
+            FastPathMode::Off => false,
+        }
+    }
+
+    // Settings that need a network connection, none of which a unix:// check uses
+    fn unix_ignored_settings(&self) -> Vec<&'static str> {
+        [
+            ("prefetch_url", self.prefetch_url.is_some()),
+            ("warmup", self.warmup),
+            ("nonce", self.nonce.is_some()),
+            ("graphql", self.graphql.is_some()),
+            ("range", self.range.is_some()),
+            ("http3", self.http3 != Http3Mode::Off),
+            ("require_https_final", self.require_https_final),
+            (
+                "expected_cache_control",
+                self.expected_cache_control.is_some(),
+            ),
+            ("require_cache_validator", self.require_cache_validator),
+            ("check_ipv6", self.check_ipv6),
+            ("allowed_ip_ranges", !self.allowed_ip_ranges.is_empty()),
+            ("dnssec", self.dnssec.is_some()),
+            ("sni", self.sni.is_some()),
+            ("dns_timeout_ms", self.dns_timeout_ms.is_some()),
+            ("client_cert", self.client_cert.is_some()),
+            ("client_key", self.client_key.is_some()),
+            ("signing", self.signing.is_some()),
+            ("local_address", self.local_address.is_some()),
+            ("local_addresses", !self.local_addresses.is_empty()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| name)
+        .collect()
+    }
+
+    // Whether the report sent through the global channels includes this site
+    pub fn in_global_report(&self) -> bool {
+        self.recipients.is_empty() || self.recipients_mode == RecipientsMode::Augment
+    }
+
+    // How long one request to the site may take
+    pub fn request_timeout(&self) -> Duration {
+        if self.fast_path() {
+            Duration::from_millis(self.fast_path_timeout_ms)
+        } else {
+            Duration::from_secs(REQUEST_TIMEOUT_SECONDS)
+        }
+    }
+
+    // A failed result whose category this site doesn't alert on
+    pub fn is_non_alerting(&self, result: &HealthCheckResult) -> bool {
+        result.error.as_ref().is_some_and(|error| {
+            self.non_alerting_categories
+                .iter()
+                .any(|category| category == error.category())
+        })
+    }
+}
+
+impl WebsiteConfig {
+    fn expand_env(&mut self) -> Result<(), ConfigError> {
+        for url in self.urls.iter_mut() {
+            expand_env_in_place(url)?;
+        }
+        if let Some(urls_file) = self.urls_file.as_mut() {
+            expand_env_in_place(urls_file)?;
+        }
+        if let Some(urls_url) = self.urls_url.as_mut() {
+            expand_env_in_place(urls_url)?;
+        }
+        for site in self.sites.iter_mut() {
+            expand_env_in_place(&mut site.url)?;
+            for (name, value) in site.query.iter_mut().chain(site.path_params.iter_mut()) {
+                if value.contains("${") {
+                    site.templated_params.insert(name.clone());
+                }
+                expand_env_in_place(value)?;
+            }
+            if let Some(watch_header) = site.watch_header.as_mut() {
+                expand_env_in_place(watch_header)?;
+            }
+            if let Some(runbook_url) = site.runbook_url.as_mut() {
+                expand_env_in_place(runbook_url)?;
+            }
+            if let Some(alert_message) = site.alert_message.as_mut() {
+                expand_env_in_place(alert_message)?;
+            }
+            if let Some(prefetch_url) = site.prefetch_url.as_mut() {
+                expand_env_in_place(prefetch_url)?;
+            }
+            if let Some(graphql) = site.graphql.as_mut() {
+                expand_env_in_place(&mut graphql.query)?;
+                if let Some(expected) = graphql.expected.as_mut() {
+                    expand_env_in_place(expected)?;
+                }
+            }
+            if let Some(header) = site.nonce.as_mut().and_then(|nonce| nonce.header.as_mut()) {
+                expand_env_in_place(header)?;
+            }
+            if let Some(client_cert) = site.client_cert.as_mut() {
+                expand_env_in_place(client_cert)?;
+            }
+            if let Some(client_key) = site.client_key.as_mut() {
+                expand_env_in_place(client_key)?;
+            }
+            if let Some(signing) = site.signing.as_mut() {
+                expand_env_in_place(&mut signing.secret)?;
+            }
+            if let Some(host_header) = site.host_header.as_mut() {
+                expand_env_in_place(host_header)?;
+            }
+            if let Some(sni) = site.sni.as_mut() {
+                expand_env_in_place(sni)?;
+            }
+            for token in site
+                .forbidden_body
+                .iter_mut()
+                .chain(site.forbidden_body_regex.iter_mut())
+            {
+                expand_env_in_place(token)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) {
+        for site in &self.sites {
+            for placeholder in site.unfilled_placeholders() {
+                log!(
+                    LogLevel::Warn,
+                    "{}: {{{}}} has no path_params value and is requested as is",
+                    site.url,
+                    placeholder
+                );
+            }
+            if site.url.starts_with(unix_socket::UNIX_SCHEME) {
+                let ignored: Vec<&str> = site.unix_ignored_settings();
+                if !ignored.is_empty() {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: {} can't apply to a unix socket check and will be ignored",
+                        site.url,
+                        ignored.join(", ")
+                    );
+                }
+            }
+            if site.dns_timeout_ms.is_some() && prefetch::host_to_resolve(&site.url).is_none() {
+                log!(
+                    LogLevel::Warn,
+                    "{}: dns_timeout_ms has no effect, the url has no host name to resolve",
+                    site.url
+                );
+            }
+            if site.fast_path() {
+                if site.dns_timeout_ms.is_some() {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: dns_timeout_ms has no effect on the fast path, set fast_path = \"off\" to resolve the host on its own",
+                        site.url
+                    );
+                }
+                if site.fast_path_timeout_ms == 0 {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: fast_path_timeout_ms is 0, every check will time out",
+                        site.url
+                    );
+                }
+            }
+            for range in &site.allowed_ip_ranges {
+                if parse_ip_range(range).is_none() {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: allowed_ip_ranges entry {:?} is not a CIDR or an address, it is ignored",
+                        site.url,
+                        range
+                    );
+                }
+            }
+            for category in &site.non_alerting_categories {
+                if !ERROR_CATEGORIES.contains(&category.as_str()) {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: non_alerting_categories has unknown category {:?}, known are {}",
+                        site.url,
+                        category,
+                        ERROR_CATEGORIES.join(", ")
+                    );
+                }
+            }
+            if let Some(slo) = &site.latency_slo {
+                if !(slo.target_percent > 0.0 && slo.target_percent < 100.0) {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: latency_slo target_percent ({}) should be above 0 and below 100, no budget is tracked otherwise",
+                        site.url,
+                        slo.target_percent
+                    );
+                }
+            }
+            if let Some(sla_target) = site.sla_target {
+                if !(sla_target > 0.0 && sla_target <= 100.0) {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: sla_target ({}) is a percentage and should be above 0 and at most 100",
+                        site.url,
+                        sla_target
+                    );
+                }
+            }
+            if let (Some(degraded), Some(max)) =
+                (site.degraded_response_time_ms, site.max_response_time_ms)
+            {
+                if degraded >= max {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: degraded_response_time_ms ({}) should be below max_response_time_ms ({}), it will never apply",
+                        site.url,
+                        degraded,
+                        max
+                    );
+                }
+            }
+            if let (Some(floor), Some(degraded)) = (
+                site.min_expected_response_ms,
+                site.degraded_response_time_ms,
+            ) {
+                if floor >= degraded {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: min_expected_response_ms ({}) should be below degraded_response_time_ms ({}), every response will be DEGRADED",
+                        site.url,
+                        floor,
+                        degraded
+                    );
+                }
+            }
+            if let Some(nonce) = &site.nonce {
+                if nonce.header.is_some() && nonce.json_path.is_some() {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: nonce has both header and json_path, only the header is compared",
+                        site.url
+                    );
+                } else if nonce.header.is_none() && !site.read_body {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: a nonce read from the body needs read_body, checks will fail",
+                        site.url
+                    );
+                }
+            }
+            if let Some(prefetch_url) = &site.prefetch_url {
+                if let Err(e) = Url::parse(prefetch_url) {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: prefetch_url {} is not a valid url ({}), the prefetch will fail",
+                        site.url,
+                        redact_url(prefetch_url),
+                        e
+                    );
+                }
+            }
+            if let Some(graphql) = &site.graphql {
+                if !site.read_body {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: graphql needs read_body, checks will fail",
+                        site.url
+                    );
+                }
+                if graphql.expected.is_some() && graphql.data_path.is_none() {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: graphql expected has no data_path to compare with, it will never apply",
+                        site.url
+                    );
+                }
+            }
+            if let Some(signing) = &site.signing {
+                if signing.secret.is_empty() {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: signing has an empty secret, the gateway will reject the signatures",
+                        site.url
+                    );
+                }
+                if signing.sign.is_empty() {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: signing signs nothing, every request gets the same signature",
+                        site.url
+                    );
+                }
+                for header in [&signing.header, &signing.timestamp_header] {
+                    if HeaderName::from_bytes(header.as_bytes()).is_err() {
+                        log!(
+                            LogLevel::Warn,
+                            "{}: {} is not a valid header name, checks will fail",
+                            site.url,
+                            header
+                        );
+                    }
+                }
+            }
+            if site.client_cert.is_some() != site.client_key.is_some() {
+                log!(
+                    LogLevel::Warn,
+                    "{}: client_cert and client_key must be set together, checks will fail",
+                    site.url
+                );
+            }
+            if let (Some(ttfb), Some(total)) = (site.ttfb_budget_ms, site.total_budget_ms) {
+                if ttfb > total {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: ttfb_budget_ms ({}) is above total_budget_ms ({}), it can never be the failing phase",
+                        site.url,
+                        ttfb,
+                        total
+                    );
+                }
+            }
+            if site.tls_budget_ms.is_some() && !site.url.starts_with("https://") {
+                log!(
+                    LogLevel::Warn,
+                    "{}: tls_budget_ms only applies to https urls",
+                    site.url
+                );
+            }
+            if let (Some(min), Some(max)) = (site.min_body_bytes, site.max_body_bytes) {
+                if min > max {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: min_body_bytes ({}) is above max_body_bytes ({}), every check will fail",
+                        site.url,
+                        min,
+                        max
+                    );
+                }
+            }
+            if site.http3 != Http3Mode::Off && !site.url.starts_with("https://") {
+                log!(
+                    LogLevel::Warn,
+                    "{}: http3 needs an https url, the h3 request will fail",
+                    site.url
+                );
+            }
+            if let Some(encoding) = &site.encoding {
+                if encoding_rs::Encoding::for_label(encoding.as_bytes()).is_none() {
+                    log!(
+                        LogLevel::Warn,
+                        "{}: unknown encoding {}, using the response charset",
+                        site.url,
+                        encoding
+                    );
+                }
+            }
+            if !site.read_body && (site.min_body_bytes.is_some() || site.max_body_bytes.is_some()) {
+                log!(
+                    LogLevel::Warn,
+                    "{}: body size limits need read_body, they will never apply",
+                    site.url
+                );
+            }
+            if !site.read_body
+                && (!site.forbidden_body.is_empty() || !site.forbidden_body_regex.is_empty())
+            {
+                log!(
+                    LogLevel::Warn,
+                    "{}: forbidden_body needs read_body, it will never apply",
+                    site.url
+                );
+            }
+            if !site.read_body && site.validator_command.is_some() {
+                log!(
+                    LogLevel::Warn,
+                    "{}: validator_command needs read_body, it will never run",
+                    site.url
+                );
+            }
+        }
+    }
+
+    // Compile every site's json_schema and forbidden_body_regex up front. A broken
+    // schema stops the monitor from starting rather than failing the site every cycle,
+    // an invalid pattern is warned about and ignored.
+    pub fn compile(&mut self) -> Result<(), ConfigError> {
+        for site in self.sites.iter_mut() {
+            site.compiled_forbidden_regex = site
+                .forbidden_body_regex
+                .iter()
+                .filter_map(|pattern| match Regex::new(pattern) {
+                    Ok(regex) => Some(regex),
+                    Err(e) => {
+                        log!(
+                            LogLevel::Warn,
+                            "{}: forbidden_body_regex {} is not a valid regex, ignoring it: {}",
+                            site.url,
+                            pattern,
+                            e
+                        );
+                        None
+                    }
+                })
+                .collect();
+            let source = match &site.json_schema {
+                Some(source) => source,
+                None => continue,
+            };
+            let text = if source.trim_start().starts_with('{') {
+                source.clone()
+            } else {
+                fs::read_to_string(source).map_err(|e| {
+                    ConfigError::Message(format!(
+                        "{}: cannot read json_schema {}: {}",
+                        site.url, source, e
+                    ))
+                })?
+            };
+            let schema: serde_json::Value = serde_json::from_str(&text).map_err(|e| {
+                ConfigError::Message(format!("{}: json_schema is not JSON: {}", site.url, e))
+            })?;
+            let validator = jsonschema::validator_for(&schema).map_err(|e| {
+                ConfigError::Message(format!("{}: invalid json_schema: {}", site.url, e))
+            })?;
+            site.compiled_schema = Some(Arc::new(validator));
+        }
+        Ok(())
+    }
+
+    // Every configured site, highest priority first. Ties keep config order.
+    pub fn all_sites(&self) -> Vec<SiteConfig> {
+        self.all_sites_with(&[])
+    }
+
+    // All sites plus urls from an external inventory, skipping any already configured
+    pub fn all_sites_with(&self, external_urls: &[String]) -> Vec<SiteConfig> {
+        let external = external_urls.iter().filter(|url| {
+            !self.urls.contains(url) && !self.sites.iter().any(|site| &site.url == *url)
+        });
+        let mut sites: Vec<SiteConfig> = self
+            .urls
+            .iter()
+            .chain(external)
+            .map(|url| SiteConfig::from_url(url))
+            .chain(self.sites.iter().cloned())
+            .collect();
+        sites.sort_by_key(|site| std::cmp::Reverse(site.priority));
+        sites
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Settings {
     pub app: AppSpecificConfig,
     pub websites: WebsiteConfig,
+    #[serde(default)]
+    pub server: Option<ServerConfig>,
+    #[serde(default)]
+    pub s3: Option<S3Config>,
+    #[serde(default)]
+    pub webhook: Option<WebhookConfig>,
+    // Named webhooks for teams, sites route their results to them with `recipients`
+    #[serde(default)]
+    pub recipients: BTreeMap<String, WebhookConfig>,
+    #[serde(default)]
+    pub email_template: Option<EmailTemplateConfig>,
+    #[serde(default)]
+    pub status_page: Option<StatusPageConfig>,
+    #[serde(default)]
+    pub leader: Option<LeaderConfig>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+    #[serde(default)]
+    pub one_shots: Vec<OneShotConfig>,
+}
+
+// Check some urls once at a set time, e.g. right after a planned migration, and report
+// as usual. Fired checks are remembered in the site state, so a restart neither loses
+// a pending one nor runs a fired one again.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OneShotConfig {
+    // RFC 3339 with an offset, e.g. "2026-10-16T03:00:00-04:00"
+    pub at: DateTime<FixedOffset>,
+    // Checked with the settings of the configured site of the same url, if there is one
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl OneShotConfig {
+    // Identifies the check across restarts, editing the entry makes it a new one
+    pub fn id(&self) -> String {
+        format!("{} {}", self.at.to_rfc3339(), self.urls.join(","))
+    }
+
+    pub fn label(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.at.to_rfc3339())
+    }
+}
+
+// Render report emails from a Handlebars template file instead of the built-in format
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmailTemplateConfig {
+    pub path: String,
+    // Static key/values available to the template as {{labels.<key>}}
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+// POST every message to a webhook, shaped by a preset or a custom JSON template
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub preset: WebhookPreset,
+    // Replaces the preset's template. Placeholders: {{subject}}, {{report}}, {{status}},
+    // {{url}} (the DOWN urls), {{down_count}}, {{labels}} (a JSON object) and {{alerts}}
+    // (a JSON array of the DOWN sites' runbook_url and alert_message)
+    #[serde(default)]
+    pub template: Option<String>,
+    // Static key/values for the {{labels}} placeholder, e.g. the environment
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookPreset {
+    #[default]
+    Generic,
+    Slack,
+    Discord,
+}
+
+// Archive each cycle's JSON report to an S3-compatible bucket (path-style urls)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    #[serde(default)]
+    pub key_prefix: String,
+    // Reports that failed to upload wait here for the next cycle
+    #[serde(default = "default_s3_spool_dir")]
+    pub spool_dir: String,
+}
+
+// Embedded http server exposing the latest results
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerConfig {
+    pub listen: SocketAddr,
+}
+
+// Several instances checking the same sites, only the lease holder notifies
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LeaderConfig {
+    // Lease file on storage shared by every instance
+    pub lease_path: String,
+    // Defaults to the host name and process id
+    #[serde(default)]
+    pub instance_id: Option<String>,
+    // How long a lease lasts without renewal, renewed once per cycle, so it must
+    // outlast the interval plus a cycle's run time
+    #[serde(default = "default_lease_seconds")]
+    pub lease_seconds: u64,
+}
+
+fn default_lease_seconds() -> u64 {
+    600
+}
+
+// A self-contained HTML page of the latest cycle, written to a file and/or served at /
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatusPageConfig {
+    #[serde(default)]
+    pub path: Option<String>,
+    // Serve the page at / on the status server, needs [settings.server]
+    #[serde(default)]
+    pub serve: bool,
+    #[serde(default = "default_status_page_title")]
+    pub title: String,
+    // How often the browser reloads the page
+    #[serde(default = "default_status_page_refresh_seconds")]
+    pub refresh_seconds: u64,
+}
+
+fn default_status_page_title() -> String {
+    String::from("Service Status")
+}
+
+fn default_status_page_refresh_seconds() -> u64 {
+    60
 }
 
 pub fn load_settings() -> Result<Settings, ConfigError> {
     let mut settings = Config::builder();
     settings = settings.add_source(File::with_name("Config").required(false));
     let settings = settings.build()?;
-    let app_settings: Settings = settings.get("settings")?;
+    let mut app_settings: Settings = settings.get("settings")?;
+    app_settings.expand_env()?;
+    app_settings.app.validate();
+    app_settings.websites.validate();
+    app_settings.validate_profiles();
+    app_settings.validate_recipients();
+    app_settings.websites.compile()?;
+    for profile in app_settings.profiles.values_mut() {
+        if let Some(websites) = profile.websites.as_mut() {
+            websites.compile()?;
+        }
+    }
+    if let Some(leader) = &app_settings.leader {
+        let longest_interval = app_settings
+            .profiles
+            .values()
+            .filter_map(|profile| profile.interval_seconds)
+            .chain([app_settings.app.interval_seconds])
+            .max()
+            .unwrap_or(app_settings.app.interval_seconds);
+        if leader.lease_seconds <= longest_interval {
+            log!(
+                LogLevel::Warn,
+                "leader.lease_seconds ({}) should be well above interval_seconds ({}), or leadership will change hands between cycles",
+                leader.lease_seconds,
+                longest_interval
+            );
+        }
+    }
+    if app_settings.app.doh.is_none()
+        && app_settings
+            .websites
+            .sites
+            .iter()
+            .any(|site| site.dnssec.is_some())
+    {
+        log!(
+            LogLevel::Warn,
+            "dnssec is checked through [settings.app.doh], no DNSSEC status will be recorded"
+        );
+    }
+    if let Some(status_page) = &app_settings.status_page {
+        if status_page.serve && app_settings.server.is_none() {
+            log!(
+                LogLevel::Warn,
+                "status_page.serve needs [settings.server], the page will not be served"
+            );
+        }
+    }
     Ok(app_settings)
 }
 
+// Replace every `${VAR}` in the value with the variable from the environment
+pub fn expand_env(value: &str) -> Result<String, ConfigError> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find('}').ok_or_else(|| {
+            ConfigError::Message(format!("Unterminated ${{ in config value: {}", value))
+        })?;
+        let name = &after[..end];
+        let var = std::env::var(name).map_err(|_| {
+            ConfigError::Message(format!(
+                "Environment variable {} referenced in config is not set",
+                name
+            ))
+        })?;
+        expanded.push_str(&var);
+        rest = &after[end + 1..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn expand_env_in_place(value: &mut String) -> Result<(), ConfigError> {
+    *value = expand_env(value)?;
+    Ok(())
+}
+
+impl Settings {
+    // Expand environment references in every string field that can carry one
+    fn expand_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(statsd_addr) = self.app.statsd_addr.as_mut() {
+            expand_env_in_place(statsd_addr)?;
+        }
+        expand_env_in_place(&mut self.app.statsd_prefix)?;
+        if let Some(report_archive_path) = self.app.report_archive_path.as_mut() {
+            expand_env_in_place(report_archive_path)?;
+        }
+        if let Some(history_path) = self.app.history_path.as_mut() {
+            expand_env_in_place(history_path)?;
+        }
+        if let Some(heartbeat_path) = self.app.heartbeat_path.as_mut() {
+            expand_env_in_place(heartbeat_path)?;
+        }
+        if let Some(sqlite_path) = self.app.sqlite_path.as_mut() {
+            expand_env_in_place(sqlite_path)?;
+        }
+        if let Some(fifo_path) = self.app.fifo_path.as_mut() {
+            expand_env_in_place(fifo_path)?;
+        }
+        if let Some(connectivity_probe_url) = self.app.connectivity_probe_url.as_mut() {
+            expand_env_in_place(connectivity_probe_url)?;
+        }
+        if let Some(doh) = self.app.doh.as_mut() {
+            expand_env_in_place(&mut doh.endpoint)?;
+        }
+        if let Some(watch_header) = self.app.watch_header.as_mut() {
+            expand_env_in_place(watch_header)?;
+        }
+        if let Some(s3) = self.s3.as_mut() {
+            expand_env_in_place(&mut s3.endpoint)?;
+            expand_env_in_place(&mut s3.bucket)?;
+            expand_env_in_place(&mut s3.region)?;
+            expand_env_in_place(&mut s3.key_prefix)?;
+            expand_env_in_place(&mut s3.spool_dir)?;
+        }
+        for webhook in self.webhook.iter_mut().chain(self.recipients.values_mut()) {
+            expand_env_in_place(&mut webhook.url)?;
+            for value in webhook.labels.values_mut() {
+                expand_env_in_place(value)?;
+            }
+        }
+        if let Some(email_template) = self.email_template.as_mut() {
+            expand_env_in_place(&mut email_template.path)?;
+            for value in email_template.labels.values_mut() {
+                expand_env_in_place(value)?;
+            }
+        }
+        for one_shot in self.one_shots.iter_mut() {
+            for url in one_shot.urls.iter_mut() {
+                expand_env_in_place(url)?;
+            }
+            if let Some(name) = one_shot.name.as_mut() {
+                expand_env_in_place(name)?;
+            }
+        }
+        if let Some(leader) = self.leader.as_mut() {
+            expand_env_in_place(&mut leader.lease_path)?;
+            if let Some(instance_id) = leader.instance_id.as_mut() {
+                expand_env_in_place(instance_id)?;
+            }
+        }
+        if let Some(status_page) = self.status_page.as_mut() {
+            if let Some(path) = status_page.path.as_mut() {
+                expand_env_in_place(path)?;
+            }
+            expand_env_in_place(&mut status_page.title)?;
+        }
+
+        self.websites.expand_env()?;
+        for profile in self.profiles.values_mut() {
+            if let Some(websites) = profile.websites.as_mut() {
+                websites.expand_env()?;
+            }
+        }
+        Ok(())
+    }
+
+    // The profile picked by name, else the first (in name order) whose schedule covers
+    // `now`. None means the base settings apply.
+    pub fn active_profile(
+        &self,
+        name: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Option<(&String, &ProfileConfig)> {
+        if let Some(name) = name {
+            return self.profiles.get_key_value(name);
+        }
+        self.profiles.iter().find(|(_, profile)| {
+            profile
+                .schedule
+                .as_ref()
+                .is_some_and(|schedule| schedule.is_active(now))
+        })
+    }
+
+    // Sites routed to a recipient group that isn't configured would go unreported
+    fn validate_recipients(&self) {
+        let profile_websites = self
+            .profiles
+            .values()
+            .filter_map(|profile| profile.websites.as_ref());
+        for websites in std::iter::once(&self.websites).chain(profile_websites) {
+            for site in &websites.sites {
+                for name in &site.recipients {
+                    if !self.recipients.contains_key(name) {
+                        log!(
+                            LogLevel::Warn,
+                            "{}: recipients has {:?}, which is not under [settings.recipients]",
+                            site.url,
+                            name
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // Clamp and check the profiles the way the base settings are
+    fn validate_profiles(&mut self) {
+        let min_interval = self.app.min_interval_seconds;
+        for (name, profile) in self.profiles.iter_mut() {
+            if let Some(interval) = profile.interval_seconds {
+                let clamped = interval.clamp(min_interval, MAX_INTERVAL_SECONDS);
+                if clamped != interval {
+                    log!(
+                        LogLevel::Warn,
+                        "profile {}: interval_seconds {} is out of range, using {}",
+                        name,
+                        interval,
+                        clamped
+                    );
+                    profile.interval_seconds = Some(clamped);
+                }
+            }
+            if let Some(websites) = &profile.websites {
+                websites.validate();
+            }
+        }
+    }
+
+    // The resolved settings as JSON, with anything that looks like a credential masked
+    pub fn redacted_json(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).unwrap_or(serde_json::Value::Null);
+        redact(&mut value);
+        // Slack and Discord webhook urls carry their secret in the path
+        if let Some(url) = value.pointer_mut("/webhook/url") {
+            *url = serde_json::Value::String(REDACTED.to_string());
+        }
+        if let Some(recipients) = value
+            .pointer_mut("/recipients")
+            .and_then(|recipients| recipients.as_object_mut())
+        {
+            for webhook in recipients.values_mut() {
+                if let Some(url) = webhook.get_mut("url") {
+                    *url = serde_json::Value::String(REDACTED.to_string());
+                }
+            }
+        }
+        // Parameter values taken from the environment, under names that don't look secret
+        let websites = std::iter::once((String::from("/websites"), &self.websites)).chain(
+            self.profiles.iter().filter_map(|(name, profile)| {
+                let websites = profile.websites.as_ref()?;
+                Some((
+                    format!("/profiles/{}/websites", pointer_token(name)),
+                    websites,
+                ))
+            }),
+        );
+        for (pointer, websites) in websites {
+            for (index, site) in websites.sites.iter().enumerate() {
+                for name in &site.templated_params {
+                    for params in ["query", "path_params"] {
+                        let param = format!(
+                            "{}/sites/{}/{}/{}",
+                            pointer,
+                            index,
+                            params,
+                            pointer_token(name)
+                        );
+                        if let Some(param) = value.pointer_mut(&param) {
+                            *param = serde_json::Value::String(REDACTED.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        value
+    }
+}
+
+pub const REDACTED: &str = "REDACTED";
+
+// A key escaped for use in a JSON pointer
+fn pointer_token(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+// Key names whose values are treated as secrets wherever they appear
+const SECRET_KEYS: [&str; 8] = [
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "credential",
+    "auth",
+    "api_key",
+    "client_key",
+];
+
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    SECRET_KEYS.iter().any(|secret| key.contains(secret))
+}
+
+fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_secret_key(key) && !value.is_null() {
+                    *value = serde_json::Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact),
+        serde_json::Value::String(text) => *text = redact_url(text),
+        _ => {}
+    }
+}
+
+// Percent-encode a path parameter so it stays a single path segment
+fn encode_path_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// Strip user:password@ and secret looking query parameters from urls (proxy urls included)
+pub fn redact_url(text: &str) -> String {
+    let mut url = match Url::parse(text) {
+        Ok(url) if url.has_host() => url,
+        _ => return text.to_string(),
+    };
+    let has_credentials = !url.username().is_empty() || url.password().is_some();
+    let has_secret_query = url.query_pairs().any(|(key, _)| is_secret_key(&key));
+    if !has_credentials && !has_secret_query {
+        // Leave the rest untouched rather than normalised by the parser
+        return text.to_string();
+    }
+    if has_credentials {
+        let _ = url.set_username(REDACTED);
+        let _ = url.set_password(None);
+    }
+    if has_secret_query {
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(key, value)| {
+                let value = if is_secret_key(&key) {
+                    REDACTED.to_string()
+                } else {
+                    value.into_owned()
+                };
+                (key.into_owned(), value)
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
+}
+
+impl AppSpecificConfig {
+    // Clamp values that would make the monitor spin or never run
+    pub fn validate(&mut self) {
+        let min_interval = self.min_interval_seconds.max(1);
+        if self.min_interval_seconds != min_interval {
+            log!(
+                LogLevel::Warn,
+                "min_interval_seconds must be at least 1, using {}",
+                min_interval
+            );
+            self.min_interval_seconds = min_interval;
+        }
+
+        if self.interval_seconds < min_interval {
+            log!(
+                LogLevel::Warn,
+                "interval_seconds {} is below the minimum of {}, clamping",
+                self.interval_seconds,
+                min_interval
+            );
+            self.interval_seconds = min_interval;
+        } else if self.interval_seconds > MAX_INTERVAL_SECONDS {
+            log!(
+                LogLevel::Warn,
+                "interval_seconds {} is longer than a week, clamping to {}",
+                self.interval_seconds,
+                MAX_INTERVAL_SECONDS
+            );
+            self.interval_seconds = MAX_INTERVAL_SECONDS;
+        }
+
+        if let Some(probe_url) = &self.connectivity_probe_url {
+            if !Url::parse(probe_url).is_ok_and(|url| url.has_host()) {
+                log!(
+                    LogLevel::Warn,
+                    "connectivity_probe_url {} is not a valid url, cycles will not be probed",
+                    probe_url
+                );
+                self.connectivity_probe_url = None;
+            }
+        }
+
+        let concurrency = self
+            .max_concurrent_checks
+            .resolve(self.concurrency_multiplier);
+        log!(
+            LogLevel::Info,
+            "Running up to {} checks at once{}",
+            concurrency,
+            match self.max_concurrent_checks {
+                MaxConcurrentChecks::Auto(_) => format!(
+                    " (auto, x{} of the available parallelism)",
+                    self.concurrency_multiplier
+                ),
+                MaxConcurrentChecks::Fixed(_) => String::new(),
+            }
+        );
+
+        let ascending = self
+            .metrics_histogram_buckets
+            .windows(2)
+            .all(|pair| pair[0] < pair[1]);
+        if self.metrics_histogram_buckets.is_empty() || !ascending {
+            log!(
+                LogLevel::Warn,
+                "metrics_histogram_buckets must be a non-empty list of ascending ms bounds, using the defaults"
+            );
+            self.metrics_histogram_buckets = default_metrics_histogram_buckets();
+        }
+
+        for pattern in &self.error_body_redact_patterns {
+            if let Err(e) = Regex::new(pattern) {
+                log!(
+                    LogLevel::Warn,
+                    "error_body_redact_patterns: {} is not a valid regex, ignoring it: {}",
+                    pattern,
+                    e
+                );
+            }
+        }
+    }
+}
+
 pub fn get_config() -> AppConfig {
     let mut config: AppConfig = match AppConfig::new() {
         Ok(loaded_data) => loaded_data,
         Err(e) => {
             log!(LogLevel::Error, "Couldn't load config: {}", e.to_string());
-            std::process::exit(0)
+            ExitCode::BadConfig.exit()
         }
     };
     config.app_name = env!("CARGO_PKG_NAME").to_string();
@@ -55,6 +2136,99 @@ impl fmt::Display for AppSpecificConfig {
             "{}\n  {}",
             "AppSpecificConfig:".bold().blue(),
             format!("Interval Seconds: {}", self.interval_seconds).green()
+        )?;
+        if let Some(size_alert_bytes) = self.size_alert_bytes {
+            write!(
+                f,
+                "\n  {}",
+                format!("Size Alert Bytes: {}", size_alert_bytes).green()
+            )?;
+        }
+        if let Some(quiet_hours) = &self.quiet_hours {
+            write!(f, "\n  {}", quiet_hours)?;
+        }
+        if let Some(doh) = &self.doh {
+            write!(
+                f,
+                "\n  {}",
+                format!(
+                    "DNS-over-HTTPS: {}{}",
+                    doh.endpoint,
+                    if doh.strict { " (strict)" } else { "" }
+                )
+                .green()
+            )?;
+        }
+        if let Some(min_tls_version) = self.min_tls_version {
+            write!(
+                f,
+                "\n  {}",
+                format!("Minimum TLS Version: {}", min_tls_version).green()
+            )?;
+        }
+        if let Some(max_consecutive_self_errors) = self.max_consecutive_self_errors {
+            write!(
+                f,
+                "\n  {}",
+                format!(
+                    "Max Consecutive Self Errors: {}",
+                    max_consecutive_self_errors
+                )
+                .green()
+            )?;
+        }
+        if let Some(statsd_addr) = &self.statsd_addr {
+            write!(
+                f,
+                "\n  {}",
+                format!("StatsD: {} (prefix {})", statsd_addr, self.statsd_prefix).green()
+            )?;
+        }
+        if let Some(email_report_max_bytes) = self.email_report_max_bytes {
+            write!(
+                f,
+                "\n  {}",
+                format!(
+                    "Email Reports Compressed Over: {} bytes",
+                    email_report_max_bytes
+                )
+                .green()
+            )?;
+        }
+        if let Some(report_archive_path) = &self.report_archive_path {
+            write!(
+                f,
+                "\n  {}",
+                format!(
+                    "Report Archive: {} (rotate at {} bytes, keep {})",
+                    report_archive_path, self.report_archive_max_bytes, self.report_archive_keep
+                )
+                .green()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days = if self.days.is_empty() {
+            "every day".to_string()
+        } else {
+            self.days
+                .iter()
+                .map(|day| day.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        write!(
+            f,
+            "{}",
+            format!(
+                "Quiet Hours: {} - {} {} ({}), below {} suppressed",
+                self.start, self.end, self.timezone, days, self.min_severity
+            )
+            .green()
         )
     }
 }
@@ -63,20 +2237,468 @@ impl fmt::Display for AppSpecificConfig {
 impl fmt::Display for WebsiteConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}\n  {}", "WebsiteConfig:".bold().blue(), "URLs: \n".yellow())?;
-        for (index, url) in self.urls.iter().enumerate() {
-            writeln!(f, "    {}. {}", (index + 1).to_string().cyan(), url.magenta())?;
+        for (index, site) in self.all_sites().iter().enumerate() {
+            writeln!(f, "    {}. {}", (index + 1).to_string().cyan(), site)?;
         }
         Ok(())
     }
 }
 
+impl fmt::Display for SiteConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.resolved_url().magenta())?;
+        if self.priority != 0 {
+            write!(f, " {}", format!("(priority {})", self.priority).dimmed())?;
+        }
+        Ok(())
+    }
+}
+
+impl SiteConfig {
+    // Every setting that applies to this site once defaults and the app config are
+    // folded in, for --list-sites
+    pub fn describe(&self, app: &AppSpecificConfig) -> String {
+        let mut lines = vec![
+            format!("Interval: {}s", app.interval_seconds),
+            if self.fast_path() {
+                format!("Timeout: {} ms", self.fast_path_timeout_ms)
+            } else {
+                format!("Timeout: {}s", REQUEST_TIMEOUT_SECONDS)
+            },
+            format!("Method: {}", self.method.to_uppercase()),
+            format!("Priority: {}", self.priority),
+            format!("Weight: {}", self.weight),
+        ];
+        if self.expected_status.is_empty() {
+            lines.push(String::from("Expected Status: any"));
+        }
+        for (method, statuses) in &self.expected_status {
+            lines.push(format!("Expected Status ({}): {:?}", method, statuses));
+        }
+        if let Some(degraded) = self.degraded_response_time_ms {
+            lines.push(format!("Degraded Above: {} ms", degraded));
+        }
+        if let Some(max) = self.max_response_time_ms {
+            lines.push(format!("Down Above: {} ms", max));
+        }
+        if let Some(floor) = self.min_expected_response_ms {
+            lines.push(format!("Degraded Below: {} ms", floor));
+        }
+        if let Some(p95_degraded) = self.p95_degraded_ms {
+            lines.push(format!("Degraded Above (p95): {} ms", p95_degraded));
+        }
+        if let Some(p95_max) = self.p95_max_ms {
+            lines.push(format!("Down Above (p95): {} ms", p95_max));
+        }
+        let budgets: Vec<String> = [
+            ("connect", self.connect_budget_ms),
+            ("tls", self.tls_budget_ms),
+            ("ttfb", self.ttfb_budget_ms),
+            ("total", self.total_budget_ms),
+        ]
+        .iter()
+        .filter_map(|(phase, budget)| budget.map(|budget| format!("{} {} ms", phase, budget)))
+        .collect();
+        if !budgets.is_empty() {
+            lines.push(format!("Phase Budgets: {}", budgets.join(", ")));
+        }
+        if let Some(range) = &self.range {
+            lines.push(format!("Range: {}", range));
+        }
+        if let Some(host_header) = &self.host_header {
+            lines.push(format!("Host Header: {}", host_header));
+        }
+        if let Some(sni) = &self.sni {
+            lines.push(format!("SNI: {}", sni));
+        }
+        if let Some(dns_timeout) = self.dns_timeout_ms {
+            lines.push(format!("DNS Timeout: {} ms", dns_timeout));
+        }
+        match self.fast_path {
+            FastPathMode::Auto if self.fast_path() => {
+                lines.push(String::from("Fast Path: on (loopback host)"))
+            }
+            FastPathMode::On => lines.push(String::from("Fast Path: on")),
+            _ => {}
+        }
+        if !self.local_addresses.is_empty() {
+            let addresses: Vec<String> = self
+                .local_addresses
+                .iter()
+                .map(|address| address.to_string())
+                .collect();
+            lines.push(format!("Source Addresses: {}", addresses.join(", ")));
+        } else if let Some(address) = self.local_address.or(app.local_address) {
+            lines.push(format!("Source Address: {}", address));
+        }
+        if self.warmup {
+            lines.push(format!("Warmup: {}", self.warmup_method.to_uppercase()));
+        }
+        if !self.read_body {
+            lines.push(String::from("Read Body: no"));
+        }
+        if self.http3 != Http3Mode::Off {
+            lines.push(format!("HTTP/3: {:?}", self.http3));
+        }
+        if let Some(encoding) = &self.encoding {
+            lines.push(format!("Encoding: {}", encoding));
+        }
+        if !self.allow_empty_body {
+            lines.push(String::from("Empty Body: not allowed"));
+        }
+        if let Some(min_body_bytes) = self.min_body_bytes {
+            lines.push(format!("Min Body Size: {} bytes", min_body_bytes));
+        }
+        if let Some(max_body_bytes) = self.max_body_bytes {
+            lines.push(format!("Max Body Size: {} bytes", max_body_bytes));
+        }
+        if !self.forbidden_body.is_empty() || !self.forbidden_body_regex.is_empty() {
+            let forbidden: Vec<&str> = self
+                .forbidden_body
+                .iter()
+                .chain(&self.forbidden_body_regex)
+                .map(String::as_str)
+                .collect();
+            lines.push(format!("Forbidden Body: {}", forbidden.join(", ")));
+        }
+        if let Some(validator_command) = &self.validator_command {
+            lines.push(format!(
+                "Validator: {} (timeout {}s)",
+                validator_command, self.validator_timeout_seconds
+            ));
+        }
+        if self.require_https_final {
+            lines.push(String::from("Require HTTPS Final: yes"));
+        }
+        if let Some(expected_cache_control) = &self.expected_cache_control {
+            lines.push(format!(
+                "Expected Cache-Control: {}",
+                expected_cache_control
+            ));
+        }
+        if self.require_cache_validator {
+            lines.push(String::from("Require ETag or Last-Modified: yes"));
+        }
+        if let Some(max_redirect_hops) = self.max_redirect_hops {
+            lines.push(format!("Max Redirect Hops: {}", max_redirect_hops));
+        }
+        if let Some(resolve_after) = self.resolve_after_successes {
+            lines.push(format!("Resolve After: {} UP cycles", resolve_after));
+        }
+        if !self.non_alerting_categories.is_empty() {
+            lines.push(format!(
+                "Non-Alerting Categories: {}",
+                self.non_alerting_categories.join(", ")
+            ));
+        }
+        if let Some(sla_target) = self.sla_target {
+            lines.push(format!("SLA Target: {}%", sla_target));
+        }
+        if let Some(slo) = &self.latency_slo {
+            lines.push(format!(
+                "Latency SLO: {}% within {} ms over {} days",
+                slo.target_percent, slo.threshold_ms, app.slo_window_days
+            ));
+        }
+        if let Some(watch_header) = &self.watch_header {
+            lines.push(format!("Watch Header: {}", watch_header));
+        }
+        if let Some(alert_message) = &self.alert_message {
+            lines.push(format!("Alert Message: {}", alert_message));
+        }
+        if let Some(runbook_url) = &self.runbook_url {
+            lines.push(format!("Runbook: {}", runbook_url));
+        }
+        if !self.recipients.is_empty() {
+            lines.push(format!(
+                "Recipients: {} ({})",
+                self.recipients.join(", "),
+                match self.recipients_mode {
+                    RecipientsMode::Override => "instead of the global channels",
+                    RecipientsMode::Augment => "as well as the global channels",
+                }
+            ));
+        }
+        if self.client_cert.is_some() {
+            lines.push(String::from("Client Certificate: yes"));
+        }
+        if let Some(signing) = &self.signing {
+            let parts: Vec<String> = signing.sign.iter().map(|part| part.to_string()).collect();
+            lines.push(format!(
+                "Signing: {} in {} over {}",
+                signing.algorithm,
+                signing.header,
+                parts.join(", ")
+            ));
+        }
+        if self.expect_down {
+            lines.push(String::from("Expect Down: yes (inverted check)"));
+        }
+        if self.check_ipv6 {
+            lines.push(String::from("Dual-Stack Check: yes"));
+        }
+        if !self.allowed_ip_ranges.is_empty() {
+            lines.push(format!(
+                "Allowed IP Ranges: {}",
+                self.allowed_ip_ranges.join(", ")
+            ));
+        }
+        if let Some(graphql) = &self.graphql {
+            lines.push(format!(
+                "GraphQL: {}",
+                match (&graphql.data_path, &graphql.expected) {
+                    (Some(path), Some(expected)) => format!("data.{} = {}", path, expected),
+                    (Some(path), None) => format!("data.{} present", path),
+                    _ => String::from("no errors"),
+                }
+            ));
+        }
+        if self.json_schema.is_some() {
+            lines.push(String::from("JSON Schema: yes"));
+        }
+        if let Some(prefetch_url) = &self.prefetch_url {
+            lines.push(format!("Prefetch: {}", redact_url(prefetch_url)));
+        }
+        if let Some(dnssec) = self.dnssec {
+            lines.push(format!(
+                "DNSSEC: {}",
+                match dnssec {
+                    DnssecMode::Record => "record",
+                    DnssecMode::Alert => "alert when no longer secure",
+                }
+            ));
+        }
+        if let Some(nonce) = &self.nonce {
+            lines.push(format!(
+                "Nonce: {}",
+                nonce
+                    .header
+                    .as_deref()
+                    .or(nonce.json_path.as_deref())
+                    .unwrap_or("<whole body>")
+            ));
+        }
+        if let Some(canary) = &self.canary {
+            lines.push(format!(
+                "Canary: {} ({:?}, window {}s)",
+                canary.json_path.as_deref().unwrap_or("<whole body>"),
+                canary.alert_on,
+                canary.window_seconds
+            ));
+        }
+
+        let mut description = self.to_string();
+        for line in lines {
+            description.push_str(&format!("\n    {}", line.green()));
+        }
+        description
+    }
+}
+
 // Implement Display for Settings
 impl fmt::Display for Settings {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}\n\n{}",
-            self.app, self.websites
+        write!(f, "{}\n\n{}", self.app, self.websites)?;
+        if let Some(server) = &self.server {
+            write!(
+                f,
+                "\n{}\n  {}",
+                "ServerConfig:".bold().blue(),
+                format!("Listen: {}", server.listen).green()
+            )?;
+        }
+        if let Some(s3) = &self.s3 {
+            write!(
+                f,
+                "\n{}\n  {}",
+                "S3Config:".bold().blue(),
+                format!("Bucket: {}/{}{}", s3.endpoint, s3.bucket, s3.key_prefix).green()
+            )?;
+        }
+        if !self.profiles.is_empty() {
+            let names: Vec<&str> = self.profiles.keys().map(|name| name.as_str()).collect();
+            write!(
+                f,
+                "\n{}\n  {}",
+                "Profiles:".bold().blue(),
+                names.join(", ").green()
+            )?;
+        }
+        if !self.one_shots.is_empty() {
+            write!(f, "\n{}", "OneShotChecks:".bold().blue())?;
+            for one_shot in &self.one_shots {
+                write!(
+                    f,
+                    "\n  {}",
+                    format!(
+                        "{}: {} at {}",
+                        one_shot.label(),
+                        one_shot.urls.join(", "),
+                        one_shot.at.to_rfc3339()
+                    )
+                    .green()
+                )?;
+            }
+        }
+        if let Some(webhook) = &self.webhook {
+            let format = match webhook.template {
+                Some(_) => String::from("custom template"),
+                None => format!("{:?} preset", webhook.preset),
+            };
+            write!(
+                f,
+                "\n{}\n  {}",
+                "WebhookConfig:".bold().blue(),
+                format!("Format: {}", format).green()
+            )?;
+        }
+        if !self.recipients.is_empty() {
+            write!(f, "\n{}", "Recipients:".bold().blue())?;
+            for (name, webhook) in &self.recipients {
+                let format = match webhook.template {
+                    Some(_) => String::from("custom template"),
+                    None => format!("{:?} preset", webhook.preset),
+                };
+                write!(
+                    f,
+                    "\n  {}",
+                    format!("{}: webhook, {}", name, format).green()
+                )?;
+            }
+        }
+        if let Some(email_template) = &self.email_template {
+            write!(
+                f,
+                "\n{}\n  {}",
+                "EmailTemplateConfig:".bold().blue(),
+                format!("Template: {}", email_template.path).green()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(toml: &str) -> AppSpecificConfig {
+        toml::from_str(toml).expect("valid app settings")
+    }
+
+    #[test]
+    fn zero_interval_is_clamped_to_the_minimum() {
+        let mut app = app("interval_seconds = 0");
+        app.validate();
+        assert_eq!(app.interval_seconds, default_min_interval_seconds());
+    }
+
+    #[test]
+    fn zero_minimum_is_raised_to_one() {
+        let mut app = app("interval_seconds = 0\nmin_interval_seconds = 0");
+        app.validate();
+        assert_eq!(app.min_interval_seconds, 1);
+        assert_eq!(app.interval_seconds, 1);
+    }
+
+    #[test]
+    fn absurd_interval_is_clamped_to_a_week() {
+        let mut app = app("interval_seconds = 315360000");
+        app.validate();
+        assert_eq!(app.interval_seconds, MAX_INTERVAL_SECONDS);
+    }
+
+    #[test]
+    fn reasonable_interval_is_kept() {
+        let mut app = app("interval_seconds = 300");
+        app.validate();
+        assert_eq!(app.interval_seconds, 300);
+    }
+
+    #[test]
+    fn profile_intervals_are_clamped() {
+        let mut settings: Settings = toml::from_str(
+            r#"
+            [app]
+            interval_seconds = 60
+
+            [websites]
+
+            [profiles.spin]
+            interval_seconds = 0
+
+            [profiles.never]
+            interval_seconds = 315360000
+            "#,
         )
+        .expect("valid settings");
+        settings.app.validate();
+        settings.validate_profiles();
+        assert_eq!(
+            settings.profiles["spin"].interval_seconds,
+            Some(settings.app.min_interval_seconds)
+        );
+        assert_eq!(
+            settings.profiles["never"].interval_seconds,
+            Some(MAX_INTERVAL_SECONDS)
+        );
     }
-}
\ No newline at end of file
+
+    // Each test sets its own variables, tests run in parallel within one process
+    #[test]
+    fn env_references_are_expanded() {
+        std::env::set_var("WM_TEST_EXPAND_HOST", "example.com");
+        std::env::set_var("WM_TEST_EXPAND_TOKEN", "s3cret");
+        let mut value =
+            String::from("https://${WM_TEST_EXPAND_HOST}/health?t=${WM_TEST_EXPAND_TOKEN}");
+        expand_env_in_place(&mut value).expect("variables are set");
+        assert_eq!(value, "https://example.com/health?t=s3cret");
+    }
+
+    #[test]
+    fn values_without_references_are_kept() {
+        let mut value = String::from("https://example.com/$HOME/{id}");
+        expand_env_in_place(&mut value).expect("nothing to expand");
+        assert_eq!(value, "https://example.com/$HOME/{id}");
+    }
+
+    #[test]
+    fn missing_variable_is_an_error() {
+        std::env::remove_var("WM_TEST_EXPAND_MISSING");
+        let mut value = String::from("https://${WM_TEST_EXPAND_MISSING}/health");
+        let error = expand_env_in_place(&mut value).expect_err("variable is unset");
+        assert!(error.to_string().contains("WM_TEST_EXPAND_MISSING"));
+        assert_eq!(value, "https://${WM_TEST_EXPAND_MISSING}/health");
+    }
+
+    #[test]
+    fn unterminated_reference_is_an_error() {
+        let mut value = String::from("https://${WM_TEST_EXPAND_HOST/health");
+        assert!(expand_env_in_place(&mut value).is_err());
+    }
+
+    #[test]
+    fn settings_expand_site_values() {
+        std::env::set_var("WM_TEST_EXPAND_REGION", "eu");
+        let mut settings: Settings = toml::from_str(
+            r#"
+            [app]
+            interval_seconds = 60
+
+            [[websites.sites]]
+            url = "https://example.com/health"
+            query = { region = "${WM_TEST_EXPAND_REGION}" }
+            "#,
+        )
+        .expect("valid settings");
+        settings.expand_env().expect("variables are set");
+        let site = &settings.websites.sites[0];
+        assert_eq!(site.query["region"], "eu");
+        assert!(site.templated_params.contains("region"));
+        assert_eq!(
+            site.resolved_url(),
+            "https://example.com/health?region=REDACTED"
+        );
+        assert_eq!(site.request_url(), "https://example.com/health?region=eu");
+    }
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use reqwest::Url;
+use tokio::net::{lookup_host, TcpStream};
+use tokio::time::Instant;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Resolve and connect to the probe url, telling "this machine is offline" apart from
+// "every site is down". Nothing is sent over the connection.
+pub async fn probe(url: &str) -> Result<(), String> {
+    connect(url).await.map(|_| ())
+}
+
+// How long opening a TCP connection to the url's host takes, resolution excluded
+pub async fn connect_time(url: &str) -> Result<u128, String> {
+    connect(url).await.map(|elapsed| elapsed.as_millis())
+}
+
+async fn connect(url: &str) -> Result<Duration, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("invalid url {}: {}", url, e))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("no host in {}", url))?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| format!("no port for {}", url))?;
+
+    let address = match tokio::time::timeout(PROBE_TIMEOUT, lookup_host((host, port))).await {
+        Ok(Ok(mut addresses)) => addresses
+            .next()
+            .ok_or_else(|| format!("{} has no addresses", host))?,
+        Ok(Err(e)) => return Err(format!("cannot resolve {}: {}", host, e)),
+        Err(_) => return Err(format!("resolving {} timed out", host)),
+    };
+
+    let start = Instant::now();
+    match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(address)).await {
+        Ok(Ok(_)) => Ok(start.elapsed()),
+        Ok(Err(e)) => Err(format!("cannot connect to {}: {}", address, e)),
+        Err(_) => Err(format!("connecting to {} timed out", address)),
+    }
+}
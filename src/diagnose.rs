@@ -0,0 +1,87 @@
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::Url;
+use serde::Serialize;
+use tokio::net::{lookup_host, TcpStream};
+
+use crate::tls;
+
+// The connection stage a DOWN site fell over at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Dns,
+    TcpConnect,
+    TlsHandshake,
+    // Everything below HTTP works, the request itself failed
+    Http,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Dns => write!(f, "DNS"),
+            Stage::TcpConnect => write!(f, "TCP connect"),
+            Stage::TlsHandshake => write!(f, "TLS handshake"),
+            Stage::Http => write!(f, "HTTP"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnosis {
+    pub failed_at: Stage,
+    pub detail: Option<String>,
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed at {}", self.failed_at)?;
+        if let Some(detail) = &self.detail {
+            write!(f, " ({})", detail)?;
+        }
+        Ok(())
+    }
+}
+
+const STAGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Walk DNS, TCP and TLS one at a time to find where a failing site breaks
+pub async fn diagnose(url: &str) -> Option<Diagnosis> {
+    let parsed = Url::parse(url).ok()?;
+    let host = parsed.host_str()?.to_string();
+    let port = parsed.port_or_known_default()?;
+    let failed = |failed_at, detail: String| {
+        Some(Diagnosis {
+            failed_at,
+            detail: Some(detail),
+        })
+    };
+
+    let addresses: Vec<_> = match lookup_host((host.as_str(), port)).await {
+        Ok(addresses) => addresses.collect(),
+        Err(e) => return failed(Stage::Dns, e.to_string()),
+    };
+    let address = match addresses.first() {
+        Some(address) => *address,
+        None => return failed(Stage::Dns, format!("{} has no addresses", host)),
+    };
+
+    match tokio::time::timeout(STAGE_TIMEOUT, TcpStream::connect(address)).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => return failed(Stage::TcpConnect, format!("{}: {}", address, e)),
+        Err(_) => return failed(Stage::TcpConnect, format!("{}: timed out", address)),
+    }
+
+    if parsed.scheme() == "https" {
+        if let Err(e) = tls::verified_handshake(&host, port).await {
+            return failed(Stage::TlsHandshake, e);
+        }
+    }
+
+    Some(Diagnosis {
+        failed_at: Stage::Http,
+        detail: None,
+    })
+}
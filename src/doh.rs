@@ -0,0 +1,140 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use reqwest::header::ACCEPT;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Record types from the DNS JSON API
+const TYPE_A: u64 = 1;
+const TYPE_AAAA: u64 = 28;
+
+// Response codes
+const RCODE_NOERROR: u64 = 0;
+const RCODE_SERVFAIL: u64 = 2;
+
+// What a validating resolver made of a host's records
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DnssecStatus {
+    // Signed and validated (the AD flag was set)
+    Secure,
+    // Answered, but not validated, typically an unsigned zone
+    Insecure,
+    // Signed, but validation failed: answers only come with checking disabled
+    Bogus,
+}
+
+impl fmt::Display for DnssecStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnssecStatus::Secure => write!(f, "secure"),
+            DnssecStatus::Insecure => write!(f, "insecure"),
+            DnssecStatus::Bogus => write!(f, "bogus"),
+        }
+    }
+}
+
+// Resolve a host through a DNS-over-HTTPS endpoint speaking the JSON API
+// (Cloudflare, Google and most public resolvers support it)
+pub async fn resolve(endpoint: &str, host: &str) -> Result<IpAddr, String> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    for record_type in ["A", "AAAA"] {
+        let response = client
+            .get(endpoint)
+            .query(&[("name", host), ("type", record_type)])
+            .header(ACCEPT, "application/dns-json")
+            .send()
+            .await
+            .map_err(|e| format!("DoH request to {} failed: {}", endpoint, e))?;
+
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| format!("DoH response from {} could not be read: {}", endpoint, e))?;
+        let answer: Value = serde_json::from_slice(&body)
+            .map_err(|e| format!("DoH response from {} was not valid JSON: {}", endpoint, e))?;
+
+        if let Some(address) = first_address(&answer) {
+            return Ok(address);
+        }
+    }
+
+    Err(format!("DoH returned no addresses for {}", host))
+}
+
+// Ask the DoH endpoint, which has to validate DNSSEC itself, about the host's A records.
+// A SERVFAIL that goes away with checking disabled (cd) means validation failed.
+pub async fn dnssec_status(endpoint: &str, host: &str) -> Result<DnssecStatus, String> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let answer = query_dnssec(&client, endpoint, host, false).await?;
+    match answer.get("Status").and_then(Value::as_u64) {
+        Some(RCODE_NOERROR) => {
+            if answer.get("AD").and_then(Value::as_bool) == Some(true) {
+                Ok(DnssecStatus::Secure)
+            } else {
+                Ok(DnssecStatus::Insecure)
+            }
+        }
+        Some(RCODE_SERVFAIL) => {
+            let unchecked = query_dnssec(&client, endpoint, host, true).await?;
+            match unchecked.get("Status").and_then(Value::as_u64) {
+                Some(RCODE_NOERROR) => Ok(DnssecStatus::Bogus),
+                _ => Err(format!("DoH lookup of {} failed (SERVFAIL)", host)),
+            }
+        }
+        Some(status) => Err(format!("DoH lookup of {} failed (rcode {})", host, status)),
+        None => Err(format!("DoH response from {} has no status", endpoint)),
+    }
+}
+
+async fn query_dnssec(
+    client: &Client,
+    endpoint: &str,
+    host: &str,
+    checking_disabled: bool,
+) -> Result<Value, String> {
+    let response = client
+        .get(endpoint)
+        .query(&[
+            ("name", host),
+            ("type", "A"),
+            ("do", "1"),
+            ("cd", if checking_disabled { "1" } else { "0" }),
+        ])
+        .header(ACCEPT, "application/dns-json")
+        .send()
+        .await
+        .map_err(|e| format!("DoH request to {} failed: {}", endpoint, e))?;
+
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| format!("DoH response from {} could not be read: {}", endpoint, e))?;
+    serde_json::from_slice(&body)
+        .map_err(|e| format!("DoH response from {} was not valid JSON: {}", endpoint, e))
+}
+
+fn first_address(answer: &Value) -> Option<IpAddr> {
+    answer
+        .get("Answer")?
+        .as_array()?
+        .iter()
+        .filter(|record| {
+            matches!(
+                record.get("type").and_then(Value::as_u64),
+                Some(TYPE_A) | Some(TYPE_AAAA)
+            )
+        })
+        .find_map(|record| record.get("data")?.as_str()?.parse().ok())
+}
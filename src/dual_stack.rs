@@ -0,0 +1,117 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use reqwest::header::{HOST, USER_AGENT};
+use reqwest::{Client, Url};
+use serde::Serialize;
+use tokio::net::lookup_host;
+use tokio::time::Instant;
+
+use crate::health::error_chain;
+
+const FAMILY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl fmt::Display for IpFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpFamily::Ipv4 => write!(f, "IPv4"),
+            IpFamily::Ipv6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+// The same request sent to one advertised address of a family
+#[derive(Debug, Clone, Serialize)]
+pub struct FamilyPath {
+    pub family: IpFamily,
+    pub address: String,
+    // Whether an HTTP response came back, whatever its status
+    pub ok: bool,
+    pub status_code: Option<u16>,
+    pub response_time_ms: Option<u128>,
+    pub error: Option<String>,
+}
+
+// Request the url once over each address family the host advertises, A records and
+// AAAA records alike, to tell a working IPv4 path from a broken IPv6 one. Hosts that
+// are IP literals or don't resolve give no paths.
+pub async fn check_families(url: &str, host_header: Option<&str>) -> Vec<FamilyPath> {
+    let parsed = match Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(_) => return Vec::new(),
+    };
+    let (host, port) = match (parsed.host_str(), parsed.port_or_known_default()) {
+        (Some(host), Some(port)) if host.parse::<IpAddr>().is_err() => (host.to_string(), port),
+        _ => return Vec::new(),
+    };
+    let addresses: Vec<SocketAddr> = match lookup_host((host.as_str(), port)).await {
+        Ok(addresses) => addresses.collect(),
+        Err(_) => return Vec::new(),
+    };
+
+    let mut paths = Vec::new();
+    for family in [IpFamily::Ipv4, IpFamily::Ipv6] {
+        let address = addresses.iter().find(|address| match family {
+            IpFamily::Ipv4 => address.is_ipv4(),
+            IpFamily::Ipv6 => address.is_ipv6(),
+        });
+        if let Some(address) = address {
+            paths.push(check_path(url, &host, *address, family, host_header).await);
+        }
+    }
+    paths
+}
+
+async fn check_path(
+    url: &str,
+    host: &str,
+    address: SocketAddr,
+    family: IpFamily,
+    host_header: Option<&str>,
+) -> FamilyPath {
+    let mut path = FamilyPath {
+        family,
+        address: address.ip().to_string(),
+        ok: false,
+        status_code: None,
+        response_time_ms: None,
+        error: None,
+    };
+    // Redirects could lead to other hosts, resolved without the pin
+    let client = match Client::builder()
+        .timeout(FAMILY_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve(host, address)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            path.error = Some(e.to_string());
+            return path;
+        }
+    };
+
+    let mut request = client.get(url).header(USER_AGENT, "HealthChecker/1.0");
+    if let Some(host_header) = host_header {
+        request = request.header(HOST, host_header);
+    }
+    let start = Instant::now();
+    match request.send().await {
+        Ok(response) => {
+            path.ok = true;
+            path.status_code = Some(response.status().as_u16());
+            path.response_time_ms = Some(start.elapsed().as_millis());
+        }
+        // reqwest's message alone doesn't say what went wrong on the way
+        Err(e) => path.error = Some(error_chain(&e)),
+    }
+    path
+}
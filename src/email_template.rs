@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+use chrono::Utc;
+use chrono_tz::Tz;
+use handlebars::{no_escape, Handlebars};
+use serde_json::{json, Value};
+
+use crate::alerts::Alert;
+use crate::changes::CycleChanges;
+use crate::config::{EmailTemplateConfig, SiteConfig};
+use crate::health::{CheckStatus, HealthCheckResult};
+use crate::health_score::HealthScore;
+use crate::report::format_timestamp;
+
+const TEMPLATE_NAME: &str = "email";
+
+// A Handlebars template the report emails are rendered from instead of the built-in
+// format. Emails are plain text, so nothing is HTML escaped.
+pub struct EmailTemplate {
+    registry: Handlebars<'static>,
+    labels: BTreeMap<String, String>,
+}
+
+impl EmailTemplate {
+    // Fails when the file can't be read or isn't a valid template
+    pub fn load(config: &EmailTemplateConfig) -> Result<Self, String> {
+        let template = fs::read_to_string(&config.path)
+            .map_err(|e| format!("cannot read email template {}: {}", config.path, e))?;
+        let mut registry = Handlebars::new();
+        registry.register_escape_fn(no_escape);
+        registry
+            .register_template_string(TEMPLATE_NAME, template)
+            .map_err(|e| format!("invalid email template {}: {}", config.path, e))?;
+        Ok(EmailTemplate {
+            registry,
+            labels: config.labels.clone(),
+        })
+    }
+
+    pub fn render(&self, subject: &str, data: &Value) -> Result<String, String> {
+        let mut data = data.clone();
+        if let Some(fields) = data.as_object_mut() {
+            fields.insert(String::from("subject"), json!(subject));
+            fields.insert(String::from("labels"), json!(self.labels));
+        }
+        self.registry
+            .render(TEMPLATE_NAME, &data)
+            .map_err(|e| e.to_string())
+    }
+}
+
+// Everything a template can use, apart from the subject and labels added when it is
+// rendered and the report part set per message:
+//   generated_at, generated_at_local, timestamp
+//   status (the worst site status), health_score, rolling_health_score
+//   counts.total / up / degraded / down / stale
+//   sites: every result with its url, sorted by url
+//   alerts: url, severity and message of each
+//   changes.newly_down / recovered: urls
+//   report (the built-in text of this part), part, part_count
+pub fn template_data(
+    sites: &[SiteConfig],
+    results: &HashMap<String, HealthCheckResult>,
+    alerts: &[Alert],
+    changes: &CycleChanges,
+    health_score: Option<&HealthScore>,
+    timezone: Tz,
+) -> Value {
+    let now = Utc::now();
+    let count = |status: CheckStatus| {
+        results
+            .values()
+            .filter(|result| result.status == status)
+            .count()
+    };
+
+    let mut urls: Vec<String> = sites.iter().map(SiteConfig::resolved_url).collect();
+    urls.sort();
+    urls.dedup();
+    let site_data: Vec<Value> = urls
+        .iter()
+        .filter_map(|url| {
+            let mut data = serde_json::to_value(results.get(url)?).ok()?;
+            data.as_object_mut()?
+                .insert(String::from("url"), json!(url));
+            Some(data)
+        })
+        .collect();
+    let alert_data: Vec<Value> = alerts
+        .iter()
+        .map(|alert| {
+            json!({
+                "url": alert.url,
+                "severity": alert.severity,
+                "message": alert.message,
+            })
+        })
+        .collect();
+
+    json!({
+        "generated_at": now.to_rfc3339(),
+        "generated_at_local": format_timestamp(now, timezone),
+        "timestamp": now.timestamp(),
+        "status": results
+            .values()
+            .map(|result| result.status)
+            .max()
+            .unwrap_or_default(),
+        "health_score": health_score.map(|score| score.current),
+        "rolling_health_score": health_score.map(|score| score.rolling),
+        "counts": {
+            "total": results.len(),
+            "up": count(CheckStatus::Up),
+            "degraded": count(CheckStatus::Degraded),
+            "down": count(CheckStatus::Down),
+            "stale": results.values().filter(|result| result.stale_since.is_some()).count(),
+        },
+        "sites": site_data,
+        "alerts": alert_data,
+        "changes": {
+            "newly_down": changes.newly_down,
+            "recovered": changes.recovered,
+        },
+    })
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+
+// Process exit codes for fatal errors, so a supervisor can tell them apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    // The application config (Overrides.toml / environment) couldn't be loaded
+    BadConfig = 2,
+    // The monitor settings (Config.toml) couldn't be loaded or were invalid
+    SettingsLoad = 3,
+    // The state file couldn't be written at startup
+    StateSave = 4,
+    // Sending or saving kept failing past max_consecutive_self_errors
+    SelfErrors = 5,
+    // --check-heartbeat found the heartbeat missing or older than allowed
+    StaleHeartbeat = 6,
+    // --test-notify couldn't deliver through at least one notifier
+    TestNotify = 7,
+}
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    pub fn exit(self) -> ! {
+        log!(LogLevel::Error, "Exiting: {}", self);
+        std::process::exit(self.code())
+    }
+}
+
+impl fmt::Display for ExitCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ExitCode::BadConfig => "bad config",
+            ExitCode::SettingsLoad => "settings load failure",
+            ExitCode::StateSave => "state save failure",
+            ExitCode::SelfErrors => "too many consecutive self errors",
+            ExitCode::StaleHeartbeat => "stale heartbeat",
+            ExitCode::TestNotify => "test notification failure",
+        };
+        write!(f, "{} ({})", name, self.code())
+    }
+}
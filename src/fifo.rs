@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use serde::Serialize;
+
+use crate::health::HealthCheckResult;
+
+// One line written to the pipe
+#[derive(Serialize)]
+struct FifoRecord<'a> {
+    at: u64,
+    url: &'a str,
+    #[serde(flatten)]
+    result: &'a HealthCheckResult,
+}
+
+// Streams every result of every cycle as JSON lines into a named pipe. The reader is
+// only waited on to finish a line it already has the start of: with no reader the
+// cycle's results are dropped, and once the pipe is full the rest of them are, each
+// counted in `dropped`.
+pub struct FifoOutput {
+    path: String,
+    dropped: u64,
+}
+
+impl FifoOutput {
+    // Creates the FIFO when nothing is at `path` yet
+    pub fn new(path: &str) -> Result<Self, String> {
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.file_type().is_fifo() => {}
+            Ok(_) => return Err(format!("{} exists and is not a FIFO", path)),
+            Err(e) if e.kind() == ErrorKind::NotFound => make_fifo(path)?,
+            Err(e) => return Err(format!("cannot stat {}: {}", path, e)),
+        }
+        Ok(FifoOutput {
+            path: path.to_string(),
+            dropped: 0,
+        })
+    }
+
+    // Results dropped since startup
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn write(&mut self, results: &HashMap<String, HealthCheckResult>, now: u64) {
+        let mut lines = Vec::new();
+        for (url, result) in results {
+            let record = FifoRecord {
+                at: now,
+                url,
+                result,
+            };
+            match serde_json::to_string(&record) {
+                Ok(line) => lines.push(line + "\n"),
+                Err(e) => log!(LogLevel::Error, "Failed to serialize {}: {}", url, e),
+            }
+        }
+        if lines.is_empty() {
+            return;
+        }
+
+        // Opening the write end without a reader fails right away instead of blocking
+        let mut pipe = match OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&self.path)
+        {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                if e.raw_os_error() != Some(libc::ENXIO) {
+                    log!(LogLevel::Warn, "Cannot open FIFO {}: {}", self.path, e);
+                }
+                self.drop_lines(lines.len(), "no reader");
+                return;
+            }
+        };
+
+        for (index, line) in lines.iter().enumerate() {
+            if let Err(e) = write_line(&mut pipe, line.as_bytes()) {
+                let reason = match e.kind() {
+                    ErrorKind::WouldBlock => String::from("the pipe is full"),
+                    ErrorKind::BrokenPipe => String::from("the reader went away"),
+                    _ => e.to_string(),
+                };
+                self.drop_lines(lines.len() - index, &reason);
+                return;
+            }
+        }
+    }
+
+    fn drop_lines(&mut self, count: usize, reason: &str) {
+        self.dropped += count as u64;
+        log!(
+            LogLevel::Debug,
+            "Dropped {} results for FIFO {}, {} ({} since startup)",
+            count,
+            self.path,
+            reason,
+            self.dropped
+        );
+    }
+}
+
+// Lines up to PIPE_BUF go in whole or not at all. A longer one is finished once the
+// pipe took its start, waiting for the reader to make room, so the reader never sees
+// half a line followed by another.
+fn write_line(pipe: &mut File, line: &[u8]) -> std::io::Result<()> {
+    let mut written = pipe.write(line)?;
+    while written < line.len() {
+        match pipe.write(&line[written..]) {
+            Ok(count) => written += count,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => wait_writable(pipe)?,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+// Block until the reader drained some of the pipe, or went away
+fn wait_writable(pipe: &File) -> std::io::Result<()> {
+    let mut poll_fd = libc::pollfd {
+        fd: pipe.as_raw_fd(),
+        events: libc::POLLOUT,
+        revents: 0,
+    };
+    loop {
+        if unsafe { libc::poll(&mut poll_fd, 1, -1) } >= 0 {
+            return Ok(());
+        }
+        let error = std::io::Error::last_os_error();
+        if error.kind() != ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+fn make_fifo(path: &str) -> Result<(), String> {
+    let c_path = CString::new(Path::new(path).as_os_str().as_bytes())
+        .map_err(|_| format!("invalid FIFO path {}", path))?;
+    // Readable and writable by the owner only, results can hold internal urls
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(format!(
+            "cannot create FIFO {}: {}",
+            path,
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    // A FIFO path of its own per test, removed again when dropped
+    struct TempFifo(String);
+
+    impl TempFifo {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "website_monitor_{}_{}.fifo",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+            TempFifo(path.to_string_lossy().into_owned())
+        }
+    }
+
+    impl Drop for TempFifo {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn results(count: usize, body_bytes: usize) -> HashMap<String, HealthCheckResult> {
+        (0..count)
+            .map(|index| {
+                let result = HealthCheckResult {
+                    body_snippet: Some("x".repeat(body_bytes)),
+                    ..Default::default()
+                };
+                (format!("https://example.com/{}", index), result)
+            })
+            .collect()
+    }
+
+    // Opened without blocking, before anything is written, then read like a consumer
+    // would: blocking until the writer closes its end
+    fn reader(path: &str) -> File {
+        let reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .expect("open the read end");
+        assert_eq!(
+            unsafe { libc::fcntl(reader.as_raw_fd(), libc::F_SETFL, 0) },
+            0
+        );
+        reader
+    }
+
+    fn read_lines(mut reader: File) -> Vec<serde_json::Value> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text).expect("read the pipe");
+        text.lines()
+            .map(|line| serde_json::from_str(line).expect("a whole JSON line"))
+            .collect()
+    }
+
+    #[test]
+    fn creates_the_fifo() {
+        let fifo = TempFifo::new("create");
+        FifoOutput::new(&fifo.0).expect("creates the FIFO");
+        assert!(fs::metadata(&fifo.0).unwrap().file_type().is_fifo());
+        // An existing FIFO is reused
+        FifoOutput::new(&fifo.0).expect("reuses the FIFO");
+    }
+
+    #[test]
+    fn refuses_a_regular_file() {
+        let fifo = TempFifo::new("regular");
+        fs::write(&fifo.0, "").unwrap();
+        assert!(FifoOutput::new(&fifo.0).is_err());
+    }
+
+    #[test]
+    fn drops_results_without_a_reader() {
+        let fifo = TempFifo::new("no_reader");
+        let mut output = FifoOutput::new(&fifo.0).unwrap();
+        output.write(&results(3, 0), 1);
+        assert_eq!(output.dropped(), 3);
+    }
+
+    #[test]
+    fn writes_one_line_per_result() {
+        let fifo = TempFifo::new("lines");
+        let mut output = FifoOutput::new(&fifo.0).unwrap();
+        let reader = reader(&fifo.0);
+        output.write(&results(3, 0), 42);
+        assert_eq!(output.dropped(), 0);
+
+        let lines = read_lines(reader);
+        assert_eq!(lines.len(), 3);
+        for line in &lines {
+            assert_eq!(line["at"], 42);
+            assert!(line["url"]
+                .as_str()
+                .unwrap()
+                .starts_with("https://example.com/"));
+        }
+    }
+
+    #[test]
+    fn long_lines_are_never_torn() {
+        let fifo = TempFifo::new("full");
+        let mut output = FifoOutput::new(&fifo.0).unwrap();
+        let reader = reader(&fifo.0);
+        // A reader that only starts once the pipe has filled up
+        let reading = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            read_lines(reader)
+        });
+        // Well past the default 64 KiB, each line longer than PIPE_BUF
+        let results = results(40, 3 * libc::PIPE_BUF);
+        output.write(&results, 1);
+        let dropped = output.dropped();
+
+        let lines = reading.join().expect("every line is whole JSON");
+        assert!(!lines.is_empty());
+        assert_eq!(lines.len() as u64 + dropped, results.len() as u64);
+    }
+}
@@ -0,0 +1,2118 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use artisan_middleware::timestamp::current_timestamp;
+use chrono::Utc;
+use encoding_rs::{Encoding, UTF_8};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use ipnet::IpNet;
+use openssl::pkey::PKey;
+use reqwest::header::{
+    HeaderMap, HeaderName, ACCEPT_ENCODING, CACHE_CONTROL, CONNECTION, CONTENT_ENCODING,
+    CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, EXPIRES, HOST, LAST_MODIFIED, RANGE,
+    RETRY_AFTER, USER_AGENT,
+};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Identity, Method, RequestBuilder, Url, Version};
+use serde::{Deserialize, Serialize, Serializer};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+use crate::config::{
+    redact_url, AppSpecificConfig, DohConfig, GraphqlConfig, Http3Mode, NonceConfig, SiteConfig,
+};
+use crate::diagnose::{self, Diagnosis};
+use crate::doh::{self, DnssecStatus};
+use crate::dual_stack::{self, FamilyPath};
+use crate::pacing::DomainPacer;
+use crate::prefetch::{self, ResolvedHosts};
+use crate::retry_after::{parse_retry_after, retry_delay};
+use crate::sla::SlaStatus;
+use crate::slo::SloStatus;
+use crate::tls::{self, TlsFinding};
+use crate::{connectivity, hooks, http3, signing, snippet, unix_socket};
+
+// How long a single request may take before the site is considered DOWN
+pub const REQUEST_TIMEOUT_SECONDS: u64 = 30;
+
+// Same limit as reqwest's default redirect policy
+const MAX_REDIRECTS: usize = 10;
+
+// Stands in for a watched header the response didn't carry
+pub const MISSING_HEADER: &str = "(none)";
+
+pub async fn run_health_checks(
+    sites: &[SiteConfig],
+    app: &AppSpecificConfig,
+    pacer: &mut DomainPacer,
+) -> HashMap<String, HealthCheckResult> {
+    let mut results = HashMap::new();
+    let prefetched = if app.dns_prefetch {
+        prefetch::prefetch(sites, app.doh.as_ref(), app.dns_prefetch_concurrency).await
+    } else {
+        ResolvedHosts::new()
+    };
+
+    // Identical entries (e.g. a url both in urls and in sites) are checked once and
+    // share the result
+    let mut first_with_key: HashMap<String, usize> = HashMap::new();
+    let shared_with: Vec<Option<usize>> = sites
+        .iter()
+        .enumerate()
+        .map(
+            |(index, site)| match first_with_key.entry(site.check_key()) {
+                Entry::Occupied(first) => Some(*first.get()),
+                Entry::Vacant(entry) => {
+                    entry.insert(index);
+                    None
+                }
+            },
+        )
+        .collect();
+    let duplicates = shared_with.iter().filter(|shared| shared.is_some()).count();
+    if duplicates > 0 {
+        log!(
+            LogLevel::Debug,
+            "{} duplicate site entries share another entry's check",
+            duplicates
+        );
+    }
+
+    // One check per site, or per source address for sites listing several
+    let checks: Vec<(usize, Option<IpAddr>)> = sites
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| shared_with[*index].is_none())
+        .flat_map(|(index, site)| {
+            if site.local_addresses.is_empty() {
+                vec![(index, site.local_address.or(app.local_address))]
+            } else {
+                site.local_addresses
+                    .iter()
+                    .map(|address| (index, Some(*address)))
+                    .collect()
+            }
+        })
+        .collect();
+
+    let concurrency = app
+        .max_concurrent_checks
+        .resolve(app.concurrency_multiplier);
+    let outcomes = if concurrency > 1 {
+        run_concurrently(sites, app, prefetched, pacer, &checks, concurrency).await
+    } else {
+        let mut outcomes = Vec::with_capacity(checks.len());
+        for (index, local_address) in &checks {
+            let site = &sites[*index];
+            pacer.wait_for(&site.url).await;
+            outcomes.push(check_site(site, app, &prefetched, *local_address).await);
+            tokio::time::sleep(Duration::from_nanos(500)).await;
+        }
+        outcomes
+    };
+
+    let mut per_site: Vec<Vec<(Option<IpAddr>, HealthCheckResult)>> =
+        sites.iter().map(|_| Vec::new()).collect();
+    for ((index, local_address), result) in checks.iter().zip(outcomes) {
+        per_site[*index].push((*local_address, result));
+    }
+    let mut site_results: Vec<HealthCheckResult> = Vec::with_capacity(sites.len());
+    for ((site, site_outcomes), shared) in sites.iter().zip(per_site).zip(&shared_with) {
+        let result = match shared {
+            Some(first) => site_results[*first].clone(),
+            None if site.local_addresses.is_empty() => site_outcomes
+                .into_iter()
+                .next()
+                .map(|(_, result)| result)
+                .unwrap_or_else(|| not_completed("the check did not run")),
+            None => combine_sources(site_outcomes),
+        };
+        results.insert(site.resolved_url(), result.clone());
+        site_results.push(result);
+    }
+
+    results
+}
+
+// Up to `concurrency` checks in flight, started in priority order. Pacing still
+// spaces out the starts of requests to one domain, holding back later starts too.
+async fn run_concurrently(
+    sites: &[SiteConfig],
+    app: &AppSpecificConfig,
+    prefetched: ResolvedHosts,
+    pacer: &mut DomainPacer,
+    checks: &[(usize, Option<IpAddr>)],
+    concurrency: usize,
+) -> Vec<HealthCheckResult> {
+    let sites = Arc::new(sites.to_vec());
+    let app = Arc::new(app.clone());
+    let prefetched = Arc::new(prefetched);
+
+    let mut outcomes: Vec<Option<HealthCheckResult>> = vec![None; checks.len()];
+    let mut pending = JoinSet::new();
+    let mut queued = checks.iter().copied().enumerate();
+    loop {
+        while pending.len() < concurrency {
+            let (position, (index, local_address)) = match queued.next() {
+                Some(check) => check,
+                None => break,
+            };
+            pacer.wait_for(&sites[index].url).await;
+            let (sites, app, prefetched) = (sites.clone(), app.clone(), prefetched.clone());
+            // The check runs in a task of its own, so a panic still ends up as a result
+            // for its position instead of leaving the site out
+            pending.spawn(async move {
+                let check = tokio::spawn(async move {
+                    check_site(&sites[index], &app, &prefetched, local_address).await
+                });
+                let result = match check.await {
+                    Ok(result) => result,
+                    Err(e) => {
+                        log!(LogLevel::Error, "Health check task failed: {}", e);
+                        not_completed(&format!("the check task failed: {}", e))
+                    }
+                };
+                (position, result)
+            });
+        }
+
+        match pending.join_next().await {
+            Some(Ok((position, result))) => outcomes[position] = Some(result),
+            Some(Err(e)) => log!(LogLevel::Error, "Health check task failed: {}", e),
+            None => break,
+        }
+    }
+    outcomes
+        .into_iter()
+        .map(|outcome| outcome.unwrap_or_else(|| not_completed("the check did not complete")))
+        .collect()
+}
+
+// A site whose check never produced a result is DOWN, not silently healthy
+fn not_completed(reason: &str) -> HealthCheckResult {
+    HealthCheckResult::down(CheckError::Client(reason.to_string()))
+}
+
+// One check with the site's limits, diagnosis and inversion applied
+async fn check_site(
+    site: &SiteConfig,
+    app: &AppSpecificConfig,
+    prefetched: &ResolvedHosts,
+    local_address: Option<IpAddr>,
+) -> HealthCheckResult {
+    // The loopback fast path answers right away or not at all, so it skips the extras
+    let fast_path = site.fast_path();
+    let mut result = check_website_health(site, app, prefetched, local_address).await;
+    if let Some(max_seconds) = app.retry_after_max_seconds.filter(|_| !fast_path) {
+        result =
+            retry_rate_limited(site, app, prefetched, local_address, result, max_seconds).await;
+    }
+    result.local_address = local_address.map(|address| address.to_string());
+    if let (Some(_), Some(doh), Some(host), false) = (
+        site.dnssec,
+        &app.doh,
+        prefetch::host_to_resolve(&site.url),
+        fast_path,
+    ) {
+        match doh::dnssec_status(&doh.endpoint, &host).await {
+            Ok(status) => result.dnssec = Some(status),
+            Err(e) => result
+                .warnings
+                .push(format!("DNSSEC status unknown: {}", e)),
+        }
+    }
+    if site.check_ipv6 && !site.expect_down {
+        result.ip_families =
+            dual_stack::check_families(&site.request_url(), site.host_header.as_deref()).await;
+    }
+    apply_latency_limits(site, &mut result);
+    apply_phase_budgets(site, &mut result);
+    if app.diagnose_down && result.status.is_down() && !site.expect_down && !fast_path {
+        result.diagnosis = diagnose::diagnose(&site.resolved_url()).await;
+    }
+    if site.expect_down {
+        invert_result(&mut result);
+    }
+    result
+}
+
+// A 429 or 503 with Retry-After gets one more try once the server says it's ready.
+// The wait is recorded on the result, whichever way the retry went.
+async fn retry_rate_limited(
+    site: &SiteConfig,
+    app: &AppSpecificConfig,
+    prefetched: &ResolvedHosts,
+    local_address: Option<IpAddr>,
+    result: HealthCheckResult,
+    max_seconds: u64,
+) -> HealthCheckResult {
+    let requested = match (result.status_code, result.retry_after_seconds) {
+        (Some(429) | Some(503), Some(requested)) => requested,
+        _ => return result,
+    };
+
+    let delay = retry_delay(requested, max_seconds, app.retry_after_jitter_ms);
+    log!(
+        LogLevel::Info,
+        "{} answered {} with Retry-After {}s, retrying in {} ms",
+        site.url,
+        result.status_code.unwrap_or_default(),
+        requested,
+        delay.as_millis()
+    );
+    tokio::time::sleep(delay).await;
+
+    let mut retried = check_website_health(site, app, prefetched, local_address).await;
+    retried.retry_after_seconds = Some(requested);
+    retried.retry_waited_ms = Some(delay.as_millis());
+    retried
+}
+
+// The worst path's result stands for a site checked from several source addresses,
+// so one broken uplink takes it DOWN, with every path listed under sources
+fn combine_sources(outcomes: Vec<(Option<IpAddr>, HealthCheckResult)>) -> HealthCheckResult {
+    let mut sources = Vec::new();
+    let mut worst: Option<HealthCheckResult> = None;
+
+    for (address, result) in outcomes {
+        sources.push(SourceResult {
+            local_address: address
+                .map(|address| address.to_string())
+                .unwrap_or_default(),
+            status: result.status,
+            response_time_ms: result.response_time_ms,
+            error: result.error.clone(),
+        });
+        if worst
+            .as_ref()
+            .is_none_or(|worst| result.status > worst.status)
+        {
+            worst = Some(result);
+        }
+    }
+
+    let mut result = worst.unwrap_or_else(|| not_completed("the check did not run"));
+    result.sources = sources;
+    result
+}
+
+// A measured phase of a check, for phase budgets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Connect,
+    Tls,
+    FirstByte,
+    Total,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Connect => write!(f, "TCP connect"),
+            Phase::Tls => write!(f, "TLS handshake"),
+            Phase::FirstByte => write!(f, "time to first byte"),
+            Phase::Total => write!(f, "total"),
+        }
+    }
+}
+
+// DOWN when a phase of an otherwise healthy check ran over its budget, the earliest
+// phase over budget is the one named
+fn apply_phase_budgets(site: &SiteConfig, result: &mut HealthCheckResult) {
+    if result.status.is_down() {
+        return;
+    }
+
+    let total_ms = result
+        .response_time_ms
+        .map(|response_time| response_time + result.body_time_ms.unwrap_or_default());
+    let phases = [
+        (
+            Phase::Connect,
+            result.connect_time_ms,
+            site.connect_budget_ms,
+        ),
+        (Phase::Tls, result.tls_time_ms, site.tls_budget_ms),
+        (
+            Phase::FirstByte,
+            result.response_time_ms,
+            site.ttfb_budget_ms,
+        ),
+        (Phase::Total, total_ms, site.total_budget_ms),
+    ];
+    let over_budget = phases.iter().find_map(|(phase, took_ms, budget_ms)| {
+        let (took_ms, budget_ms) = (took_ms.as_ref()?, budget_ms.as_ref()?);
+        (*took_ms > *budget_ms as u128).then_some((*phase, *took_ms, *budget_ms))
+    });
+    if let Some((phase, took_ms, budget_ms)) = over_budget {
+        result.failed_phase = Some(phase);
+        result.fail(
+            CheckStatus::Down,
+            CheckError::OverBudget {
+                phase,
+                took_ms,
+                budget_ms,
+            },
+        );
+    }
+}
+
+// Downgrade a successful check that took longer than the site allows
+fn apply_latency_limits(site: &SiteConfig, result: &mut HealthCheckResult) {
+    let response_time_ms = match result.response_time_ms {
+        Some(response_time) if result.status == CheckStatus::Up => response_time,
+        _ => return,
+    };
+
+    if let Some(max_ms) = site.max_response_time_ms {
+        if response_time_ms > max_ms as u128 {
+            result.fail(
+                CheckStatus::Down,
+                CheckError::ExceededMaxResponseTime {
+                    response_time_ms,
+                    max_ms,
+                },
+            );
+            return;
+        }
+    }
+
+    if let Some(degraded_ms) = site.degraded_response_time_ms {
+        if response_time_ms > degraded_ms as u128 {
+            result.fail(
+                CheckStatus::Degraded,
+                CheckError::SlowResponse {
+                    response_time_ms,
+                    degraded_ms,
+                },
+            );
+            return;
+        }
+    }
+
+    if let Some(min_ms) = site.min_expected_response_ms {
+        if response_time_ms < min_ms as u128 {
+            result.fail(
+                CheckStatus::Degraded,
+                CheckError::SuspiciouslyFast {
+                    response_time_ms,
+                    min_ms,
+                },
+            );
+        }
+    }
+}
+
+// For sites that should be unreachable: failing is healthy, answering is an outage.
+// The original failure stays on the result as the reason it's UP.
+fn invert_result(result: &mut HealthCheckResult) {
+    result.expect_down = true;
+    if result.status.is_down() {
+        result.status = CheckStatus::Up;
+    } else {
+        result.fail(
+            CheckStatus::Down,
+            CheckError::UnexpectedlyReachable {
+                status_code: result.status_code,
+            },
+        );
+    }
+}
+
+// Serialized as "UP" / "DEGRADED" / "DOWN", same as the strings it replaced
+// Ordered by severity, UP < DEGRADED < DOWN. A result nobody filled in defaults to
+// DOWN, so it can never pass for a healthy site.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CheckStatus {
+    Up,
+    Degraded,
+    #[default]
+    Down,
+}
+
+impl CheckStatus {
+    pub fn is_down(self) -> bool {
+        self == CheckStatus::Down
+    }
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckStatus::Up => write!(f, "UP"),
+            CheckStatus::Degraded => write!(f, "DEGRADED"),
+            CheckStatus::Down => write!(f, "DOWN"),
+        }
+    }
+}
+
+// Why a check wasn't UP. Serialized as its message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckError {
+    // DNS-over-HTTPS resolution failed in strict mode, or resolution with a
+    // dns_timeout_ms failed
+    Resolve(String),
+    // Resolving the host took longer than dns_timeout_ms
+    DnsTimeout {
+        host: String,
+        timeout_ms: u64,
+    },
+    // The request couldn't be built
+    Client(String),
+    // No response: connection refused, TLS failure, timeout and so on
+    Connect(String),
+    IncompleteBody {
+        received: u64,
+        expected: Option<u64>,
+        cause: Option<String>,
+    },
+    ExceededMaxResponseTime {
+        response_time_ms: u128,
+        max_ms: u64,
+    },
+    SlowResponse {
+        response_time_ms: u128,
+        degraded_ms: u64,
+    },
+    // The response didn't honour the configured Range header
+    Range(String),
+    // Caching headers missing or not as expected
+    Caching(String),
+    // The site requires HTTP/3 and the QUIC request failed
+    Http3(String),
+    // A step (warmup or the timed check) answered with a status its method doesn't accept
+    UnexpectedStatus {
+        step: String,
+        method: String,
+        status_code: u16,
+        expected: Vec<u16>,
+    },
+    // Faster than min_expected_response_ms, likely a canned or cached response
+    SuspiciouslyFast {
+        response_time_ms: u128,
+        min_ms: u64,
+    },
+    // One phase of the request took longer than its budget
+    OverBudget {
+        phase: Phase,
+        took_ms: u128,
+        budget_ms: u64,
+    },
+    // The p95 response time over the recent window is above a limit
+    SlowPercentile {
+        p95_ms: u128,
+        limit_ms: u64,
+        samples: usize,
+    },
+    // An expect_down site answered
+    UnexpectedlyReachable {
+        status_code: Option<u16>,
+    },
+    // Content-Length: 0 or no body at all, on a site with allow_empty_body off
+    EmptyBody {
+        content_length: Option<u64>,
+    },
+    // The body read was outside min_body_bytes / max_body_bytes
+    BodyTooSmall {
+        received: u64,
+        min_bytes: u64,
+    },
+    BodyTooLarge {
+        received: u64,
+        max_bytes: u64,
+    },
+    // The GraphQL response carried errors or lacked the expected data
+    Graphql(String),
+    // The validator_command rejected the body, failed to run or timed out
+    Validator(String),
+    // The body isn't JSON or doesn't match the site's json_schema
+    JsonSchema(String),
+    // A nonce was the same on two requests in a row, or missing
+    Nonce(String),
+    // client_cert / client_key could not be read or used
+    ClientCert(String),
+    // The body held one of the site's forbidden_body strings or patterns
+    ForbiddenContent(String),
+    // require_https_final is set but the redirects ended on another scheme
+    InsecureFinalUrl {
+        scheme: String,
+        url: String,
+    },
+    // The host resolved outside the site's allowed_ip_ranges
+    OutsideIpRanges {
+        address: String,
+        allowed: Vec<String>,
+    },
+}
+
+// Every name category() can return
+pub const ERROR_CATEGORIES: &[&str] = &[
+    "dns",
+    "dns_timeout",
+    "client",
+    "connect",
+    "incomplete_body",
+    "slow",
+    "too_fast",
+    "range",
+    "caching",
+    "http3",
+    "status",
+    "reachable",
+    "empty_body",
+    "body_size",
+    "forbidden_content",
+    "client_cert",
+    "nonce",
+    "json_schema",
+    "graphql",
+    "validator",
+    "insecure_final_url",
+    "ip_range",
+];
+
+impl CheckError {
+    // Coarse failure mode, stable across the details of individual errors
+    pub fn category(&self) -> &'static str {
+        match self {
+            CheckError::Resolve(_) => "dns",
+            CheckError::DnsTimeout { .. } => "dns_timeout",
+            CheckError::Client(_) => "client",
+            CheckError::Connect(_) => "connect",
+            CheckError::IncompleteBody { .. } => "incomplete_body",
+            CheckError::ExceededMaxResponseTime { .. }
+            | CheckError::SlowResponse { .. }
+            | CheckError::SlowPercentile { .. }
+            | CheckError::OverBudget { .. } => "slow",
+            CheckError::SuspiciouslyFast { .. } => "too_fast",
+            CheckError::Range(_) => "range",
+            CheckError::Caching(_) => "caching",
+            CheckError::Http3(_) => "http3",
+            CheckError::UnexpectedStatus { .. } => "status",
+            CheckError::UnexpectedlyReachable { .. } => "reachable",
+            CheckError::EmptyBody { .. } => "empty_body",
+            CheckError::BodyTooSmall { .. } | CheckError::BodyTooLarge { .. } => "body_size",
+            CheckError::ForbiddenContent(_) => "forbidden_content",
+            CheckError::ClientCert(_) => "client_cert",
+            CheckError::Nonce(_) => "nonce",
+            CheckError::JsonSchema(_) => "json_schema",
+            CheckError::Graphql(_) => "graphql",
+            CheckError::Validator(_) => "validator",
+            CheckError::InsecureFinalUrl { .. } => "insecure_final_url",
+            CheckError::OutsideIpRanges { .. } => "ip_range",
+        }
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::Resolve(e) | CheckError::Client(e) | CheckError::Connect(e) => {
+                write!(f, "{}", e)
+            }
+            CheckError::DnsTimeout { host, timeout_ms } => {
+                write!(f, "resolving {} took longer than {} ms", host, timeout_ms)
+            }
+            CheckError::IncompleteBody {
+                received,
+                expected: Some(expected),
+                ..
+            } => write!(
+                f,
+                "incomplete response body (got {} of {} bytes)",
+                received, expected
+            ),
+            CheckError::IncompleteBody {
+                received,
+                expected: None,
+                cause: Some(cause),
+            } => write!(
+                f,
+                "incomplete response body (got {} bytes): {}",
+                received, cause
+            ),
+            CheckError::IncompleteBody { received, .. } => {
+                write!(f, "incomplete response body (got {} bytes)", received)
+            }
+            CheckError::ExceededMaxResponseTime {
+                response_time_ms,
+                max_ms,
+            } => write!(
+                f,
+                "exceeded max response time ({} ms > {} ms)",
+                response_time_ms, max_ms
+            ),
+            CheckError::SlowResponse {
+                response_time_ms,
+                degraded_ms,
+            } => write!(
+                f,
+                "slow response ({} ms > {} ms)",
+                response_time_ms, degraded_ms
+            ),
+            CheckError::OverBudget {
+                phase,
+                took_ms,
+                budget_ms,
+            } => write!(
+                f,
+                "{} took {} ms, over its {} ms budget",
+                phase, took_ms, budget_ms
+            ),
+            CheckError::SuspiciouslyFast {
+                response_time_ms,
+                min_ms,
+            } => write!(
+                f,
+                "suspiciously fast response ({} ms < {} ms), possibly a canned or cached error",
+                response_time_ms, min_ms
+            ),
+            CheckError::Range(e)
+            | CheckError::Caching(e)
+            | CheckError::Http3(e)
+            | CheckError::Nonce(e)
+            | CheckError::JsonSchema(e)
+            | CheckError::Graphql(e)
+            | CheckError::Validator(e) => write!(f, "{}", e),
+            CheckError::SlowPercentile {
+                p95_ms,
+                limit_ms,
+                samples,
+            } => write!(
+                f,
+                "p95 response time {} ms over the last {} checks is above {} ms",
+                p95_ms, samples, limit_ms
+            ),
+            CheckError::UnexpectedlyReachable {
+                status_code: Some(status_code),
+            } => write!(
+                f,
+                "expected to be unreachable but answered with {}",
+                status_code
+            ),
+            CheckError::UnexpectedlyReachable { status_code: None } => {
+                write!(f, "expected to be unreachable but answered")
+            }
+            CheckError::EmptyBody {
+                content_length: Some(0),
+            } => write!(f, "empty response (Content-Length: 0)"),
+            CheckError::EmptyBody { .. } => write!(f, "empty response body"),
+            CheckError::BodyTooSmall {
+                received,
+                min_bytes,
+            } => write!(
+                f,
+                "response too small (got {} bytes, expected >= {})",
+                received, min_bytes
+            ),
+            CheckError::BodyTooLarge {
+                received,
+                max_bytes,
+            } => write!(
+                f,
+                "response too large (got {} bytes, expected <= {})",
+                received, max_bytes
+            ),
+            CheckError::ClientCert(e) => write!(f, "client certificate unusable: {}", e),
+            CheckError::ForbiddenContent(token) => {
+                write!(f, "forbidden content present: {}", token)
+            }
+            CheckError::InsecureFinalUrl { scheme, url } => {
+                write!(f, "redirects ended on {} instead of https: {}", scheme, url)
+            }
+            CheckError::OutsideIpRanges { address, allowed } => write!(
+                f,
+                "resolved to {}, outside the allowed ranges {}, possibly a hijacked or misconfigured DNS record",
+                address,
+                allowed.join(", ")
+            ),
+            CheckError::UnexpectedStatus {
+                step,
+                method,
+                status_code,
+                expected,
+            } => write!(
+                f,
+                "{} {} returned {}, expected {}",
+                step,
+                method,
+                status_code,
+                expected
+                    .iter()
+                    .map(|status| status.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            ),
+        }
+    }
+}
+
+impl Serialize for CheckError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HealthCheckResult {
+    pub status: CheckStatus,
+    pub dns_time_ms: Option<u128>,
+    // Connect and TLS handshake of a separate connection, see connect_budget_ms
+    pub connect_time_ms: Option<u128>,
+    pub tls_time_ms: Option<u128>,
+    pub response_time_ms: Option<u128>,
+    pub body_time_ms: Option<u128>,
+    pub status_code: Option<u16>,
+    pub content_length: Option<u64>,
+    pub content_range: Option<String>,
+    pub body_bytes: Option<u64>,
+    // Size of the body once its Content-Encoding is undone, compressed responses only
+    pub decoded_bytes: Option<u64>,
+    pub canary_value: Option<String>,
+    // Validation status of the host's records, for sites with dnssec set
+    pub dnssec: Option<DnssecStatus>,
+    // Time from sending a GraphQL query to having read the whole answer
+    pub graphql_time_ms: Option<u128>,
+    // Set when the site hasn't answered since this time, longer than stale_after_seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stale_since: Option<u64>,
+    // The nonce of both requests, in order, MISSING_HEADER where there was none
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub nonce_values: Vec<String>,
+    // Value of the watch_header, MISSING_HEADER when the response had none
+    pub watched_header: Option<String>,
+    // Negotiated protocol and cipher suite, HTTPS sites only
+    pub tls_version: Option<String>,
+    pub tls_cipher: Option<String>,
+    // Problems with the served certificates, when tls_audit is on
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tls_findings: Vec<TlsFinding>,
+    // The Host the request was sent as and the address it actually reached
+    pub effective_host: Option<String>,
+    pub remote_addr: Option<String>,
+    // What the url's host resolved to, when it was resolved ahead of the request
+    pub resolved_address: Option<String>,
+    // The phase that ran over its budget
+    pub failed_phase: Option<Phase>,
+    // Caching headers of the response, for sites with caching checks
+    pub cache_headers: Option<CacheHeaders>,
+    // Rolling uptime against the site's sla_target
+    pub sla: Option<SlaStatus>,
+    // Error budget against the site's latency_slo
+    pub slo: Option<SloStatus>,
+    // Redirects followed on the way to the final response, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<RedirectHop>,
+    // Whether the prefetch_url answered successfully before the check
+    pub prefetch_ok: Option<bool>,
+    // Inferred: the timed request went over a connection opened by the warmup
+    pub connection_reused: Option<bool>,
+    // The site is configured with expect_down, so UP means it was unreachable
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub expect_down: bool,
+    // Where a DOWN site broke, when diagnose_down is on
+    pub diagnosis: Option<Diagnosis>,
+    // Source address the check was sent from, when bound to one
+    pub local_address: Option<String>,
+    // Every path checked when the site lists local_addresses
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceResult>,
+    // The IPv4 and IPv6 paths, for sites with check_ipv6 on
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ip_families: Vec<FamilyPath>,
+    // Retry-After the server sent with a 429 or 503, and how long was waited before
+    // the retry this result comes from
+    pub retry_after_seconds: Option<u64>,
+    pub retry_waited_ms: Option<u128>,
+    // Whether the HTTP/3 request succeeded, for sites with http3 on
+    pub http3: Option<bool>,
+    // Character encoding the body was decoded with
+    pub body_encoding: Option<String>,
+    // Start of the body of a DOWN response, when error_body_snippet_bytes is set
+    pub body_snippet: Option<String>,
+    // Problems that didn't fail the check, e.g. undecodable bytes in the body
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    pub error: Option<CheckError>,
+}
+
+// The outcome of checking a site from one source address
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceResult {
+    pub local_address: String,
+    pub status: CheckStatus,
+    pub response_time_ms: Option<u128>,
+    pub error: Option<CheckError>,
+}
+
+impl HealthCheckResult {
+    // A check that failed before any response was received
+    pub fn down(error: CheckError) -> Self {
+        HealthCheckResult {
+            status: CheckStatus::Down,
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    pub fn fail(&mut self, status: CheckStatus, error: CheckError) {
+        self.status = status;
+        self.error = Some(error);
+    }
+
+    // Decompressed bytes per byte downloaded, only known for compressed responses
+    pub fn compression_ratio(&self) -> Option<f64> {
+        match (self.decoded_bytes, self.body_bytes) {
+            (Some(decoded_bytes), Some(body_bytes)) if body_bytes > 0 => {
+                Some(decoded_bytes as f64 / body_bytes as f64)
+            }
+            _ => None,
+        }
+    }
+}
+
+// Pull a value out of the body, the whole body when no path is set
+fn extract_value(text: &str, json_path: Option<&str>) -> Option<String> {
+    let path = match json_path {
+        Some(path) => path,
+        None => return Some(text.trim().to_string()),
+    };
+
+    let json: serde_json::Value = serde_json::from_str(text).ok()?;
+    let pointer = format!("/{}", path.replace('.', "/"));
+    match json.pointer(&pointer)? {
+        serde_json::Value::String(value) => Some(value.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+pub async fn check_website_health(
+    site: &SiteConfig,
+    app: &AppSpecificConfig,
+    prefetched: &ResolvedHosts,
+    local_address: Option<IpAddr>,
+) -> HealthCheckResult {
+    if site.url.starts_with(unix_socket::UNIX_SCHEME) {
+        return check_unix_site(site, app).await;
+    }
+
+    let url = site.url.as_str();
+    let fast_path = site.fast_path();
+    let redirects: Arc<Mutex<RedirectLog>> = Arc::default();
+    let mut builder = Client::builder()
+        .timeout(site.request_timeout())
+        .local_address(local_address)
+        .cookie_store(site.prefetch_url.is_some())
+        .redirect(redirect_policy(redirects.clone()));
+    if site.client_cert.is_some() || site.client_key.is_some() {
+        match client_identity(site) {
+            Ok(identity) => builder = builder.identity(identity),
+            Err(e) => return HealthCheckResult::down(CheckError::ClientCert(e)),
+        }
+    }
+
+    // With DoH or a prefetched address the resolution is timed on its own, otherwise
+    // it's folded into the request. Port 0 keeps the url's own port.
+    let mut doh_time: Option<u128> = None;
+    let mut resolved_address: Option<IpAddr> = None;
+    let mut request_url = site.request_url();
+    if let Some(sni) = &site.sni {
+        match sni_target(&request_url, sni, prefetched, app, site.dns_timeout_ms).await {
+            Ok((sni_url, address)) => {
+                request_url = sni_url;
+                resolved_address = Some(address);
+                builder = builder.resolve(sni, SocketAddr::new(address, 0));
+            }
+            Err(e) => return HealthCheckResult::down(e),
+        }
+    } else if fast_path {
+        // Localhost names skip the resolver, other hosts are left to the request
+        if let Some(host) =
+            prefetch::host_to_resolve(url).filter(|host| prefetch::is_localhost_name(host))
+        {
+            let addresses: Vec<SocketAddr> = prefetch::LOOPBACK_ADDRESSES
+                .iter()
+                .map(|address| SocketAddr::new(*address, 0))
+                .collect();
+            builder = builder.resolve_to_addrs(&host, &addresses);
+        }
+    } else if let Some(host) = prefetch::host_to_resolve(url) {
+        if let Some(resolved) = prefetched.get(&host) {
+            doh_time = Some(resolved.time_ms);
+            resolved_address = Some(resolved.address);
+            builder = builder.resolve(&host, SocketAddr::new(resolved.address, 0));
+        } else if let Some(timeout_ms) = site.dns_timeout_ms {
+            let resolve_start = Instant::now();
+            match resolve_within(&host, app.doh.as_ref(), timeout_ms).await {
+                Ok(address) => {
+                    doh_time = Some(resolve_start.elapsed().as_millis());
+                    resolved_address = Some(address);
+                    builder = builder.resolve(&host, SocketAddr::new(address, 0));
+                }
+                Err(e) => return HealthCheckResult::down(e),
+            }
+        } else if let Some(doh) = &app.doh {
+            let resolve_start = Instant::now();
+            match doh::resolve(&doh.endpoint, &host).await {
+                Ok(address) => {
+                    doh_time = Some(resolve_start.elapsed().as_millis());
+                    resolved_address = Some(address);
+                    builder = builder.resolve(&host, SocketAddr::new(address, 0));
+                }
+                Err(e) if doh.strict => return HealthCheckResult::down(CheckError::Resolve(e)),
+                Err(e) => log!(LogLevel::Warn, "{}, falling back to system resolution", e),
+            }
+        } else if !site.allowed_ip_ranges.is_empty() {
+            // Pinned, so the address checked against the ranges is the one connected to
+            let resolve_start = Instant::now();
+            match resolve_within(&host, None, REQUEST_TIMEOUT_SECONDS * 1000).await {
+                Ok(address) => {
+                    doh_time = Some(resolve_start.elapsed().as_millis());
+                    resolved_address = Some(address);
+                    builder = builder.resolve(&host, SocketAddr::new(address, 0));
+                }
+                Err(e) => return HealthCheckResult::down(e),
+            }
+        }
+    }
+
+    let client = match builder.build() {
+        Ok(client) => client,
+        Err(e) => return HealthCheckResult::down(CheckError::Client(e.to_string())),
+    };
+
+    let method = match (&site.graphql, parse_method(&site.method)) {
+        (Some(_), _) => Method::POST,
+        (None, Ok(method)) => method,
+        (None, Err(e)) => return HealthCheckResult::down(e),
+    };
+    let graphql_payload = site.graphql.as_ref().map(|graphql| {
+        serde_json::json!({ "query": graphql.query, "variables": graphql.variables }).to_string()
+    });
+    let signed_url = match &site.signing {
+        Some(_) => match signing::signed_url(&request_url) {
+            Ok(signed_url) => Some(signed_url),
+            Err(e) => return HealthCheckResult::down(CheckError::Client(e)),
+        },
+        None => None,
+    };
+    let build_request = |method: Method| {
+        let mut request = client
+            .request(method.clone(), &request_url)
+            .header(USER_AGENT, "HealthChecker/1.0")
+            // reqwest is built without decompression, body_bytes stays the download size
+            .header(ACCEPT_ENCODING, "gzip, deflate");
+        let body = graphql_payload.as_ref().filter(|_| method == Method::POST);
+        if let Some(payload) = body {
+            request = request
+                .header(CONTENT_TYPE, "application/json")
+                .body(payload.clone());
+        }
+        // Signed per request, so the warmup and nonce requests get their own timestamp
+        if let (Some(signing), Some(signed_url)) = (&site.signing, &signed_url) {
+            let headers = signing::signature_headers(
+                signing,
+                &method,
+                signed_url,
+                site.host_header.as_deref(),
+                body.map(String::as_bytes).unwrap_or_default(),
+                current_timestamp(),
+            );
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+        }
+        if let Some(host_header) = &site.host_header {
+            request = request.header(HOST, host_header);
+        }
+        match &site.range {
+            Some(range) => request.header(RANGE, range),
+            None => request,
+        }
+    };
+
+    // A warmup that errors out is only logged, one answering with a status the
+    // site doesn't accept fails the check once the timed request is done
+    let mut warmup_error: Option<CheckError> = None;
+    // The client is new each check, so only a prefetch or warmup can leave a pooled
+    // connection behind
+    let mut connection_reused = false;
+    let mut prefetch_ok: Option<bool> = None;
+    if let Some(prefetch_url) = &site.prefetch_url {
+        let prefetch = client
+            .get(prefetch_url)
+            .header(USER_AGENT, "HealthChecker/1.0")
+            .send()
+            .await;
+        match prefetch {
+            Ok(response) => {
+                let success = response.status().is_success();
+                let same_host = response.url().host_str()
+                    == Url::parse(&request_url)
+                        .ok()
+                        .as_ref()
+                        .and_then(|check_url| check_url.host_str());
+                let keep_alive = keeps_alive(&response);
+                let drained = response.bytes().await.is_ok();
+                connection_reused = same_host && keep_alive && drained;
+                if !success {
+                    log!(
+                        LogLevel::Debug,
+                        "Prefetch of {} for {} was not successful",
+                        redact_url(prefetch_url),
+                        url
+                    );
+                }
+                prefetch_ok = Some(success && drained);
+            }
+            Err(e) => {
+                log!(
+                    LogLevel::Debug,
+                    "Prefetch of {} for {} failed: {}",
+                    redact_url(prefetch_url),
+                    url,
+                    e
+                );
+                prefetch_ok = Some(false);
+            }
+        }
+    }
+    if site.warmup {
+        let warmup_method = match parse_method(&site.warmup_method) {
+            Ok(method) => method,
+            Err(e) => return HealthCheckResult::down(e),
+        };
+        match build_request(warmup_method.clone()).send().await {
+            Ok(response) => {
+                let status_code = response.status().as_u16();
+                let keep_alive = keeps_alive(&response);
+                // A connection dropped with its body unread can't go back to the pool
+                let drained = if site.read_body {
+                    response.bytes().await.is_ok()
+                } else {
+                    warmup_method == Method::HEAD
+                };
+                connection_reused = keep_alive && drained;
+                warmup_error = check_status(site, "warmup", &warmup_method, status_code);
+            }
+            Err(e) => log!(LogLevel::Debug, "Warmup request to {} failed: {}", url, e),
+        }
+    }
+
+    let start_time = Instant::now();
+    let dns_start = Instant::now();
+
+    // Only the hops of the timed request, not those of a prefetch or warmup
+    if let Ok(mut log) = redirects.lock() {
+        *log = RedirectLog {
+            mark: Some(start_time),
+            hops: Vec::new(),
+        };
+    }
+    let take_redirects = || {
+        redirects
+            .lock()
+            .map(|mut log| std::mem::take(&mut log.hops))
+            .unwrap_or_default()
+    };
+    let mut response = match build_request(method.clone()).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            // reqwest's message alone doesn't say the server turned the certificate down
+            let error = if site.client_cert.is_some() {
+                format!(
+                    "request with client certificate failed: {}",
+                    error_chain(&e)
+                )
+            } else {
+                e.to_string()
+            };
+            let mut result = HealthCheckResult::down(CheckError::Connect(error));
+            result.prefetch_ok = prefetch_ok;
+            result.redirects = take_redirects();
+            return result;
+        }
+    };
+
+    let dns_duration: u128 = doh_time.unwrap_or_else(|| dns_start.elapsed().as_millis());
+    let response_time: u128 = start_time.elapsed().as_millis();
+    let status_code = response.status().as_u16();
+    let remote_addr = response
+        .remote_addr()
+        .map(|address| address.ip().to_string());
+    // Userinfo and secret query parameters of a redirect target stay out of reports
+    let insecure_final_url = Some(response.url())
+        .filter(|final_url| site.require_https_final && final_url.scheme() != "https")
+        .map(|final_url| CheckError::InsecureFinalUrl {
+            scheme: final_url.scheme().to_string(),
+            url: redact_url(final_url.as_str()),
+        });
+    let effective_host = site
+        .host_header
+        .clone()
+        .or_else(|| response.url().host_str().map(|host| host.to_string()));
+    let content_length: Option<u64> = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    let watched_header = site
+        .watch_header
+        .as_deref()
+        .or(app.watch_header.as_deref())
+        .map(|name| {
+            response
+                .headers()
+                .get(name)
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+                .unwrap_or_else(|| MISSING_HEADER.to_string())
+        });
+    let first_headers = response.headers().clone();
+    let content_range: Option<String> = response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    let retry_after_seconds: Option<u64> = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_retry_after(value, Utc::now()));
+    let charset: Option<String> = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(charset_of);
+
+    let mut result = HealthCheckResult {
+        status: CheckStatus::Up,
+        dns_time_ms: Some(dns_duration),
+        response_time_ms: Some(response_time),
+        status_code: Some(status_code),
+        content_length,
+        content_range,
+        connection_reused: Some(connection_reused),
+        prefetch_ok,
+        redirects: take_redirects(),
+        retry_after_seconds,
+        watched_header,
+        effective_host,
+        remote_addr,
+        resolved_address: resolved_address.map(|address| address.to_string()),
+        ..Default::default()
+    };
+    if site.expected_cache_control.is_some() || site.require_cache_validator {
+        result.cache_headers = Some(CacheHeaders::from_headers(&first_headers));
+    }
+
+    // Read chunk by chunk so a dropped connection still tells us how far we got.
+    // With read_body off the response is dropped unread, closing the connection
+    // after the headers, and the body fields stay empty.
+    let mut body: Vec<u8> = Vec::new();
+    let mut body_error: Option<reqwest::Error> = None;
+    if site.read_body {
+        let body_start: Instant = Instant::now();
+        loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+                Ok(None) => break,
+                Err(e) => {
+                    body_error = Some(e);
+                    break;
+                }
+            }
+        }
+        result.body_time_ms = Some(body_start.elapsed().as_millis());
+        result.body_bytes = Some(body.len() as u64);
+        if site.graphql.is_some() {
+            result.graphql_time_ms = Some(start_time.elapsed().as_millis());
+        }
+    }
+    drop(response);
+    let received = body.len() as u64;
+
+    // HEAD responses advertise a length without sending a body
+    let truncated = site.read_body
+        && method != Method::HEAD
+        && content_length.is_some_and(|expected| received < expected);
+    if body_error.is_some() || truncated {
+        let error = CheckError::IncompleteBody {
+            received,
+            expected: content_length,
+            cause: body_error.map(|e| e.to_string()),
+        };
+        log!(LogLevel::Warn, "{}: {}", url, error);
+        result.body_time_ms = None;
+        result.fail(CheckStatus::Down, error);
+        result.body_snippet = error_body_snippet(app, &body);
+        return result;
+    }
+    let body = match decompress(&first_headers, &body) {
+        Ok(Some(decoded)) => {
+            result.decoded_bytes = Some(decoded.len() as u64);
+            decoded
+        }
+        Ok(None) => body,
+        Err(e) => {
+            // Every byte arrived, so the cause is what's worth reporting
+            let error = CheckError::IncompleteBody {
+                received,
+                expected: None,
+                cause: Some(format!("could not decompress the body: {}", e)),
+            };
+            log!(LogLevel::Warn, "{}: {}", url, error);
+            result.fail(CheckStatus::Down, error);
+            return result;
+        }
+    };
+
+    let mut forbidden = None;
+    let mut schema_error = None;
+    let mut graphql_error = site
+        .graphql
+        .as_ref()
+        .filter(|_| !site.read_body)
+        .map(|_| CheckError::Graphql(String::from("GraphQL checks need read_body")));
+    let mut first_nonce = site
+        .nonce
+        .as_ref()
+        .and_then(|nonce| nonce.header.as_deref())
+        .and_then(|name| first_headers.get(name))
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned());
+    if site.read_body {
+        let text = decode_body(site, charset.as_deref(), &body, &mut result);
+        result.canary_value = site
+            .canary
+            .as_ref()
+            .and_then(|canary| extract_value(&text, canary.json_path.as_deref()));
+        forbidden = find_forbidden(site, &text);
+        schema_error = check_json_schema(site, &text);
+        graphql_error = site
+            .graphql
+            .as_ref()
+            .and_then(|graphql| check_graphql(graphql, &text));
+        if let Some(nonce) = site.nonce.as_ref().filter(|nonce| nonce.header.is_none()) {
+            first_nonce = extract_value(&text, nonce.json_path.as_deref());
+        }
+    }
+    let validator_error = match (&site.validator_command, site.read_body) {
+        (Some(command), true) => hooks::run_validator(
+            command,
+            &site.resolved_url(),
+            status_code,
+            &body,
+            site.validator_timeout_seconds,
+        )
+        .await
+        .err()
+        .map(CheckError::Validator),
+        _ => None,
+    };
+    if let Some(error) = check_status(site, "check", &method, status_code).or(warmup_error) {
+        result.fail(CheckStatus::Down, error);
+    } else if let Some(error) = insecure_final_url {
+        result.fail(CheckStatus::Down, error);
+    } else if let Some(error) = check_empty_body(site, &method, &result) {
+        result.fail(CheckStatus::Down, error);
+    } else if let Some(error) = check_body_size(site, &method, result.body_bytes) {
+        result.fail(CheckStatus::Down, error);
+    } else if let Some(token) = forbidden {
+        result.fail(CheckStatus::Down, CheckError::ForbiddenContent(token));
+    } else if let Some(error) = schema_error {
+        result.fail(CheckStatus::Down, error);
+    } else if let Some(error) = graphql_error {
+        result.fail(CheckStatus::Down, error);
+    } else if let Some(error) = validator_error {
+        result.fail(CheckStatus::Down, error);
+    } else if let Some(range) = &site.range {
+        check_range_response(range, &mut result);
+    }
+    // Logged whatever the status, only an otherwise UP site is marked DEGRADED for it
+    if let Some(error) = check_ip_ranges(site, &result) {
+        log!(LogLevel::Warn, "{}: {}", url, error);
+        if result.status == CheckStatus::Up {
+            result.fail(CheckStatus::Degraded, error);
+        }
+    }
+    if result.status == CheckStatus::Up {
+        if let Some(error) = check_caching(site, result.cache_headers.as_ref()) {
+            result.fail(CheckStatus::Degraded, error);
+        }
+    }
+    if let Some(nonce) = site.nonce.as_ref().filter(|_| !result.status.is_down()) {
+        let second = build_request(method.clone());
+        if let Some(error) = check_nonce(site, nonce, first_nonce, second, &mut result).await {
+            result.fail(CheckStatus::Down, error);
+        }
+    }
+    if site.http3 != Http3Mode::Off {
+        check_http3(site, &request_url, local_address, &mut result).await;
+    }
+    if result.status.is_down() {
+        result.body_snippet = error_body_snippet(app, &body);
+    }
+    if site.connect_budget_ms.is_some() && url.starts_with("http://") {
+        match connectivity::connect_time(url).await {
+            Ok(connect_ms) => result.connect_time_ms = Some(connect_ms),
+            Err(e) => log!(
+                LogLevel::Warn,
+                "Could not time connecting to {}: {}",
+                url,
+                e
+            ),
+        }
+    }
+    // A second connection, so only made when something needs what it tells
+    let wants_tls = app.tls_details
+        || app.tls_audit
+        || app.min_tls_version.is_some()
+        || site.connect_budget_ms.is_some()
+        || site.tls_budget_ms.is_some();
+    if wants_tls && request_url.starts_with("https://") {
+        let target = tls::ProbeTarget {
+            url: request_url.clone(),
+            // After a redirect the remote address is another host's
+            address: resolved_address.or_else(|| {
+                result
+                    .remote_addr
+                    .as_deref()
+                    .filter(|_| result.redirects.is_empty())
+                    .and_then(|address| address.parse().ok())
+            }),
+            local_address,
+            identity: match (&site.client_cert, &site.client_key) {
+                (Some(cert), Some(key)) => read_pem(cert).ok().zip(read_pem(key).ok()),
+                _ => None,
+            },
+            timeout: site.request_timeout(),
+        };
+        match tls::probe(target, app.tls_audit).await {
+            Ok(negotiated) => {
+                result.tls_version = Some(negotiated.version);
+                result.tls_cipher = Some(negotiated.cipher);
+                result.tls_findings = negotiated.findings;
+                result.connect_time_ms = Some(negotiated.connect_ms);
+                result.tls_time_ms = Some(negotiated.handshake_ms);
+            }
+            Err(e) => log!(
+                LogLevel::Warn,
+                "Could not read TLS details of {}: {}",
+                url,
+                e
+            ),
+        }
+    }
+    result
+}
+
+// One redirect: the url that answered with it, and how long it took to answer
+#[derive(Debug, Clone, Serialize)]
+pub struct RedirectHop {
+    pub url: String,
+    pub status: u16,
+    pub time_ms: u128,
+}
+
+// Redirects of the request in flight, filled in by the redirect policy
+#[derive(Default)]
+struct RedirectLog {
+    mark: Option<Instant>,
+    hops: Vec<RedirectHop>,
+}
+
+// Follow redirects like reqwest's default policy, timing each hop from the previous
+// one (or the request start) to its redirect arriving
+fn redirect_policy(log: Arc<Mutex<RedirectLog>>) -> Policy {
+    Policy::custom(move |attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            return attempt.error(format!("more than {} redirects", MAX_REDIRECTS));
+        }
+        if let (Ok(mut log), Some(from)) = (log.lock(), attempt.previous().last()) {
+            let now = Instant::now();
+            let time_ms = log
+                .mark
+                .map(|mark| now.duration_since(mark).as_millis())
+                .unwrap_or_default();
+            log.mark = Some(now);
+            log.hops.push(RedirectHop {
+                url: redact_url(from.as_str()),
+                status: attempt.status().as_u16(),
+                time_ms,
+            });
+        }
+        attempt.follow()
+    })
+}
+
+// Whether the server leaves the connection open for the next request: HTTP/1.0 only
+// when it asks for keep-alive, later versions unless it asks to close
+fn keeps_alive(response: &reqwest::Response) -> bool {
+    let connection = response
+        .headers()
+        .get(CONNECTION)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_ascii_lowercase());
+    if response.version() <= Version::HTTP_10 {
+        connection.is_some_and(|value| value.contains("keep-alive"))
+    } else {
+        !connection.is_some_and(|value| value.contains("close"))
+    }
+}
+
+// A check over a unix domain socket, bounded by the site's timeout as a whole and with
+// the status and body checks of an http one. Settings that need a network connection
+// (warmup, TLS, DNS...) are warned about in validate and ignored here.
+async fn check_unix_site(site: &SiteConfig, app: &AppSpecificConfig) -> HealthCheckResult {
+    let method = match parse_method(&site.method) {
+        Ok(method) => method,
+        Err(e) => return HealthCheckResult::down(e),
+    };
+    let url = site.request_url();
+    let timeout = site.request_timeout();
+    let request = unix_socket::request(
+        &url,
+        method.clone(),
+        site.host_header.as_deref(),
+        site.read_body,
+    );
+    let response = match tokio::time::timeout(timeout, request).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => return HealthCheckResult::down(e),
+        Err(_) => {
+            return HealthCheckResult::down(CheckError::Connect(format!(
+                "no response within {} ms",
+                timeout.as_millis()
+            )))
+        }
+    };
+
+    let header = |name: &str| {
+        response
+            .headers
+            .get(name)
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+    };
+    let mut result = HealthCheckResult {
+        status: CheckStatus::Up,
+        connection_reused: Some(false),
+        dns_time_ms: Some(response.response_time_ms),
+        response_time_ms: Some(response.response_time_ms),
+        body_time_ms: response.body_time_ms,
+        status_code: Some(response.status_code),
+        content_length: header(CONTENT_LENGTH.as_str()).and_then(|value| value.parse().ok()),
+        watched_header: site
+            .watch_header
+            .as_deref()
+            .or(app.watch_header.as_deref())
+            .map(|name| header(name).unwrap_or_else(|| MISSING_HEADER.to_string())),
+        ..Default::default()
+    };
+    if !site.read_body {
+        return match check_status(site, "check", &method, response.status_code) {
+            Some(error) => {
+                result.fail(CheckStatus::Down, error);
+                result
+            }
+            None => result,
+        };
+    }
+    result.body_bytes = Some(response.body.len() as u64);
+    if let Some(cause) = response.body_error {
+        result.body_time_ms = None;
+        result.fail(
+            CheckStatus::Down,
+            CheckError::IncompleteBody {
+                received: response.body.len() as u64,
+                expected: result.content_length,
+                cause: Some(cause),
+            },
+        );
+        result.body_snippet = error_body_snippet(app, &response.body);
+        return result;
+    }
+
+    let charset = header(CONTENT_TYPE.as_str()).and_then(|value| charset_of(&value));
+    let text = decode_body(site, charset.as_deref(), &response.body, &mut result);
+    result.canary_value = site
+        .canary
+        .as_ref()
+        .and_then(|canary| extract_value(&text, canary.json_path.as_deref()));
+    let validator_error = match &site.validator_command {
+        Some(command) => hooks::run_validator(
+            command,
+            &site.resolved_url(),
+            response.status_code,
+            &response.body,
+            site.validator_timeout_seconds,
+        )
+        .await
+        .err()
+        .map(CheckError::Validator),
+        None => None,
+    };
+    if let Some(error) = check_status(site, "check", &method, response.status_code) {
+        result.fail(CheckStatus::Down, error);
+    } else if let Some(error) = check_empty_body(site, &method, &result) {
+        result.fail(CheckStatus::Down, error);
+    } else if let Some(error) = check_body_size(site, &method, result.body_bytes) {
+        result.fail(CheckStatus::Down, error);
+    } else if let Some(token) = find_forbidden(site, &text) {
+        result.fail(CheckStatus::Down, CheckError::ForbiddenContent(token));
+    } else if let Some(error) = check_json_schema(site, &text) {
+        result.fail(CheckStatus::Down, error);
+    } else if let Some(error) = validator_error {
+        result.fail(CheckStatus::Down, error);
+    }
+    if result.status.is_down() {
+        result.body_snippet = error_body_snippet(app, &response.body);
+    }
+    result
+}
+
+// Resolve on the site's dns_timeout_ms, without falling back from DoH to the system
+// resolver since that would only take longer
+async fn resolve_within(
+    host: &str,
+    doh: Option<&DohConfig>,
+    timeout_ms: u64,
+) -> Result<IpAddr, CheckError> {
+    let resolution = prefetch::resolve_host(host, doh);
+    match tokio::time::timeout(Duration::from_millis(timeout_ms), resolution).await {
+        Ok(resolved) => resolved.map_err(CheckError::Resolve),
+        Err(_) => Err(CheckError::DnsTimeout {
+            host: host.to_string(),
+            timeout_ms,
+        }),
+    }
+}
+
+// Connect to wherever the url points but present `sni` in the handshake (and as the
+// Host), by requesting the url under the sni name pinned to the original address
+async fn sni_target(
+    url: &str,
+    sni: &str,
+    prefetched: &ResolvedHosts,
+    app: &AppSpecificConfig,
+    dns_timeout_ms: Option<u64>,
+) -> Result<(String, IpAddr), CheckError> {
+    let mut parsed = Url::parse(url).map_err(|e| CheckError::Client(e.to_string()))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| CheckError::Client(format!("no host in {}", url)))?
+        .to_string();
+
+    let address = match host.parse::<IpAddr>() {
+        Ok(address) => address,
+        Err(_) => match prefetched.get(&host) {
+            Some(resolved) => resolved.address,
+            None => match dns_timeout_ms {
+                Some(timeout_ms) => resolve_within(&host, app.doh.as_ref(), timeout_ms).await?,
+                None => prefetch::resolve_host(&host, app.doh.as_ref())
+                    .await
+                    .map_err(CheckError::Resolve)?,
+            },
+        },
+    };
+
+    parsed
+        .set_host(Some(sni))
+        .map_err(|e| CheckError::Client(format!("invalid sni {}: {}", sni, e)))?;
+    Ok((parsed.to_string(), address))
+}
+
+// Send the request again and require its nonce to differ from the first response's.
+// Both values are kept on the result.
+async fn check_nonce(
+    site: &SiteConfig,
+    nonce: &NonceConfig,
+    first: Option<String>,
+    request: RequestBuilder,
+    result: &mut HealthCheckResult,
+) -> Option<CheckError> {
+    let source = match (&nonce.header, &nonce.json_path) {
+        (Some(header), _) => format!("{} header", header),
+        (None, Some(json_path)) => format!("body value {}", json_path),
+        (None, None) => String::from("body"),
+    };
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            return Some(CheckError::Nonce(format!(
+                "second request for the {} failed: {}",
+                source, e
+            )))
+        }
+    };
+
+    let second = match &nonce.header {
+        Some(name) => response
+            .headers()
+            .get(name)
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned()),
+        None => {
+            let charset = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(charset_of);
+            let headers = response.headers().clone();
+            let body = match response.bytes().await {
+                Ok(body) => decompress(&headers, &body)
+                    .map(|decoded| decoded.unwrap_or_else(|| body.to_vec()))
+                    .map_err(|e| format!("could not decompress the body: {}", e)),
+                Err(e) => Err(e.to_string()),
+            };
+            match body {
+                // Decoding warnings were already recorded for the first response
+                Ok(body) => {
+                    let text = decode_body(
+                        site,
+                        charset.as_deref(),
+                        &body,
+                        &mut HealthCheckResult::default(),
+                    );
+                    extract_value(&text, nonce.json_path.as_deref())
+                }
+                Err(e) => {
+                    return Some(CheckError::Nonce(format!(
+                        "second request for the {} failed: {}",
+                        source, e
+                    )))
+                }
+            }
+        }
+    };
+
+    result.nonce_values = [&first, &second]
+        .iter()
+        .map(|value| value.as_deref().unwrap_or(MISSING_HEADER).to_string())
+        .collect();
+    match (first, second) {
+        (Some(first), Some(second)) if first != second => None,
+        (Some(first), Some(_)) => Some(CheckError::Nonce(format!(
+            "{} was {} on two requests in a row, responses look cached",
+            source, first
+        ))),
+        _ => Some(CheckError::Nonce(format!(
+            "{} missing from the response",
+            source
+        ))),
+    }
+}
+
+// Read the site's client certificate and key, re-read every check so renewed files are
+// picked up. The key is converted to PKCS#8, the only form reqwest accepts.
+fn client_identity(site: &SiteConfig) -> Result<Identity, String> {
+    let (cert, key) = match (&site.client_cert, &site.client_key) {
+        (Some(cert), Some(key)) => (cert, key),
+        _ => {
+            return Err(String::from(
+                "client_cert and client_key must be set together",
+            ))
+        }
+    };
+    let cert = read_pem(cert)?;
+    let key = PKey::private_key_from_pem(&read_pem(key)?)
+        .and_then(|key| key.private_key_to_pem_pkcs8())
+        .map_err(|e| format!("invalid client_key: {}", e))?;
+    Identity::from_pkcs8_pem(&cert, &key).map_err(|e| format!("invalid client_cert: {}", e))
+}
+
+// A PEM given inline (usually through an environment variable) or a path to one
+fn read_pem(value: &str) -> Result<Vec<u8>, String> {
+    if value.trim_start().starts_with("-----BEGIN") {
+        return Ok(value.as_bytes().to_vec());
+    }
+    std::fs::read(value).map_err(|e| format!("cannot read {}: {}", value, e))
+}
+
+// An error with its causes, where TLS alerts like a rejected certificate end up
+pub fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
+
+// A GraphQL answer is only healthy without an errors array, and with the data_path
+// value (when set) present and equal to the expected one
+fn check_graphql(graphql: &GraphqlConfig, text: &str) -> Option<CheckError> {
+    let body: serde_json::Value = match serde_json::from_str(text) {
+        Ok(body) => body,
+        Err(e) => return Some(CheckError::Graphql(format!("response is not JSON: {}", e))),
+    };
+
+    if let Some(errors) = body
+        .get("errors")
+        .and_then(|errors| errors.as_array())
+        .filter(|errors| !errors.is_empty())
+    {
+        let messages: Vec<String> = errors
+            .iter()
+            .map(|error| match error.get("message") {
+                Some(serde_json::Value::String(message)) => message.clone(),
+                _ => error.to_string(),
+            })
+            .collect();
+        return Some(CheckError::Graphql(format!(
+            "GraphQL errors: {}",
+            messages.join("; ")
+        )));
+    }
+
+    let path = graphql.data_path.as_ref()?;
+    let pointer = format!("/data/{}", path.replace('.', "/"));
+    let value = match body.pointer(&pointer) {
+        None | Some(serde_json::Value::Null) => {
+            return Some(CheckError::Graphql(format!("data.{} is missing", path)))
+        }
+        Some(serde_json::Value::String(value)) => value.clone(),
+        Some(value) => value.to_string(),
+    };
+    match &graphql.expected {
+        Some(expected) if *expected != value => Some(CheckError::Graphql(format!(
+            "data.{} is {}, expected {}",
+            path, value, expected
+        ))),
+        _ => None,
+    }
+}
+
+// Validate the body against the site's compiled schema, reporting the first violation
+fn check_json_schema(site: &SiteConfig, text: &str) -> Option<CheckError> {
+    let validator = site.compiled_schema.as_ref()?;
+    let body: serde_json::Value = match serde_json::from_str(text) {
+        Ok(body) => body,
+        Err(e) => return Some(CheckError::JsonSchema(format!("body is not JSON: {}", e))),
+    };
+    let error = validator.validate(&body).err()?;
+    let path = error.instance_path.to_string();
+    Some(CheckError::JsonSchema(format!(
+        "body does not match the JSON schema at {}: {}",
+        if path.is_empty() { "/" } else { &path },
+        error
+    )))
+}
+
+// The first forbidden string in the body, or the first forbidden pattern matching it.
+// Invalid patterns were already warned about and left out when the settings were loaded.
+fn find_forbidden(site: &SiteConfig, text: &str) -> Option<String> {
+    if let Some(token) = site
+        .forbidden_body
+        .iter()
+        .find(|token| !token.is_empty() && text.contains(token.as_str()))
+    {
+        return Some(token.clone());
+    }
+    site.compiled_forbidden_regex
+        .iter()
+        .find(|regex| regex.is_match(text))
+        .map(|regex| regex.as_str().to_string())
+}
+
+// Repeat the request over QUIC. Only "require" turns a failure into DOWN.
+async fn check_http3(
+    site: &SiteConfig,
+    request_url: &str,
+    local_address: Option<IpAddr>,
+    result: &mut HealthCheckResult,
+) {
+    match http3::probe(request_url, local_address).await {
+        Ok(()) => result.http3 = Some(true),
+        Err(e) => {
+            result.http3 = Some(false);
+            if site.http3 == Http3Mode::Require {
+                if !result.status.is_down() {
+                    result.fail(CheckStatus::Down, CheckError::Http3(e));
+                }
+            } else {
+                log!(LogLevel::Debug, "{}: {}", site.url, e);
+                result.warnings.push(e);
+            }
+        }
+    }
+}
+
+fn error_body_snippet(app: &AppSpecificConfig, body: &[u8]) -> Option<String> {
+    snippet::body_snippet(
+        body,
+        app.error_body_snippet_bytes,
+        &app.error_body_redact_patterns,
+    )
+}
+
+// The charset parameter of a Content-Type, e.g. "text/html; charset=ISO-8859-1"
+fn charset_of(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+// Undo the Content-Encoding asked for with Accept-Encoding, None when the body came
+// as is. Deflate is meant to be zlib wrapped, some servers send it raw.
+fn decompress(headers: &HeaderMap, body: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+    let encoding = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase());
+    let mut decoded: Vec<u8> = Vec::new();
+    match encoding.as_deref() {
+        _ if body.is_empty() => return Ok(None),
+        Some("gzip") | Some("x-gzip") => GzDecoder::new(body).read_to_end(&mut decoded)?,
+        Some("deflate") => match ZlibDecoder::new(body).read_to_end(&mut decoded) {
+            Ok(read) => read,
+            Err(_) => {
+                decoded.clear();
+                DeflateDecoder::new(body).read_to_end(&mut decoded)?
+            }
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(decoded))
+}
+
+// Decode with the site's encoding, else the response's charset, else UTF-8. A byte
+// order mark wins over both. Replaced sequences are kept as a warning on the result.
+fn decode_body(
+    site: &SiteConfig,
+    charset: Option<&str>,
+    body: &[u8],
+    result: &mut HealthCheckResult,
+) -> String {
+    let encoding = site
+        .encoding
+        .as_deref()
+        .or(charset)
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .unwrap_or(UTF_8);
+    let (text, used, had_errors) = encoding.decode(body);
+    result.body_encoding = Some(used.name().to_string());
+    if had_errors {
+        let warning = format!(
+            "body is not valid {}, invalid sequences were replaced",
+            used.name()
+        );
+        log!(LogLevel::Warn, "{}: {}", site.url, warning);
+        result.warnings.push(warning);
+    }
+    text.into_owned()
+}
+
+fn parse_method(method: &str) -> Result<Method, CheckError> {
+    Method::from_bytes(method.to_uppercase().as_bytes())
+        .map_err(|_| CheckError::Client(format!("invalid http method {}", method)))
+}
+
+// Compare a step's status with the ones accepted for its method, if any are configured
+fn check_status(
+    site: &SiteConfig,
+    step: &str,
+    method: &Method,
+    status_code: u16,
+) -> Option<CheckError> {
+    let expected = site
+        .expected_status
+        .iter()
+        .find(|(expected_method, _)| expected_method.eq_ignore_ascii_case(method.as_str()))
+        .map(|(_, expected)| expected)?;
+    if expected.contains(&status_code) {
+        return None;
+    }
+    Some(CheckError::UnexpectedStatus {
+        step: step.to_string(),
+        method: method.to_string(),
+        status_code,
+        expected: expected.clone(),
+    })
+}
+
+// An advertised Content-Length of 0 counts even when the body isn't read
+fn check_empty_body(
+    site: &SiteConfig,
+    method: &Method,
+    result: &HealthCheckResult,
+) -> Option<CheckError> {
+    if site.allow_empty_body || method == Method::HEAD {
+        return None;
+    }
+    if result.content_length == Some(0) || result.body_bytes == Some(0) {
+        return Some(CheckError::EmptyBody {
+            content_length: result.content_length,
+        });
+    }
+    None
+}
+
+// A 200 with an empty or half-rendered page is still broken. Nothing to compare
+// when the body wasn't read, and HEAD never has one.
+fn check_body_size(
+    site: &SiteConfig,
+    method: &Method,
+    body_bytes: Option<u64>,
+) -> Option<CheckError> {
+    let received = body_bytes.filter(|_| method != Method::HEAD)?;
+    if let Some(min_bytes) = site
+        .min_body_bytes
+        .filter(|min_bytes| received < *min_bytes)
+    {
+        return Some(CheckError::BodyTooSmall {
+            received,
+            min_bytes,
+        });
+    }
+    if let Some(max_bytes) = site
+        .max_body_bytes
+        .filter(|max_bytes| received > *max_bytes)
+    {
+        return Some(CheckError::BodyTooLarge {
+            received,
+            max_bytes,
+        });
+    }
+    None
+}
+
+// The response headers that decide how it's cached
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CacheHeaders {
+    pub cache_control: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub expires: Option<String>,
+}
+
+impl CacheHeaders {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name: HeaderName| {
+            headers
+                .get(name)
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+        };
+        CacheHeaders {
+            cache_control: get(CACHE_CONTROL),
+            etag: get(ETAG),
+            last_modified: get(LAST_MODIFIED),
+            expires: get(EXPIRES),
+        }
+    }
+}
+
+// A CIDR such as "203.0.113.0/24", or a single address
+pub fn parse_ip_range(range: &str) -> Option<IpNet> {
+    range
+        .parse()
+        .ok()
+        .or_else(|| range.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+// The address the host resolved to, or else the one the response came from, must be in
+// one of the site's allowed_ip_ranges
+fn check_ip_ranges(site: &SiteConfig, result: &HealthCheckResult) -> Option<CheckError> {
+    if site.allowed_ip_ranges.is_empty() {
+        return None;
+    }
+    let address: IpAddr = result
+        .resolved_address
+        .as_ref()
+        .or(result.remote_addr.as_ref())?
+        .parse()
+        .ok()?;
+    let allowed = site
+        .allowed_ip_ranges
+        .iter()
+        .filter_map(|range| parse_ip_range(range))
+        .any(|range| range.contains(&address));
+    (!allowed).then(|| CheckError::OutsideIpRanges {
+        address: address.to_string(),
+        allowed: site.allowed_ip_ranges.clone(),
+    })
+}
+
+// Every expected Cache-Control directive must be sent, compared case-insensitively
+// and in any order, and a validator must be there when required
+fn check_caching(site: &SiteConfig, headers: Option<&CacheHeaders>) -> Option<CheckError> {
+    let headers = headers?;
+    let directives = |value: &str| -> Vec<String> {
+        value
+            .split(',')
+            .map(|directive| directive.trim().to_ascii_lowercase().replace(' ', ""))
+            .filter(|directive| !directive.is_empty())
+            .collect()
+    };
+
+    let mut problems = Vec::new();
+    if let Some(expected) = &site.expected_cache_control {
+        match &headers.cache_control {
+            Some(cache_control) => {
+                let sent = directives(cache_control);
+                let missing: Vec<String> = directives(expected)
+                    .into_iter()
+                    .filter(|directive| !sent.contains(directive))
+                    .collect();
+                if !missing.is_empty() {
+                    problems.push(format!(
+                        "Cache-Control \"{}\" is missing {}",
+                        cache_control,
+                        missing.join(", ")
+                    ));
+                }
+            }
+            None => problems.push(format!("no Cache-Control, expected \"{}\"", expected)),
+        }
+    }
+    if site.require_cache_validator && headers.etag.is_none() && headers.last_modified.is_none() {
+        problems.push(String::from("neither ETag nor Last-Modified is set"));
+    }
+
+    if problems.is_empty() {
+        None
+    } else {
+        Some(CheckError::Caching(problems.join("; ")))
+    }
+}
+
+// A range request must come back as 206 with a Content-Range covering what was asked for
+fn check_range_response(range: &str, result: &mut HealthCheckResult) {
+    let error = match (result.status_code, result.content_range.as_deref()) {
+        (Some(206), Some(content_range)) if range_matches(range, content_range) => return,
+        (Some(206), Some(content_range)) => format!(
+            "Content-Range {} does not match requested {}",
+            content_range, range
+        ),
+        (Some(206), None) => String::from("206 response without a Content-Range header"),
+        (Some(status), _) => format!("range request ignored, expected 206 but got {}", status),
+        (None, _) => return,
+    };
+    result.fail(CheckStatus::Down, CheckError::Range(error));
+}
+
+// "bytes=0-1023" is satisfied by "bytes 0-1023/5000" or a shorter "bytes 0-99/100"
+fn range_matches(requested: &str, content_range: &str) -> bool {
+    let parse = |value: &str, prefix: &str| -> Option<(u64, Option<u64>)> {
+        let spec = value.strip_prefix(prefix)?.split('/').next()?;
+        let (start, end) = spec.split_once('-')?;
+        Some((start.trim().parse().ok()?, end.trim().parse().ok()))
+    };
+
+    match (parse(requested, "bytes="), parse(content_range, "bytes ")) {
+        (Some((start, end)), Some((got_start, Some(got_end)))) => {
+            got_start == start && end.is_none_or(|end| got_end <= end)
+        }
+        _ => false,
+    }
+}
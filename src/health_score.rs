@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use crate::config::SiteConfig;
+use crate::health::HealthCheckResult;
+use crate::site_state::SiteStates;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HealthScore {
+    // Weighted share of sites up this cycle, 0.0 - 1.0
+    pub current: f64,
+    // Mean of the current score over the rolling window
+    pub rolling: f64,
+}
+
+// Sum of the weights of sites that are up over the sum of all weights
+pub fn weighted_score(
+    sites: &[SiteConfig],
+    results: &HashMap<String, HealthCheckResult>,
+) -> Option<f64> {
+    let mut up_weight = 0.0;
+    let mut total_weight = 0.0;
+
+    for site in sites {
+        let result = match results.get(&site.resolved_url()) {
+            Some(result) => result,
+            None => continue,
+        };
+        total_weight += site.weight;
+        if !result.status.is_down() {
+            up_weight += site.weight;
+        }
+    }
+
+    if total_weight > 0.0 {
+        Some(up_weight / total_weight)
+    } else {
+        None
+    }
+}
+
+// Record each site's outcome and return its uptime percentage over the same
+// rolling window as the health score
+pub fn update_uptime(
+    results: &HashMap<String, HealthCheckResult>,
+    site_states: &mut SiteStates,
+    window: usize,
+) -> HashMap<String, f64> {
+    let mut uptime = HashMap::new();
+
+    for (url, result) in results {
+        let history = &mut site_states.entry(url).uptime_window;
+        history.push(!result.status.is_down());
+        if history.len() > window.max(1) {
+            let excess = history.len() - window.max(1);
+            history.drain(..excess);
+        }
+        let up = history.iter().filter(|up| **up).count();
+        uptime.insert(url.clone(), up as f64 * 100.0 / history.len() as f64);
+    }
+
+    uptime
+}
+
+// Score this cycle and fold it into the persisted rolling window
+pub fn update_health_score(
+    sites: &[SiteConfig],
+    results: &HashMap<String, HealthCheckResult>,
+    site_states: &mut SiteStates,
+    window: usize,
+) -> Option<HealthScore> {
+    let current = weighted_score(sites, results)?;
+
+    let history = &mut site_states.health_scores;
+    history.push(current);
+    if history.len() > window.max(1) {
+        let excess = history.len() - window.max(1);
+        history.drain(..excess);
+    }
+    let rolling = history.iter().sum::<f64>() / history.len() as f64;
+
+    Some(HealthScore { current, rolling })
+}
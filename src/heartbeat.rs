@@ -0,0 +1,37 @@
+use std::fs;
+
+use artisan_middleware::timestamp::current_timestamp;
+use dusa_collection_utils::errors::ErrorArrayItem;
+
+// A timestamp file rewritten on every turn of the run loop, between cycles too,
+// so a watchdog can tell a hung monitor from one that's just waiting
+pub struct Heartbeat {
+    path: String,
+}
+
+impl Heartbeat {
+    pub fn new(path: &str) -> Self {
+        Heartbeat {
+            path: path.to_string(),
+        }
+    }
+
+    // Written to a temporary file and renamed, a reader never sees half a timestamp
+    pub fn beat(&self) -> Result<(), ErrorArrayItem> {
+        let temp_path = format!("{}.tmp", self.path);
+        fs::write(&temp_path, format!("{}\n", current_timestamp()))?;
+        fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+// Seconds since the heartbeat at `path` was written
+pub fn heartbeat_age(path: &str, now: u64) -> Result<u64, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("cannot read heartbeat {}: {}", path, e))?;
+    let written: u64 = content
+        .trim()
+        .parse()
+        .map_err(|_| format!("heartbeat {} does not hold a timestamp", path))?;
+    Ok(now.saturating_sub(written))
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::health::{CheckStatus, HealthCheckResult};
+use crate::site_state::SiteStates;
+
+// One line of the history file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub at: u64,
+    pub url: String,
+    pub status: CheckStatus,
+    pub response_time_ms: Option<u128>,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+}
+
+// Picks the per-site results worth keeping, the state store writes them. Status
+// changes are always kept, steady states only every `sample_every` cycles and no
+// more often than `min_interval_seconds`.
+pub struct History {
+    sample_every: u32,
+    min_interval_seconds: u64,
+}
+
+impl History {
+    pub fn new(sample_every: u32, min_interval_seconds: u64) -> Self {
+        History {
+            sample_every: sample_every.max(1),
+            min_interval_seconds,
+        }
+    }
+
+    pub fn sample(
+        &self,
+        results: &HashMap<String, HealthCheckResult>,
+        site_states: &mut SiteStates,
+        now: u64,
+    ) -> Vec<HistoryRecord> {
+        let mut records = Vec::new();
+
+        for (url, result) in results {
+            let site_state = site_states.entry(url);
+            site_state.history_cycles_skipped += 1;
+            let changed = site_state.history_last_status != Some(result.status);
+            let sampled = site_state.history_cycles_skipped >= self.sample_every
+                && site_state
+                    .history_last_recorded_at
+                    .is_none_or(|last| now.saturating_sub(last) >= self.min_interval_seconds);
+            if !changed && !sampled {
+                continue;
+            }
+
+            records.push(HistoryRecord {
+                at: now,
+                url: url.clone(),
+                status: result.status,
+                response_time_ms: result.response_time_ms,
+                status_code: result.status_code,
+                error: result.error.as_ref().map(|error| error.to_string()),
+            });
+
+            site_state.history_last_status = Some(result.status);
+            site_state.history_last_recorded_at = Some(now);
+            site_state.history_cycles_skipped = 0;
+        }
+
+        records
+    }
+}
@@ -0,0 +1,137 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::changes::StatusTransition;
+
+// Run a site's on_change_command for a transition. The command goes through `sh -c`
+// with the url, old status, new status and error as $1..$4 and as environment variables.
+pub async fn run_on_change(command: &str, transition: &StatusTransition, timeout_seconds: u64) {
+    let error = transition.error.as_deref().unwrap_or("");
+    let old_status = transition.from.to_string();
+    let new_status = transition.to.to_string();
+
+    let mut child = Command::new("sh");
+    child
+        .arg("-c")
+        .arg(command)
+        .arg("website_monitor")
+        .args([transition.url.as_str(), &old_status, &new_status, error])
+        .env("WEBSITE_MONITOR_URL", &transition.url)
+        .env("WEBSITE_MONITOR_OLD_STATUS", &old_status)
+        .env("WEBSITE_MONITOR_NEW_STATUS", &new_status)
+        .env("WEBSITE_MONITOR_ERROR", error)
+        .kill_on_drop(true);
+
+    let output =
+        match tokio::time::timeout(Duration::from_secs(timeout_seconds), child.output()).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                log!(
+                    LogLevel::Error,
+                    "Failed to run on_change_command for {}: {}",
+                    transition.url,
+                    e
+                );
+                return;
+            }
+            Err(_) => {
+                log!(
+                    LogLevel::Error,
+                    "on_change_command for {} killed after {}s",
+                    transition.url,
+                    timeout_seconds
+                );
+                return;
+            }
+        };
+
+    let level = if output.status.success() {
+        LogLevel::Info
+    } else {
+        LogLevel::Warn
+    };
+    log!(
+        level,
+        "on_change_command for {} ({} -> {}) exited with {}",
+        transition.url,
+        old_status,
+        new_status,
+        output.status
+    );
+    for (stream, bytes) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+        let text = String::from_utf8_lossy(bytes);
+        if !text.trim().is_empty() {
+            log!(
+                LogLevel::Debug,
+                "on_change_command {}: {}",
+                stream,
+                text.trim()
+            );
+        }
+    }
+}
+
+// Longest stderr kept as the reason a validator rejected a body
+const VALIDATOR_REASON_CHARS: usize = 500;
+
+// Run a site's validator_command with the response body on stdin, through `sh -c` with
+// the url and status code as $1 and $2 and as environment variables. Err holds why the
+// body was rejected: the validator's stderr, or how it exited when that's empty.
+pub async fn run_validator(
+    command: &str,
+    url: &str,
+    status_code: u16,
+    body: &[u8],
+    timeout_seconds: u64,
+) -> Result<(), String> {
+    let status_code = status_code.to_string();
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .arg("website_monitor")
+        .args([url, &status_code])
+        .env("WEBSITE_MONITOR_URL", url)
+        .env("WEBSITE_MONITOR_STATUS_CODE", &status_code)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("validator_command failed to start: {}", e))?;
+
+    // Written while waiting, so a validator that exits without reading all of its
+    // input doesn't hold up the check. Dropping stdin afterwards signals the end.
+    let stdin = child.stdin.take();
+    let write = async move {
+        if let Some(mut stdin) = stdin {
+            let _ = stdin.write_all(body).await;
+        }
+    };
+    let run = async { tokio::join!(write, child.wait_with_output()).1 };
+    let output = match tokio::time::timeout(Duration::from_secs(timeout_seconds), run).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(format!("validator_command failed: {}", e)),
+        Err(_) => {
+            return Err(format!(
+                "validator_command killed after {}s",
+                timeout_seconds
+            ))
+        }
+    };
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason: String = stderr.trim().chars().take(VALIDATOR_REASON_CHARS).collect();
+    if reason.is_empty() {
+        Err(format!("validator_command exited with {}", output.status))
+    } else {
+        Err(reason)
+    }
+}
@@ -0,0 +1,35 @@
+use std::net::IpAddr;
+
+// Request the url over QUIC with prior knowledge, so there is no silent fallback to
+// TCP: either h3 answers or this fails. Needs the `http3` cargo feature.
+#[cfg(feature = "http3")]
+pub async fn probe(url: &str, local_address: Option<IpAddr>) -> Result<(), String> {
+    use std::time::Duration;
+
+    use reqwest::{Client, Version};
+
+    let client = Client::builder()
+        .use_rustls_tls()
+        .http3_prior_knowledge()
+        .local_address(local_address)
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client
+        .get(url)
+        .version(Version::HTTP_3)
+        .send()
+        .await
+        .map_err(|e| format!("HTTP/3 request failed: {}", e))?;
+    match response.version() {
+        Version::HTTP_3 => Ok(()),
+        version => Err(format!("answered over {:?} instead of HTTP/3", version)),
+    }
+}
+
+#[cfg(not(feature = "http3"))]
+pub async fn probe(_url: &str, _local_address: Option<IpAddr>) -> Result<(), String> {
+    Err(String::from(
+        "HTTP/3 checks need a build with the http3 cargo feature",
+    ))
+}
@@ -0,0 +1,65 @@
+use std::fs;
+use std::time::Duration;
+
+use reqwest::Client;
+
+use crate::config::WebsiteConfig;
+
+// Read the urls kept outside Config.toml. None when no external source is configured.
+pub async fn load_external_urls(websites: &WebsiteConfig) -> Option<Result<Vec<String>, String>> {
+    if websites.urls_file.is_none() && websites.urls_url.is_none() {
+        return None;
+    }
+
+    let mut urls = Vec::new();
+    if let Some(path) = &websites.urls_file {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => return Some(Err(format!("Failed to read {}: {}", path, e))),
+        };
+        match parse_url_list(&content) {
+            Ok(list) => urls.extend(list),
+            Err(e) => return Some(Err(format!("Failed to parse {}: {}", path, e))),
+        }
+    }
+    if let Some(endpoint) = &websites.urls_url {
+        match fetch_url_list(endpoint).await {
+            Ok(list) => urls.extend(list),
+            Err(e) => return Some(Err(e)),
+        }
+    }
+    Some(Ok(urls))
+}
+
+async fn fetch_url_list(endpoint: &str) -> Result<Vec<String>, String> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client
+        .get(endpoint)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Failed to fetch url list from {}: {}", endpoint, e))?;
+    let body = response
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read url list from {}: {}", endpoint, e))?;
+    parse_url_list(&body).map_err(|e| format!("Failed to parse url list from {}: {}", endpoint, e))
+}
+
+// Either a JSON array of strings or one url per line, blank lines and # comments skipped
+fn parse_url_list(content: &str) -> Result<Vec<String>, String> {
+    let trimmed = content.trim();
+    if trimmed.starts_with('[') {
+        return serde_json::from_str(trimmed).map_err(|e| e.to_string());
+    }
+
+    Ok(trimmed
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use crate::config::SiteConfig;
+use crate::health::{CheckError, CheckStatus, HealthCheckResult};
+use crate::site_state::SiteStates;
+
+// Cumulative response time histogram of one site since startup, for /metrics
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    // Observations per bucket, the last one past the highest bound (+Inf)
+    pub counts: Vec<u64>,
+    pub sum: u128,
+    pub count: u64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, bounds: &[u64], value: u128) {
+        if self.counts.len() != bounds.len() + 1 {
+            self.counts = vec![0; bounds.len() + 1];
+        }
+        let bucket = bounds
+            .iter()
+            .position(|bound| value <= *bound as u128)
+            .unwrap_or(bounds.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+// Fold this cycle's response times into each site's histogram
+pub fn observe_histograms(
+    results: &HashMap<String, HealthCheckResult>,
+    histograms: &mut HashMap<String, Histogram>,
+    bounds: &[u64],
+) {
+    for (url, result) in results {
+        if let Some(response_time) = result.response_time_ms {
+            histograms
+                .entry(url.clone())
+                .or_default()
+                .observe(bounds, response_time);
+        }
+    }
+}
+
+// Nearest-rank percentile, None for an empty window
+pub fn percentile(values: &[u128], percent: f64) -> Option<u128> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let rank = ((percent / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+// Keep each site's recent response times and downgrade sites whose p95 over that
+// window is above their limits, so a single slow outlier doesn't trip anything
+pub fn apply_percentile_limits(
+    sites: &[SiteConfig],
+    results: &mut HashMap<String, HealthCheckResult>,
+    site_states: &mut SiteStates,
+    window: usize,
+) {
+    for site in sites {
+        let url = site.resolved_url();
+        let result = match results.get_mut(&url) {
+            Some(result) if !result.expect_down => result,
+            _ => continue,
+        };
+        let response_time = match result.response_time_ms {
+            Some(response_time) if !result.status.is_down() => response_time,
+            _ => continue,
+        };
+
+        let samples = &mut site_states.entry(&url).latency_window;
+        samples.push(response_time);
+        if samples.len() > window {
+            samples.drain(..samples.len() - window);
+        }
+        if site.p95_degraded_ms.is_none() && site.p95_max_ms.is_none() {
+            continue;
+        }
+
+        let p95_ms = match percentile(samples, 95.0) {
+            Some(p95_ms) => p95_ms,
+            None => continue,
+        };
+        let limits = [
+            (site.p95_max_ms, CheckStatus::Down),
+            (site.p95_degraded_ms, CheckStatus::Degraded),
+        ];
+        for (limit_ms, status) in limits {
+            if let Some(limit_ms) = limit_ms.filter(|limit_ms| p95_ms > *limit_ms as u128) {
+                if status > result.status {
+                    result.fail(
+                        status,
+                        CheckError::SlowPercentile {
+                            p95_ms,
+                            limit_ms,
+                            samples: samples.len(),
+                        },
+                    );
+                }
+                break;
+            }
+        }
+    }
+}
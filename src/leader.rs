@@ -0,0 +1,94 @@
+use std::fs;
+
+use dusa_collection_utils::errors::{ErrorArrayItem, Errors};
+use serde::{Deserialize, Serialize};
+
+use crate::config::LeaderConfig;
+
+// Who holds the lease and until when, as stored in the shared lease file
+#[derive(Debug, Serialize, Deserialize)]
+struct Lease {
+    holder: String,
+    expires_at: u64,
+}
+
+pub enum Leadership {
+    Leader,
+    Standby { holder: String },
+}
+
+// A timestamp lease in a file every instance can reach (e.g. on a shared mount). The
+// holder renews it each cycle, the others take it over once it has expired.
+pub struct LeaderLease {
+    path: String,
+    instance_id: String,
+    lease_seconds: u64,
+}
+
+impl LeaderLease {
+    pub fn new(config: &LeaderConfig) -> Self {
+        LeaderLease {
+            path: config.lease_path.clone(),
+            instance_id: config
+                .instance_id
+                .clone()
+                .unwrap_or_else(default_instance_id),
+            lease_seconds: config.lease_seconds,
+        }
+    }
+
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    // Take or renew the lease unless another live instance holds it. The file is read
+    // back after writing so that of two instances racing for an expired lease, the one
+    // whose write landed last wins and the other stands by.
+    pub fn acquire(&self, now: u64) -> Result<Leadership, ErrorArrayItem> {
+        if let Some(lease) = self.read()? {
+            if lease.holder != self.instance_id && lease.expires_at > now {
+                return Ok(Leadership::Standby {
+                    holder: lease.holder,
+                });
+            }
+        }
+
+        let lease = Lease {
+            holder: self.instance_id.clone(),
+            expires_at: now + self.lease_seconds,
+        };
+        let json = serde_json::to_string(&lease)
+            .map_err(|e| ErrorArrayItem::new(Errors::JsonCreation, e.to_string()))?;
+        let temp_path = format!("{}.{}.tmp", self.path, std::process::id());
+        fs::write(&temp_path, json)?;
+        fs::rename(&temp_path, &self.path)?;
+
+        match self.read()? {
+            Some(lease) if lease.holder != self.instance_id => Ok(Leadership::Standby {
+                holder: lease.holder,
+            }),
+            _ => Ok(Leadership::Leader),
+        }
+    }
+
+    fn read(&self) -> Result<Option<Lease>, ErrorArrayItem> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        // A damaged lease is treated as expired and overwritten
+        Ok(serde_json::from_str(&content).ok())
+    }
+}
+
+// Host name and process id, unique per running instance
+fn default_instance_id() -> String {
+    let host = fs::read_to_string("/etc/hostname")
+        .ok()
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_else(|| String::from("unknown"));
+    format!("{}:{}", host, std::process::id())
+}
@@ -0,0 +1,50 @@
+pub mod alerts;
+pub mod archive;
+pub mod at_rest;
+pub mod bench;
+pub mod blackbox;
+pub mod changes;
+pub mod coalesce;
+pub mod config;
+pub mod connectivity;
+pub mod diagnose;
+pub mod doh;
+pub mod dual_stack;
+pub mod email_template;
+pub mod exit_code;
+pub mod fifo;
+pub mod health;
+pub mod health_score;
+pub mod heartbeat;
+pub mod history;
+pub mod hooks;
+pub mod http3;
+pub mod inventory;
+pub mod latency;
+pub mod leader;
+pub mod mailing;
+pub mod monitor;
+pub mod notifier;
+pub mod one_shot;
+pub mod pacing;
+pub mod pause;
+pub mod prefetch;
+pub mod report;
+pub mod results_db;
+pub mod retry_after;
+pub mod routing;
+pub mod s3;
+pub mod server;
+pub mod signing;
+pub mod site_state;
+pub mod sla;
+pub mod slo;
+pub mod snippet;
+pub mod stale;
+pub mod state;
+pub mod statsd;
+pub mod status_page;
+pub mod store;
+pub mod tls;
+pub mod unix_socket;
+pub mod webhook;
@@ -0,0 +1,40 @@
+use std::io::Write;
+
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use artisan_middleware::notifications::{Email, EmailSecure};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use dusa_collection_utils::errors::ErrorArrayItem;
+use dusa_collection_utils::stringy::Stringy;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+// Encrypt and deliver a single email. The relay protocol only carries a subject and
+// body, who receives it is decided by the relay, so there is no per-message recipient.
+pub fn send_email(subject: &str, body: String) -> Result<(), ErrorArrayItem> {
+    let email_data: Email = Email {
+        subject: Stringy::new(subject),
+        body: Stringy::from_string(body),
+    };
+
+    let secure_mail: EmailSecure = EmailSecure::new(email_data)?;
+    log!(LogLevel::Trace, "Encrypted report data");
+    secure_mail.send()
+}
+
+// The relay sends the body as the message text and has no notion of attachments, so
+// an oversized report travels in the body gzip compressed and base64 encoded, wrapped
+// at 76 columns like any other base64 text
+pub fn compressed_report(report: &str) -> Result<String, ErrorArrayItem> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(report.as_bytes())?;
+    let encoded = BASE64.encode(encoder.finish()?);
+    // Base64 is plain ASCII, so splitting on byte offsets is safe
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|chunk| std::str::from_utf8(chunk).unwrap_or_default())
+        .collect();
+    Ok(lines.join("\n"))
+}
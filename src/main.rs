@@ -1,22 +1,101 @@
 use artisan_middleware::config::AppConfig;
 use artisan_middleware::log;
 use artisan_middleware::logger::{get_log_level, set_log_level, LogLevel};
-use artisan_middleware::notifications::{Email, EmailSecure};
-use artisan_middleware::state_persistence::AppState;
-use artisan_middleware::{state_persistence::StatePersistence, timestamp::current_timestamp};
-use config::{get_config, load_settings, Settings};
+use artisan_middleware::state_persistence::{AppState, StatePersistence};
+use artisan_middleware::timestamp::current_timestamp;
+use chrono::Utc;
+use config::ConfigError;
 use dusa_collection_utils::errors::{ErrorArrayItem, Errors};
-use dusa_collection_utils::stringy::Stringy;
 use dusa_collection_utils::types::PathType;
-use reqwest::header::USER_AGENT;
-use reqwest::Client;
-use std::time::Duration;
-use tokio::time::Instant;
-mod config;
-mod mailing;
+use website_monitor::bench::bench;
+use website_monitor::blackbox;
+use website_monitor::config::{get_config, load_settings, Settings};
+use website_monitor::exit_code::ExitCode;
+use website_monitor::heartbeat::heartbeat_age;
+use website_monitor::inventory::load_external_urls;
+use website_monitor::monitor::{Monitor, MonitorBuilder, PROFILE_ENV};
+use website_monitor::state::{load_initial_state, update_state};
 
 #[tokio::main]
 async fn main() {
+    // Inspection mode, no state or logging setup needed
+    if std::env::args().any(|arg| arg == "--list-sites") {
+        match load_settings() {
+            Ok(settings) => list_sites(&settings, profile_arg().as_deref()).await,
+            Err(e) => {
+                log!(
+                    LogLevel::Error,
+                    "Error occoured while loading settings: {}",
+                    e
+                );
+                ExitCode::SettingsLoad.exit();
+            }
+        }
+        return;
+    }
+
+    // Watchdog mode for cron: exit non-zero when the heartbeat is stale
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(position) = args.iter().position(|arg| arg == "--check-heartbeat") {
+        check_heartbeat(args.get(position + 1), args.get(position + 2));
+        return;
+    }
+
+    // Migration helper: blackbox.yml and/or Prometheus probe jobs in, Config.toml out
+    if let Some(position) = args.iter().position(|arg| arg == "--import-blackbox") {
+        let files: Vec<&String> = args[position + 1..]
+            .iter()
+            .take_while(|arg| !arg.starts_with("--"))
+            .collect();
+        import_blackbox(&files);
+        return;
+    }
+
+    // Load test mode, nothing is recorded or sent
+    if let Some(url) = arg_value(&args, "--bench") {
+        let requests = arg_value(&args, "--requests")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(100);
+        let concurrency = arg_value(&args, "--concurrency")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(10);
+        match load_settings() {
+            Ok(settings) => {
+                println!(
+                    "Benchmarking {} with {} requests, {} at a time",
+                    url, requests, concurrency
+                );
+                print!("{}", bench(url, requests, concurrency, &settings.app).await);
+            }
+            Err(e) => {
+                log!(
+                    LogLevel::Error,
+                    "Error occoured while loading settings: {}",
+                    e
+                );
+                ExitCode::SettingsLoad.exit();
+            }
+        }
+        return;
+    }
+
+    // Batch mode for cron: run a fixed number of cycles, then exit
+    let cycles = if args.iter().any(|arg| arg == "--once") {
+        Some(1)
+    } else {
+        match arg_value(&args, "--cycles").map(|value| value.parse::<u64>()) {
+            Some(Ok(cycles)) if cycles > 0 => Some(cycles),
+            Some(_) => {
+                log!(
+                    LogLevel::Error,
+                    "--cycles needs a positive number of cycles"
+                );
+                ExitCode::SettingsLoad.exit();
+            }
+            None => None,
+        }
+    };
+
     // Initialization
     let config: AppConfig = get_config();
     let state_path: PathType = StatePersistence::get_state_path(&config);
@@ -36,7 +115,8 @@ async fn main() {
             state
                 .error_log
                 .push(ErrorArrayItem::new(Errors::InvalidFile, e.to_string()));
-            return;
+            update_state(&mut state, &state_path);
+            ExitCode::SettingsLoad.exit();
         }
     };
 
@@ -49,93 +129,172 @@ async fn main() {
         println!("{}", settings);
     };
 
-    state.is_active = true;
-    state.data = String::from("Website Monitor Initialized");
-    update_state(&mut state, &state_path);
-    simple_pretty::output("GREEN", "Website monitor running!");
+    // Onboarding check: one test message through every channel, then exit
+    if args.iter().any(|arg| arg == "--test-notify") {
+        let monitor = MonitorBuilder::new(config, settings)
+            .state(state, state_path)
+            .build();
+        test_notify(&build_or_exit(monitor)).await;
+        return;
+    }
 
-    loop {
-        // running health check
-        let results = run_health_checks(&settings.websites.urls).await;
-        let report = generate_report(&results);
+    let monitor = MonitorBuilder::new(config, settings)
+        .profile(profile_arg())
+        .cycles(cycles)
+        .state(state, state_path)
+        .build();
+    build_or_exit(monitor).run().await;
+}
 
-        let email_data: Email = Email {
-            subject: Stringy::new("Website Monitor Report"),
-            body: Stringy::from_string(report),
-        };
+// Settings the monitor can't start with end the process like any other settings error
+fn build_or_exit(monitor: Result<Monitor, ConfigError>) -> Monitor {
+    match monitor {
+        Ok(monitor) => monitor,
+        Err(e) => {
+            log!(LogLevel::Error, "{}", e);
+            ExitCode::SettingsLoad.exit();
+        }
+    }
+}
 
-        let secure_mail: EmailSecure = match EmailSecure::new(email_data) {
-            Ok(loaded_data) => {
-                log!(LogLevel::Trace, "Encrypted report data");
-                loaded_data
-            },
-            Err(e) => {
-                log!(LogLevel::Error, "Error occurred while preparing to send email: {}", e.to_string());
-                state.error_log.push(e);
-                update_state(&mut state, &state_path);
-                return;
-            },
-        };
+// --profile, then the environment, as the monitor itself picks it
+fn profile_arg() -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    arg_value(&args, "--profile")
+        .map(|profile| profile.to_string())
+        .or_else(|| std::env::var(PROFILE_ENV).ok())
+        .filter(|profile| !profile.is_empty())
+}
 
-        if let Err(err) = secure_mail.send() {
-            log!(LogLevel::Error, "Error occurred while preparing to send email: {}", err.to_string());
-            state.error_log.push(err);
-            update_state(&mut state, &state_path);
-        };
+// Print every site with its effective settings, external inventory included
+async fn list_sites(settings: &Settings, profile: Option<&str>) {
+    let mut app = settings.app.clone();
+    let websites = match settings.active_profile(profile, Utc::now()) {
+        Some((name, active)) => {
+            println!("Profile: {}", name);
+            app.interval_seconds = active.interval_seconds.unwrap_or(app.interval_seconds);
+            active.websites.as_ref().unwrap_or(&settings.websites)
+        }
+        None if profile.is_some() => {
+            log!(
+                LogLevel::Error,
+                "Unknown profile {}",
+                profile.unwrap_or_default()
+            );
+            ExitCode::SettingsLoad.exit();
+        }
+        None => &settings.websites,
+    };
+    let sites = match load_external_urls(websites).await {
+        Some(Ok(urls)) => websites.all_sites_with(&urls),
+        Some(Err(e)) => {
+            log!(LogLevel::Warn, "{}, listing configured sites only", e);
+            websites.all_sites()
+        }
+        None => websites.all_sites(),
+    };
 
-        state.event_counter = state.event_counter + 1;
-        update_state(&mut state, &state_path);
-        tokio::time::sleep(Duration::from_secs(settings.app.interval_seconds)).await;
+    for (index, site) in sites.iter().enumerate() {
+        println!("{}. {}", index + 1, site.describe(&app));
     }
 }
 
-// Load initial state, creating a new state if necessary
-fn load_initial_state(config: &AppConfig, state_path: &PathType) -> AppState {
-    match StatePersistence::load_state(state_path) {
-        Ok(loaded_data) => {
-            log!(LogLevel::Info, "Previous state data loaded");
-            loaded_data
+// The argument following `flag`, if both are present
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let position = args.iter().position(|arg| arg == flag)?;
+    args.get(position + 1).map(|value| value.as_str())
+}
+
+fn import_blackbox(files: &[&String]) {
+    if files.is_empty() {
+        log!(
+            LogLevel::Error,
+            "Usage: website_monitor --import-blackbox <blackbox.yml> [prometheus.yml]"
+        );
+        ExitCode::SettingsLoad.exit();
+    }
+
+    let mut documents = Vec::new();
+    for file in files {
+        match std::fs::read_to_string(file) {
+            Ok(document) => documents.push(document),
+            Err(e) => {
+                log!(LogLevel::Error, "Cannot read {}: {}", file, e);
+                ExitCode::SettingsLoad.exit();
+            }
         }
-        Err(_) => {
-            log!(
-                LogLevel::Warn,
-                "No previous state file found, creating a new one"
-            );
-            let state = get_initial_state(config);
-            if let Err(err) = StatePersistence::save_state(&state, state_path) {
-                log!(
-                    LogLevel::Error,
-                    "Error occurred while saving new state: {}",
-                    err
-                );
+    }
+
+    match blackbox::import(&documents) {
+        Ok((config, warnings)) => {
+            // As comments, so the output stays a valid config when redirected to a file
+            for warning in warnings {
+                println!("# {}", warning);
             }
-            state
+            print!("{}", config);
+        }
+        Err(e) => {
+            log!(LogLevel::Error, "{}", e);
+            ExitCode::SettingsLoad.exit();
         }
     }
 }
 
-// Create an initial state
-fn get_initial_state(config: &AppConfig) -> AppState {
-    AppState {
-        data: String::new(),
-        last_updated: current_timestamp(),
-        event_counter: 0,
-        is_active: false,
-        error_log: vec![],
-        config: config.clone(),
+async fn test_notify(monitor: &Monitor) {
+    let outcomes = monitor.test_notify().await;
+    if outcomes.is_empty() {
+        log!(LogLevel::Error, "No notifiers are configured");
+        ExitCode::TestNotify.exit();
+    }
+
+    let mut failed = 0;
+    for (name, outcome) in &outcomes {
+        match outcome {
+            Ok(()) => println!("{}: sent", name),
+            Err(e) => {
+                println!("{}: failed, {}", name, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        log!(
+            LogLevel::Error,
+            "{} of {} notifiers failed",
+            failed,
+            outcomes.len()
+        );
+        ExitCode::TestNotify.exit();
     }
 }
 
-// Update state and persist it to disk
-fn update_state(state: &mut AppState, path: &PathType) {
-    state.last_updated = current_timestamp();
-    if let Err(err) = StatePersistence::save_state(state, path) {
-        log!(LogLevel::Error, "Failed to save state: {}", err);
-        state.is_active = false;
-        state.error_log.push(ErrorArrayItem::new(
-            Errors::GeneralError,
-            format!("{}", err),
-        ));
+fn check_heartbeat(path: Option<&String>, max_age: Option<&String>) {
+    let (path, max_age) = match (path, max_age.and_then(|age| age.parse::<u64>().ok())) {
+        (Some(path), Some(max_age)) => (path, max_age),
+        _ => {
+            log!(
+                LogLevel::Error,
+                "Usage: website_monitor --check-heartbeat <path> <max_age_seconds>"
+            );
+            ExitCode::StaleHeartbeat.exit();
+        }
+    };
+
+    match heartbeat_age(path, current_timestamp()) {
+        Ok(age) if age <= max_age => println!("Heartbeat is {}s old", age),
+        Ok(age) => {
+            log!(
+                LogLevel::Error,
+                "Heartbeat is {}s old, more than the allowed {}s",
+                age,
+                max_age
+            );
+            ExitCode::StaleHeartbeat.exit();
+        }
+        Err(e) => {
+            log!(LogLevel::Error, "{}", e);
+            ExitCode::StaleHeartbeat.exit();
+        }
     }
 }
 
@@ -150,135 +309,3 @@ fn configure_logging(config: &AppConfig, state: &mut AppState, state_path: &Path
     state.config.debug_mode = config.debug_mode;
     update_state(state, state_path);
 }
-
-use std::collections::HashMap;
-
-async fn run_health_checks(urls: &[String]) -> HashMap<String, HealthCheckResult> {
-    let mut results = HashMap::new();
-
-    for url in urls {
-        let result = check_website_health(url).await;
-        results.insert(url.clone(), result);
-        tokio::time::sleep(Duration::from_nanos(500)).await;
-    }
-
-    results
-}
-
-#[derive(Debug, Clone)]
-pub struct HealthCheckResult {
-    pub status: String,
-    pub dns_time_ms: Option<u128>,
-    pub response_time_ms: Option<u128>,
-    pub body_time_ms: Option<u128>,
-    pub error: Option<String>,
-}
-
-fn generate_report(results: &HashMap<String, HealthCheckResult>) -> String {
-    let mut report = String::from("Website Health Check Report:\n\n");
-    let mut total_up = 0;
-    let mut total_down = 0;
-
-    for (url, result) in results {
-        report.push_str(&format!("URL: {}\n", url));
-        report.push_str(&format!("  Status: {}\n", result.status));
-
-        if result.status == "UP" {
-            report.push_str(&format!(
-                "  DNS & Request Time: {} ms\n",
-                result.dns_time_ms.unwrap_or(0)
-            ));
-            report.push_str(&format!(
-                "  Total Response Time: {} ms\n",
-                result.response_time_ms.unwrap_or(0)
-            ));
-            report.push_str(&format!(
-                "  Body Read Time: {} ms\n",
-                result.body_time_ms.unwrap_or(0)
-            ));
-            total_up += 1;
-        } else {
-            report.push_str(&format!(
-                "  Error: {}\n",
-                result.error.as_deref().unwrap_or("Unknown error")
-            ));
-            total_down += 1;
-        }
-
-        report.push_str("\n");
-    }
-
-    report.push_str(&format!(
-        "\nSummary:\n  Total Websites Checked: {}\n  Total UP: {}\n  Total DOWN: {}\n\n",
-        results.len(),
-        total_up,
-        total_down
-    ));
-
-    report
-}
-
-async fn check_website_health(url: &str) -> HealthCheckResult {
-    let client = Client::builder().timeout(Duration::from_secs(30)).build();
-
-    match client {
-        Ok(client) => {
-            let start_time = Instant::now();
-            let dns_start = Instant::now();
-
-            match client
-                .get(url)
-                .header(USER_AGENT, "HealthChecker/1.0")
-                .send()
-                .await
-            {
-                Ok(response) => {
-                    let dns_duration: u128 = dns_start.elapsed().as_millis();
-                    let response_time: u128 = start_time.elapsed().as_millis();
-                    let body_start: Instant = Instant::now();
-
-                    match response.text().await {
-                        Ok(_) => {
-                            let body_duration = body_start.elapsed().as_millis();
-                            HealthCheckResult {
-                                status: "UP".to_string(),
-                                dns_time_ms: Some(dns_duration),
-                                response_time_ms: Some(response_time),
-                                body_time_ms: Some(body_duration),
-                                error: None,
-                            }
-                        }
-                        Err(e) => {
-                            log!(
-                                LogLevel::Warn,
-                                "Error calculating body time: {}",
-                                e.to_string()
-                            );
-                            HealthCheckResult {
-                                status: "DOWN".to_string(),
-                                dns_time_ms: Some(dns_duration),
-                                response_time_ms: Some(response_time),
-                                body_time_ms: None,
-                                error: Some(e.to_string()),
-                            }
-                        }
-                    }
-                }
-                Err(e) => HealthCheckResult {
-                    status: "DOWN".to_string(),
-                    dns_time_ms: None,
-                    response_time_ms: None,
-                    body_time_ms: None,
-                    error: Some(e.to_string()),
-                },
-            }
-        }
-        Err(e) => HealthCheckResult {
-            status: "DOWN".to_string(),
-            dns_time_ms: None,
-            response_time_ms: None,
-            body_time_ms: None,
-            error: Some(e.to_string()),
-        },
-    }
-}
@@ -0,0 +1,1172 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use artisan_middleware::config::AppConfig;
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use artisan_middleware::state_persistence::{AppState, StatePersistence};
+use artisan_middleware::timestamp::current_timestamp;
+use chrono::Utc;
+use config::ConfigError;
+use dusa_collection_utils::errors::{ErrorArrayItem, Errors};
+use dusa_collection_utils::types::PathType;
+use tokio::task::JoinSet;
+
+use crate::alerts;
+use crate::archive::ReportArchive;
+use crate::at_rest::AtRest;
+use crate::changes::{compute_changes, CycleChanges};
+use crate::coalesce;
+use crate::config::{ReportFormat, Settings, SiteConfig, WebsiteConfig};
+use crate::connectivity;
+use crate::email_template::{template_data, EmailTemplate};
+use crate::exit_code::ExitCode;
+use crate::fifo::FifoOutput;
+use crate::health::{run_health_checks, CheckStatus, HealthCheckResult};
+use crate::health_score::{update_health_score, update_uptime, HealthScore};
+use crate::heartbeat::Heartbeat;
+use crate::history::History;
+use crate::hooks;
+use crate::inventory::load_external_urls;
+use crate::latency::{apply_percentile_limits, observe_histograms, Histogram};
+use crate::leader::{LeaderLease, Leadership};
+use crate::notifier::{CycleSummary, EmailNotifier, Notifier, WebhookNotifier};
+use crate::one_shot;
+use crate::pacing::DomainPacer;
+use crate::pause::{PauseFlag, PauseMode};
+use crate::report::{
+    format_timestamp, generate_digest, generate_report, generate_report_markdown, ReportOptions,
+};
+use crate::results_db::ResultsDb;
+use crate::routing;
+use crate::s3::S3Uploader;
+use crate::server::{self, SharedStatus, StatusSnapshot};
+use crate::site_state::SiteStates;
+use crate::sla::update_sla;
+use crate::slo::update_slo;
+use crate::stale::mark_stale;
+use crate::statsd::StatsdEmitter;
+use crate::status_page::StatusPage;
+use crate::store::{load_or_create_state, FileStore, StateStore};
+
+/// Assembles a [`Monitor`] from its configuration and notification channels.
+///
+/// The built-in email notifier is registered by default; call
+/// [`MonitorBuilder::without_email`] to rely solely on custom notifiers.
+pub struct MonitorBuilder {
+    config: AppConfig,
+    settings: Settings,
+    state: Option<(AppState, PathType)>,
+    notifiers: Vec<Box<dyn Notifier>>,
+    email: bool,
+    profile: Option<String>,
+    cycles: Option<u64>,
+    store: Option<Box<dyn StateStore>>,
+}
+
+// Picks a profile when no --profile flag was given
+pub const PROFILE_ENV: &str = "WEBSITE_MONITOR_PROFILE";
+
+impl MonitorBuilder {
+    pub fn new(config: AppConfig, settings: Settings) -> Self {
+        MonitorBuilder {
+            config,
+            settings,
+            state: None,
+            notifiers: Vec::new(),
+            email: true,
+            profile: None,
+            cycles: None,
+            store: None,
+        }
+    }
+
+    /// Keep state and history in this store instead of the default [`FileStore`].
+    pub fn store(mut self, store: Box<dyn StateStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Use an already loaded state instead of loading it during `build`.
+    ///
+    /// With the default store, `state_path` is also where it is saved.
+    pub fn state(mut self, state: AppState, state_path: PathType) -> Self {
+        self.state = Some((state, state_path));
+        self
+    }
+
+    /// Register an additional notification channel.
+    pub fn notifier(mut self, notifier: Box<dyn Notifier>) -> Self {
+        self.notifiers.push(notifier);
+        self
+    }
+
+    /// Register several additional notification channels.
+    pub fn notifiers(mut self, notifiers: Vec<Box<dyn Notifier>>) -> Self {
+        self.notifiers.extend(notifiers);
+        self
+    }
+
+    /// Always use the named profile instead of following profile schedules.
+    ///
+    /// Falls back to the `WEBSITE_MONITOR_PROFILE` environment variable when
+    /// not called.
+    pub fn profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Run this many cycles and return from [`Monitor::run`] instead of running
+    /// forever, e.g. when started from cron.
+    pub fn cycles(mut self, cycles: Option<u64>) -> Self {
+        self.cycles = cycles;
+        self
+    }
+
+    /// Don't register the built-in email notifier.
+    pub fn without_email(mut self) -> Self {
+        self.email = false;
+        self
+    }
+
+    /// Set up every channel and output, failing on settings that can't be used
+    /// (at_rest key, email template, webhook, unknown profile).
+    pub fn build(self) -> Result<Monitor, ConfigError> {
+        let at_rest = AtRest::new(self.settings.app.at_rest.as_ref())
+            .map_err(|e| ConfigError::Message(format!("Invalid at_rest settings: {}", e)))?;
+        let store: Box<dyn StateStore> = match self.store {
+            Some(store) => store,
+            None => Box::new(FileStore::new(
+                match &self.state {
+                    Some((_, state_path)) => state_path.clone(),
+                    None => StatePersistence::get_state_path(&self.config),
+                },
+                SiteStates::get_path(&self.config),
+                self.settings.app.history_path.clone(),
+                at_rest,
+            )),
+        };
+        let state = match self.state {
+            Some((state, _)) => state,
+            None => load_or_create_state(&self.config, store.as_ref()),
+        };
+        let mut site_states = match store.load_site_states() {
+            Ok(site_states) => site_states.unwrap_or_default(),
+            Err(err) => {
+                log!(
+                    LogLevel::Warn,
+                    "Failed to load site state from the {} store, starting fresh: {}",
+                    store.name(),
+                    err
+                );
+                SiteStates::default()
+            }
+        };
+        one_shot::prune(&mut site_states.fired_one_shots, &self.settings.one_shots);
+
+        let mut notifiers = self.notifiers;
+        if self.email {
+            let email = match &self.settings.email_template {
+                Some(config) => {
+                    let template = EmailTemplate::load(config).map_err(|e| {
+                        ConfigError::Message(format!("Invalid email template settings: {}", e))
+                    })?;
+                    EmailNotifier::with_template(template)
+                }
+                None => EmailNotifier::new(),
+            };
+            let email = match self.settings.app.email_report_max_bytes {
+                Some(max_bytes) => email.limiting_reports(max_bytes),
+                None => email,
+            };
+            notifiers.insert(0, Box::new(email));
+        }
+        if let Some(webhook) = &self.settings.webhook {
+            let notifier = WebhookNotifier::new(webhook)
+                .map_err(|e| ConfigError::Message(format!("Invalid webhook settings: {}", e)))?;
+            notifiers.push(Box::new(notifier));
+        }
+        let mut recipients = BTreeMap::new();
+        for (name, webhook) in &self.settings.recipients {
+            let notifier = WebhookNotifier::new(webhook).map_err(|e| {
+                ConfigError::Message(format!("Invalid settings for recipients {}: {}", name, e))
+            })?;
+            recipients.insert(name.clone(), notifier);
+        }
+
+        let settings = self.settings;
+        let statsd = settings.app.statsd_addr.as_ref().and_then(|addr| {
+            match StatsdEmitter::new(
+                addr,
+                &settings.app.statsd_prefix,
+                settings.app.statsd_dogstatsd,
+            ) {
+                Ok(emitter) => Some(emitter),
+                Err(e) => {
+                    log!(LogLevel::Error, "Failed to set up statsd emitter: {}", e);
+                    None
+                }
+            }
+        });
+
+        let report_archive = settings.app.report_archive_path.as_ref().map(|path| {
+            ReportArchive::new(
+                path,
+                settings.app.report_archive_max_bytes,
+                settings.app.report_archive_keep,
+                settings.app.report_timezone,
+            )
+        });
+
+        let history = History::new(
+            settings.app.history_sample_every,
+            settings.app.history_min_interval_seconds,
+        );
+
+        let results_db = settings.app.sqlite_path.as_deref().map(ResultsDb::new);
+        let heartbeat = settings.app.heartbeat_path.as_deref().map(Heartbeat::new);
+        let fifo = settings
+            .app
+            .fifo_path
+            .as_deref()
+            .and_then(|path| match FifoOutput::new(path) {
+                Ok(fifo) => Some(fifo),
+                Err(e) => {
+                    log!(LogLevel::Error, "Failed to set up FIFO output: {}", e);
+                    None
+                }
+            });
+        let leader = settings.leader.as_ref().map(LeaderLease::new);
+        if let Some(leader) = &leader {
+            log!(
+                LogLevel::Info,
+                "Leader election on, this instance is {}",
+                leader.instance_id()
+            );
+        }
+        let status_page = settings
+            .status_page
+            .as_ref()
+            .map(|status_page| StatusPage::new(status_page, settings.app.report_timezone));
+
+        let s3 = settings
+            .s3
+            .clone()
+            .and_then(|s3| match S3Uploader::new(s3) {
+                Ok(uploader) => Some(uploader),
+                Err(e) => {
+                    log!(LogLevel::Error, "Failed to set up S3 uploads: {}", e);
+                    None
+                }
+            });
+
+        let profile = self
+            .profile
+            .or_else(|| std::env::var(PROFILE_ENV).ok())
+            .filter(|profile| !profile.is_empty());
+        if let Some(profile) = &profile {
+            if !settings.profiles.contains_key(profile) {
+                return Err(ConfigError::Message(format!("Unknown profile {}", profile)));
+            }
+        }
+
+        let pacer = DomainPacer::new(settings.app.min_interval_per_domain_seconds);
+        let pause = PauseFlag::load(PauseFlag::get_path(&self.config));
+        if pause.is_paused() {
+            log!(
+                LogLevel::Warn,
+                "Monitoring is paused from a previous run, POST /resume to continue"
+            );
+        }
+
+        let mut monitor = Monitor {
+            sites: Vec::new(),
+            settings,
+            profile,
+            active_profile: None,
+            cycles: self.cycles,
+            state,
+            store,
+            site_states,
+            statsd,
+            report_archive,
+            history,
+            results_db,
+            heartbeat,
+            fifo,
+            status_page,
+            leader,
+            histograms: HashMap::new(),
+            notifiers,
+            recipients,
+            status: SharedStatus::default(),
+            s3,
+            pacer,
+            consecutive_send_errors: 0,
+            consecutive_save_errors: 0,
+            pause,
+        };
+        monitor.apply_profile();
+        Ok(monitor)
+    }
+}
+
+/// Runs health check cycles and delivers their reports.
+pub struct Monitor {
+    settings: Settings,
+    // Profile forced by name, otherwise schedules decide
+    profile: Option<String>,
+    active_profile: Option<String>,
+    // Stop after this many cycles, None runs until the process is stopped
+    cycles: Option<u64>,
+    sites: Vec<SiteConfig>,
+    state: AppState,
+    store: Box<dyn StateStore>,
+    site_states: SiteStates,
+    statsd: Option<StatsdEmitter>,
+    report_archive: Option<ReportArchive>,
+    history: History,
+    results_db: Option<ResultsDb>,
+    heartbeat: Option<Heartbeat>,
+    fifo: Option<FifoOutput>,
+    status_page: Option<StatusPage>,
+    leader: Option<LeaderLease>,
+    // Per-site response time histograms for /metrics, kept in memory only
+    histograms: HashMap<String, Histogram>,
+    notifiers: Vec<Box<dyn Notifier>>,
+    // Channels of the recipient groups sites can route their results to
+    recipients: BTreeMap<String, WebhookNotifier>,
+    status: SharedStatus,
+    s3: Option<S3Uploader>,
+    pacer: DomainPacer,
+    // Failed sends and failed saves in a row, counted apart so a working disk
+    // doesn't hide a relay that never delivers, or the other way around
+    consecutive_send_errors: u32,
+    consecutive_save_errors: u32,
+    pause: PauseFlag,
+}
+
+impl Monitor {
+    // Switch to the profile that should be active now. A switch replaces the site
+    // list right away, the new interval applies from the following wait.
+    fn apply_profile(&mut self) {
+        let active = self
+            .settings
+            .active_profile(self.profile.as_deref(), Utc::now())
+            .map(|(name, _)| name.clone());
+        if active == self.active_profile && !self.sites.is_empty() {
+            return;
+        }
+        if active != self.active_profile {
+            log!(
+                LogLevel::Info,
+                "Switching to {}",
+                active
+                    .as_ref()
+                    .map(|name| format!("profile {}", name))
+                    .unwrap_or_else(|| String::from("the base settings"))
+            );
+        }
+        self.active_profile = active;
+        self.sites = self.websites().all_sites();
+    }
+
+    // The site list in effect, the active profile's or the base one
+    fn websites(&self) -> &WebsiteConfig {
+        self.active_profile
+            .as_ref()
+            .and_then(|name| self.settings.profiles.get(name))
+            .and_then(|profile| profile.websites.as_ref())
+            .unwrap_or(&self.settings.websites)
+    }
+
+    fn interval_seconds(&self) -> u64 {
+        self.active_profile
+            .as_ref()
+            .and_then(|name| self.settings.profiles.get(name))
+            .and_then(|profile| profile.interval_seconds)
+            .unwrap_or(self.settings.app.interval_seconds)
+    }
+
+    // Pick up changes to the external url inventory, keeping the last good list on failure
+    async fn refresh_sites(&mut self) {
+        match load_external_urls(self.websites()).await {
+            Some(Ok(urls)) => self.sites = self.websites().all_sites_with(&urls),
+            Some(Err(e)) => {
+                log!(LogLevel::Error, "{}, keeping the last known site list", e);
+                self.state
+                    .error_log
+                    .push(ErrorArrayItem::new(Errors::InputOutput, e));
+            }
+            None => {}
+        }
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Send a clearly marked test message through every registered notifier,
+    /// returning each notifier's name with the outcome of its delivery.
+    ///
+    /// Nothing is recorded in the application state, so it can be used to
+    /// check the notification settings without affecting a running monitor.
+    pub async fn test_notify(&self) -> Vec<(String, Result<(), ErrorArrayItem>)> {
+        let body = format!(
+            "This is a test notification from the Website Monitor, sent at {} to check that alerts reach this channel.\n\nNo site is down and no action is needed.",
+            format_timestamp(Utc::now(), self.settings.app.report_timezone)
+        );
+        let mut outcomes = Vec::new();
+        for notifier in &self.notifiers {
+            let outcome = notifier
+                .notify_cycle(
+                    "[TEST] Website Monitor Test Notification",
+                    &body,
+                    &CycleSummary::default(),
+                )
+                .await;
+            outcomes.push((notifier.name().to_string(), outcome));
+        }
+        outcomes
+    }
+
+    /// The latest cycle's results, shared with the status server.
+    pub fn status(&self) -> SharedStatus {
+        self.status.clone()
+    }
+
+    /// Check every site forever, sleeping `interval_seconds` between cycles.
+    pub async fn run(mut self) {
+        self.state.is_active = true;
+        self.state.data = String::from("Website Monitor Initialized");
+        self.save_state();
+        if let Some(server) = &self.settings.server {
+            tokio::spawn(server::serve(
+                server.listen,
+                self.status.clone(),
+                self.pause.clone(),
+                Arc::new(self.settings.redacted_json()),
+                self.status_page.clone().filter(|_| {
+                    self.settings
+                        .status_page
+                        .as_ref()
+                        .is_some_and(|status_page| status_page.serve)
+                }),
+            ));
+        }
+        simple_pretty::output("GREEN", "Website monitor running!");
+
+        let mut completed: u64 = 0;
+        loop {
+            self.beat();
+            self.run_one_shots().await;
+            self.run_cycle().await;
+            completed += 1;
+            if self.cycles.is_some_and(|cycles| completed >= cycles) {
+                break;
+            }
+            self.wait_for_next_cycle().await;
+        }
+
+        // Site state and the event counter were saved by the last cycle, the next
+        // invocation carries on from them
+        log!(LogLevel::Info, "Completed {} cycles, exiting", completed);
+        self.state.is_active = false;
+        self.state.data = format!("Website Monitor exited after {} cycles", completed);
+        self.save_state();
+    }
+
+    // Sleep out the interval, in heartbeat sized steps when there is a heartbeat to keep,
+    // waking up for one-shot checks that fall due in between
+    async fn wait_for_next_cycle(&mut self) {
+        let interval = self.interval_seconds();
+        let step = match self.heartbeat {
+            Some(_) => self.settings.app.heartbeat_interval_seconds.max(1),
+            None => interval,
+        };
+        let mut waited = 0;
+        while waited < interval {
+            let mut sleep = step.min(interval - waited);
+            if let Some(until_due) = one_shot::seconds_until_next(
+                &self.settings.one_shots,
+                &self.site_states.fired_one_shots,
+                Utc::now(),
+            ) {
+                sleep = sleep.min(until_due.max(1));
+            }
+            tokio::time::sleep(Duration::from_secs(sleep)).await;
+            waited += sleep;
+            self.beat();
+            self.run_one_shots().await;
+        }
+    }
+
+    // Run the one-shot checks that are due and send their report, each only once.
+    // While paused or on standby they stay pending.
+    async fn run_one_shots(&mut self) {
+        let due: Vec<_> = one_shot::due(
+            &self.settings.one_shots,
+            &self.site_states.fired_one_shots,
+            Utc::now(),
+        )
+        .into_iter()
+        .cloned()
+        .collect();
+        if due.is_empty() {
+            return;
+        }
+        if self.pause.is_paused() {
+            log!(
+                LogLevel::Info,
+                "Monitoring paused, holding back {} one-shot checks",
+                due.len()
+            );
+            return;
+        }
+        if self.acquire_leadership() == Some(false) {
+            log!(
+                LogLevel::Info,
+                "Standby instance, leaving one-shot checks to the leader"
+            );
+            return;
+        }
+
+        for one_shot in due {
+            log!(
+                LogLevel::Info,
+                "Running one-shot check {}",
+                one_shot.label()
+            );
+            let sites: Vec<SiteConfig> = one_shot
+                .urls
+                .iter()
+                .map(|url| {
+                    self.sites
+                        .iter()
+                        .find(|site| site.url == *url || site.resolved_url() == *url)
+                        .cloned()
+                        .unwrap_or_else(|| SiteConfig::from_url(url))
+                })
+                .collect();
+            let results = run_health_checks(&sites, &self.settings.app, &mut self.pacer).await;
+            let mut alerts =
+                alerts::check_tls_versions(&results, self.settings.app.min_tls_version);
+            alerts.extend(alerts::check_tls_findings(&results));
+            alerts.extend(alerts::check_ipv6(&results));
+            alerts.extend(alerts::check_redirect_hops(
+                &sites,
+                &results,
+                self.settings.app.max_redirect_hops,
+            ));
+            let changes = CycleChanges::default();
+            let report = self
+                .render_report(&sites, &results, &alerts, &changes, None)
+                .concat();
+
+            let mut summary = CycleSummary::from_results(&sites, &results);
+            summary.report = true;
+            summary.template_data = self.settings.email_template.as_ref().map(|_| {
+                let mut data = template_data(
+                    &sites,
+                    &results,
+                    &alerts,
+                    &changes,
+                    None,
+                    self.settings.app.report_timezone,
+                );
+                data["report"] = serde_json::json!(report);
+                data
+            });
+            let subject = format!("Website Monitor One-Shot Check: {}", one_shot.label());
+            let delivered = self.notify(&subject, &report, &summary).await;
+            self.record_send(delivered);
+            self.site_states.fired_one_shots.push(one_shot.id());
+        }
+
+        if let Err(err) = self.store.save_site_states(&self.site_states) {
+            log!(LogLevel::Error, "Failed to save site state: {}", err);
+            self.state.error_log.push(err);
+            self.record_save(false);
+        }
+    }
+
+    // The report in the configured format, split into parts per max_sites_per_email
+    fn render_report(
+        &self,
+        sites: &[SiteConfig],
+        results: &HashMap<String, HealthCheckResult>,
+        alerts: &[alerts::Alert],
+        changes: &CycleChanges,
+        health_score: Option<&HealthScore>,
+    ) -> Vec<String> {
+        let options = ReportOptions {
+            group_by_priority: self.settings.app.report_group_by_priority,
+            group_errors: self.settings.app.report_group_errors,
+            timezone: self.settings.app.report_timezone,
+            max_sites_per_part: self.settings.app.max_sites_per_email,
+        };
+        match self.settings.app.report_format {
+            ReportFormat::Text => {
+                generate_report(sites, results, alerts, changes, health_score, &options)
+            }
+            ReportFormat::Markdown => {
+                generate_report_markdown(sites, results, alerts, changes, health_score, &options)
+            }
+        }
+    }
+
+    // A missed beat is only logged, the watchdog will notice if it keeps happening
+    fn beat(&self) {
+        if let Some(heartbeat) = &self.heartbeat {
+            if let Err(err) = heartbeat.beat() {
+                log!(LogLevel::Warn, "Failed to write heartbeat: {}", err);
+            }
+        }
+    }
+
+    /// Run a single check cycle: check, alert, report and persist.
+    pub async fn run_cycle(&mut self) {
+        let paused = self.pause.is_paused();
+        if paused && self.settings.app.pause_mode == PauseMode::Stop {
+            log!(LogLevel::Info, "Monitoring paused, skipping this cycle");
+            return;
+        }
+        if let Some(probe_url) = &self.settings.app.connectivity_probe_url {
+            if let Err(e) = connectivity::probe(probe_url).await {
+                log!(
+                    LogLevel::Warn,
+                    "No local connectivity ({}), skipping this cycle",
+                    e
+                );
+                let snapshot = match self.status.write() {
+                    Ok(mut status) => {
+                        status.skipped = Some(format!("no local connectivity: {}", e));
+                        status.clone()
+                    }
+                    Err(_) => return,
+                };
+                if let Some(status_page) = &self.status_page {
+                    if let Err(err) = status_page.write(&snapshot) {
+                        log!(LogLevel::Error, "Failed to write status page: {}", err);
+                    }
+                }
+                return;
+            }
+        }
+        self.apply_profile();
+        self.refresh_sites().await;
+
+        // running health check
+        let mut results = run_health_checks(&self.sites, &self.settings.app, &mut self.pacer).await;
+        apply_percentile_limits(
+            &self.sites,
+            &mut results,
+            &mut self.site_states,
+            self.settings.app.latency_window,
+        );
+        mark_stale(
+            &mut results,
+            &mut self.site_states,
+            self.settings.app.stale_after_seconds,
+            current_timestamp(),
+        );
+        if let Some(statsd) = &self.statsd {
+            statsd.emit(&results);
+        }
+        observe_histograms(
+            &results,
+            &mut self.histograms,
+            &self.settings.app.metrics_histogram_buckets,
+        );
+        let mut alerts = alerts::check_size_growth(
+            &results,
+            &mut self.site_states,
+            self.settings.app.size_alert_bytes,
+        );
+        alerts.extend(alerts::check_canaries(
+            &self.sites,
+            &results,
+            &mut self.site_states,
+        ));
+        alerts.extend(alerts::check_dnssec(
+            &self.sites,
+            &results,
+            &mut self.site_states,
+        ));
+        alerts.extend(alerts::check_watched_headers(
+            &self.sites,
+            &results,
+            &mut self.site_states,
+            self.settings.app.watch_header.as_deref(),
+        ));
+        alerts.extend(alerts::check_tls_versions(
+            &results,
+            self.settings.app.min_tls_version,
+        ));
+        alerts.extend(alerts::check_tls_findings(&results));
+        alerts.extend(alerts::check_ipv6(&results));
+        alerts.extend(alerts::check_redirect_hops(
+            &self.sites,
+            &results,
+            self.settings.app.max_redirect_hops,
+        ));
+        alerts.extend(update_sla(
+            &self.sites,
+            &mut results,
+            &mut self.site_states,
+            self.settings.app.sla_window_days,
+            self.settings.app.sla_hysteresis_percent,
+            current_timestamp(),
+        ));
+        alerts.extend(update_slo(
+            &self.sites,
+            &mut results,
+            &mut self.site_states,
+            self.settings.app.slo_window_days,
+            self.settings.app.slo_burn_window_hours,
+            current_timestamp(),
+        ));
+        for site in &self.sites {
+            let url = site.resolved_url();
+            if let Some(error) = results
+                .get(&url)
+                .filter(|result| site.is_non_alerting(result))
+                .and_then(|result| result.error.as_ref())
+            {
+                log!(
+                    LogLevel::Info,
+                    "{}: not alerting on {} failure: {}",
+                    url,
+                    error.category(),
+                    error
+                );
+            }
+        }
+        let changes = compute_changes(
+            &self.sites,
+            &results,
+            &mut self.site_states,
+            self.settings.app.latency_change_percent,
+            self.settings.app.recovery_confirm_cycles,
+            self.settings.app.alert_dedup_key,
+        );
+        alerts.extend(alerts::check_pending_recoveries(&changes));
+        let leader = self.acquire_leadership();
+        if paused {
+            if !changes.transitions.is_empty() {
+                log!(
+                    LogLevel::Info,
+                    "Monitoring paused, not running on_change commands for {} transitions",
+                    changes.transitions.len()
+                );
+            }
+        } else if leader != Some(false) {
+            self.run_on_change_commands(&changes).await;
+        }
+        if self.settings.app.alert_coalesce_seconds.is_some() {
+            coalesce::queue_events(&changes, &mut self.site_states, current_timestamp());
+        }
+        let health_score = update_health_score(
+            &self.sites,
+            &results,
+            &mut self.site_states,
+            self.settings.app.health_score_window,
+        );
+        let uptime_percent = update_uptime(
+            &results,
+            &mut self.site_states,
+            self.settings.app.health_score_window,
+        );
+        let records = self
+            .history
+            .sample(&results, &mut self.site_states, current_timestamp());
+        if let Err(err) = self.store.append_history(&records) {
+            log!(LogLevel::Error, "Failed to record history: {}", err);
+            self.state.error_log.push(err);
+        }
+        if let Some(results_db) = &self.results_db {
+            if let Err(err) = results_db.record(&results, current_timestamp()) {
+                log!(
+                    LogLevel::Error,
+                    "Failed to write results to SQLite: {}",
+                    err
+                );
+                self.state.error_log.push(err);
+            }
+        }
+        if let Some(fifo) = &mut self.fifo {
+            fifo.write(&results, current_timestamp());
+        }
+
+        let report_parts = self.render_report(
+            &self.sites,
+            &results,
+            &alerts,
+            &changes,
+            health_score.as_ref(),
+        );
+        let report = report_parts.concat();
+        let severity = alerts::cycle_severity(&self.sites, &results, &alerts);
+
+        if let Some(report_archive) = &self.report_archive {
+            if let Err(err) = report_archive.append(&report) {
+                log!(LogLevel::Error, "Failed to archive report: {}", err);
+                self.state.error_log.push(err);
+            }
+        }
+
+        // Sites routed only to their own recipients are left out of what the global
+        // channels get, the archive keeps the full report
+        let global_sites = routing::global_sites(&self.sites);
+        let global = routing::scope(&global_sites, &results, &alerts, &changes);
+        let global_parts = if global_sites.len() == self.sites.len() {
+            report_parts.clone()
+        } else {
+            self.render_report(
+                &global_sites,
+                &global.results,
+                &global.alerts,
+                &global.changes,
+                health_score.as_ref(),
+            )
+        };
+        let summary = CycleSummary::from_results(&global_sites, &global.results);
+        let template_data = self.settings.email_template.as_ref().map(|_| {
+            template_data(
+                &global_sites,
+                &global.results,
+                &global.alerts,
+                &global.changes,
+                health_score.as_ref(),
+                self.settings.app.report_timezone,
+            )
+        });
+        let quiet = self
+            .settings
+            .app
+            .quiet_hours
+            .as_ref()
+            .filter(|quiet_hours| quiet_hours.is_active(Utc::now()));
+
+        match quiet {
+            _ if paused => {
+                log!(LogLevel::Info, "Monitoring paused, not sending the report");
+            }
+            _ if leader == Some(false) => {
+                log!(LogLevel::Info, "Standby instance, not sending the report");
+            }
+            Some(quiet_hours) if !quiet_hours.allows(severity) => {
+                log!(
+                    LogLevel::Info,
+                    "Quiet hours active, holding back {} report",
+                    severity
+                );
+                let timestamp = format_timestamp(Utc::now(), self.settings.app.report_timezone);
+                for alert in &alerts {
+                    self.site_states
+                        .hold_for_digest(&timestamp, &alert.to_string());
+                }
+                // A site can stay DEGRADED or DOWN all night without raising an alert
+                let mut held: Vec<String> = alerts::alerting_results(&self.sites, &results)
+                    .filter(|(_, result)| result.status != CheckStatus::Up)
+                    .map(|(url, result)| format!("{}: {}", url, result.status))
+                    .collect();
+                held.sort();
+                for entry in held {
+                    self.site_states.hold_for_digest(&timestamp, &entry);
+                }
+            }
+            _ => {
+                if quiet.is_none() && !self.site_states.quiet_digest.is_empty() {
+                    let digest = generate_digest(&self.site_states.quiet_digest);
+                    let delivered = self
+                        .notify("Website Monitor Quiet Hours Digest", &digest, &summary)
+                        .await;
+                    if delivered {
+                        self.site_states.quiet_digest.clear();
+                    }
+                    self.record_send(delivered);
+                }
+
+                let mut alert_due = false;
+                if let Some(window) = self.settings.app.alert_coalesce_seconds {
+                    if let Some(alert) =
+                        coalesce::due_alert(&self.site_states, window, current_timestamp())
+                    {
+                        alert_due = true;
+                        let delivered =
+                            self.notify("Website Monitor Alert", &alert, &summary).await;
+                        if delivered {
+                            self.site_states.pending_events.clear();
+                        }
+                        self.record_send(delivered);
+                    }
+                }
+
+                // Reports would announce the buffered changes one cycle at a time after all,
+                // but only until the window closes, a failed alert mustn't silence them for good
+                let coalescing = self.settings.app.alert_coalesce_seconds.is_some()
+                    && !alert_due
+                    && !self.site_states.pending_events.is_empty();
+                if coalescing {
+                    log!(
+                        LogLevel::Info,
+                        "Holding back the report while {} status changes are coalesced",
+                        self.site_states.pending_events.len()
+                    );
+                } else {
+                    let mut delivered = true;
+                    // Nothing left for the global channels when every site is routed elsewhere
+                    let global_parts = if global_sites.is_empty() && !self.sites.is_empty() {
+                        Vec::new()
+                    } else {
+                        global_parts
+                    };
+                    let part_count = global_parts.len();
+                    for (part, report_part) in global_parts.iter().enumerate() {
+                        let subject = if part_count == 1 {
+                            String::from("Website Monitor Report")
+                        } else {
+                            format!(
+                                "Website Monitor Report (part {} of {})",
+                                part + 1,
+                                part_count
+                            )
+                        };
+                        let mut part_summary = summary.clone();
+                        part_summary.report = true;
+                        part_summary.template_data = template_data.clone().map(|mut data| {
+                            data["report"] = serde_json::json!(report_part);
+                            data["part"] = serde_json::json!(part + 1);
+                            data["part_count"] = serde_json::json!(part_count);
+                            data
+                        });
+                        delivered &= self.notify(&subject, report_part, &part_summary).await;
+                    }
+                    delivered &= self.send_routed_reports(&results, &alerts, &changes).await;
+                    self.record_send(delivered);
+                }
+            }
+        }
+
+        let saved = match self.store.save_site_states(&self.site_states) {
+            Ok(()) => true,
+            Err(err) => {
+                log!(LogLevel::Error, "Failed to save site state: {}", err);
+                self.state.error_log.push(err);
+                false
+            }
+        };
+        self.record_save(saved);
+
+        let snapshot = StatusSnapshot {
+            updated_at: Some(Utc::now().to_rfc3339()),
+            profile: self.active_profile.clone(),
+            leader,
+            skipped: None,
+            health_score: health_score.map(|score| score.current),
+            rolling_health_score: health_score.map(|score| score.rolling),
+            sites: results,
+            uptime_percent,
+            histogram_buckets: self.settings.app.metrics_histogram_buckets.clone(),
+            histograms: self.histograms.clone(),
+            fifo_dropped: self.fifo.as_ref().map(FifoOutput::dropped),
+        };
+        if let Some(status_page) = &self.status_page {
+            if let Err(err) = status_page.write(&snapshot) {
+                log!(LogLevel::Error, "Failed to write status page: {}", err);
+                self.state.error_log.push(err);
+            }
+        }
+        if let Some(s3) = &self.s3 {
+            match serde_json::to_string_pretty(&snapshot) {
+                Ok(json) => s3.upload_report(json).await,
+                Err(e) => log!(LogLevel::Error, "Failed to serialize report: {}", e),
+            }
+        }
+        if let Ok(mut status) = self.status.write() {
+            *status = snapshot;
+        }
+
+        self.state.event_counter += 1;
+        let saved = self.save_state();
+        self.record_save(saved);
+        self.check_self_errors();
+    }
+
+    // Renew or take the notification lease, None when leader election is off. An
+    // unreachable lease counts as leading: duplicate alerts beat missing ones.
+    fn acquire_leadership(&mut self) -> Option<bool> {
+        let lease = self.leader.as_ref()?;
+        match lease.acquire(current_timestamp()) {
+            Ok(Leadership::Leader) => Some(true),
+            Ok(Leadership::Standby { holder }) => {
+                log!(LogLevel::Debug, "Notification lease held by {}", holder);
+                Some(false)
+            }
+            Err(err) => {
+                log!(
+                    LogLevel::Error,
+                    "Failed to update the leader lease, notifying anyway: {}",
+                    err
+                );
+                self.state.error_log.push(err);
+                Some(true)
+            }
+        }
+    }
+
+    // All at once, so the cycle waits on_change_timeout_seconds at most rather than
+    // that long for every transition
+    async fn run_on_change_commands(&self, changes: &CycleChanges) {
+        let timeout_seconds = self.settings.app.on_change_timeout_seconds;
+        let mut running = JoinSet::new();
+        for transition in &changes.transitions {
+            let command = self
+                .sites
+                .iter()
+                .find(|site| site.resolved_url() == transition.url)
+                .and_then(|site| site.on_change_command.as_ref())
+                .or(self.settings.app.on_change_command.as_ref());
+            if let Some(command) = command {
+                let command = command.clone();
+                let transition = transition.clone();
+                running.spawn(async move {
+                    hooks::run_on_change(&command, &transition, timeout_seconds).await
+                });
+            }
+        }
+        while let Some(outcome) = running.join_next().await {
+            if let Err(e) = outcome {
+                log!(LogLevel::Error, "on_change_command task failed: {}", e);
+            }
+        }
+    }
+
+    // Count failures of the monitor's own sends, only a delivered send resets the run
+    fn record_send(&mut self, ok: bool) {
+        if ok {
+            self.consecutive_send_errors = 0;
+        } else {
+            self.consecutive_send_errors += 1;
+        }
+    }
+
+    // Same for saving state, only a successful save resets the run
+    fn record_save(&mut self, ok: bool) {
+        if ok {
+            self.consecutive_save_errors = 0;
+        } else {
+            self.consecutive_save_errors += 1;
+        }
+    }
+
+    // Shut down rather than keep running without delivering or persisting anything
+    fn check_self_errors(&mut self) {
+        let max = match self.settings.app.max_consecutive_self_errors {
+            Some(max) => max,
+            None => return,
+        };
+        let failing = if self.consecutive_send_errors > max {
+            format!("{} consecutive send failures", self.consecutive_send_errors)
+        } else if self.consecutive_save_errors > max {
+            format!("{} consecutive save failures", self.consecutive_save_errors)
+        } else {
+            return;
+        };
+
+        log!(LogLevel::Error, "{}, giving up", failing);
+        self.state.is_active = false;
+        self.state.data = String::from("Stopped after too many consecutive self errors");
+        self.save_state();
+        ExitCode::SelfErrors.exit();
+    }
+
+    // Send through every notifier, true only when all of them succeeded
+    async fn notify(&mut self, subject: &str, body: &str, summary: &CycleSummary) -> bool {
+        let mut delivered = true;
+        for notifier in &self.notifiers {
+            if let Err(err) = notifier.notify_cycle(subject, body, summary).await {
+                log!(
+                    LogLevel::Error,
+                    "Error occurred while sending via {}: {}",
+                    notifier.name(),
+                    err
+                );
+                self.state.error_log.push(err);
+                delivered = false;
+            }
+        }
+        if !delivered {
+            self.save_state();
+        }
+        delivered
+    }
+
+    // One report per recipient group with only the sites routed to it, true when every
+    // group that has a channel got its report
+    async fn send_routed_reports(
+        &mut self,
+        results: &HashMap<String, HealthCheckResult>,
+        alerts: &[alerts::Alert],
+        changes: &CycleChanges,
+    ) -> bool {
+        let mut delivered = true;
+        for (name, sites) in routing::routed_sites(&self.sites) {
+            let scoped = routing::scope(&sites, results, alerts, changes);
+            let report = self
+                .render_report(
+                    &sites,
+                    &scoped.results,
+                    &scoped.alerts,
+                    &scoped.changes,
+                    None,
+                )
+                .concat();
+            let mut summary = CycleSummary::from_results(&sites, &scoped.results);
+            summary.report = true;
+            let notifier = match self.recipients.get(&name) {
+                Some(notifier) => notifier,
+                None => {
+                    log!(
+                        LogLevel::Warn,
+                        "No recipients {} configured, its {} sites go unreported",
+                        name,
+                        sites.len()
+                    );
+                    continue;
+                }
+            };
+            let subject = format!("Website Monitor Report ({})", name);
+            if let Err(err) = notifier.notify_cycle(&subject, &report, &summary).await {
+                log!(
+                    LogLevel::Error,
+                    "Error occurred while sending to recipients {}: {}",
+                    name,
+                    err
+                );
+                self.state.error_log.push(err);
+                delivered = false;
+            }
+        }
+        delivered
+    }
+
+    // Persist the application state through the store, false when it couldn't be saved
+    fn save_state(&mut self) -> bool {
+        self.state.last_updated = current_timestamp();
+        if let Err(err) = self.store.save_state(&self.state) {
+            log!(
+                LogLevel::Error,
+                "Failed to save state to the {} store: {}",
+                self.store.name(),
+                err
+            );
+            self.state.is_active = false;
+            self.state.error_log.push(err);
+            return false;
+        }
+        true
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use async_trait::async_trait;
+use dusa_collection_utils::errors::{ErrorArrayItem, Errors};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde::Serialize;
+
+use crate::alerts::alerting_results;
+use crate::config::{SiteConfig, WebhookConfig};
+use crate::email_template::EmailTemplate;
+use crate::health::{CheckStatus, HealthCheckResult};
+use crate::{mailing, webhook};
+
+/// What the cycle a message was sent from looked like.
+#[derive(Debug, Clone)]
+pub struct CycleSummary {
+    /// The worst status of any site.
+    pub status: CheckStatus,
+    /// Sites that were DOWN, sorted.
+    pub down_urls: Vec<String>,
+    /// Runbook and alert message of each DOWN site that has either.
+    pub alert_context: Vec<AlertContext>,
+    /// The cycle's results, counts, alerts and changes for an email template.
+    /// Only set on report messages, and only when a template is configured.
+    pub template_data: Option<serde_json::Value>,
+    /// Whether the message is a report, as opposed to a digest or an alert.
+    pub report: bool,
+}
+
+/// The on-call context configured for a site.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AlertContext {
+    pub url: String,
+    pub runbook_url: Option<String>,
+    pub alert_message: Option<String>,
+}
+
+impl CycleSummary {
+    pub fn from_results(
+        sites: &[SiteConfig],
+        results: &HashMap<String, HealthCheckResult>,
+    ) -> Self {
+        let mut down_urls: Vec<String> = alerting_results(sites, results)
+            .filter(|(_, result)| result.status.is_down())
+            .map(|(url, _)| url.clone())
+            .collect();
+        down_urls.sort();
+        let mut alert_context: Vec<AlertContext> = sites
+            .iter()
+            .filter(|site| site.runbook_url.is_some() || site.alert_message.is_some())
+            .map(|site| AlertContext {
+                url: site.resolved_url(),
+                runbook_url: site.runbook_url.clone(),
+                alert_message: site.alert_message.clone(),
+            })
+            .filter(|context| down_urls.contains(&context.url))
+            .collect();
+        alert_context.sort_by(|a, b| a.url.cmp(&b.url));
+        alert_context.dedup_by(|a, b| a.url == b.url);
+        CycleSummary {
+            alert_context,
+            template_data: None,
+            report: false,
+            status: alerting_results(sites, results)
+                .map(|(_, result)| result.status)
+                .max()
+                .unwrap_or(CheckStatus::Up),
+            down_urls,
+        }
+    }
+}
+
+/// Messages sent outside a cycle (digests, tests) carry a summary with nothing DOWN.
+impl Default for CycleSummary {
+    fn default() -> Self {
+        CycleSummary {
+            status: CheckStatus::Up,
+            down_urls: Vec::new(),
+            alert_context: Vec::new(),
+            template_data: None,
+            report: false,
+        }
+    }
+}
+
+/// A channel that cycle reports and digests are delivered through.
+///
+/// Every registered notifier receives every message the monitor sends. A
+/// notifier reports failure by returning an `Err`; the monitor logs it, records
+/// it in the application state's error log and carries on with the remaining
+/// notifiers, so one broken channel never blocks the others or stops the loop.
+/// Implementations should not retry indefinitely or block for long, since
+/// notifiers are awaited one after another within a cycle.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Short name used when logging delivery failures.
+    fn name(&self) -> &str;
+
+    /// Deliver a single message.
+    async fn notify(&self, subject: &str, body: &str) -> Result<(), ErrorArrayItem>;
+
+    /// Deliver a message along with a summary of the cycle it was sent from.
+    ///
+    /// This is what the monitor calls. The default ignores the summary and
+    /// delivers through [`Notifier::notify`].
+    async fn notify_cycle(
+        &self,
+        subject: &str,
+        body: &str,
+        summary: &CycleSummary,
+    ) -> Result<(), ErrorArrayItem> {
+        let _ = summary;
+        self.notify(subject, body).await
+    }
+}
+
+/// Delivers messages as encrypted emails through the Artisan mail relay.
+#[derive(Default)]
+pub struct EmailNotifier {
+    template: Option<EmailTemplate>,
+    report_limit: Option<usize>,
+}
+
+impl EmailNotifier {
+    pub fn new() -> Self {
+        EmailNotifier::default()
+    }
+
+    /// Render report emails from `template`, other messages keep their body.
+    pub fn with_template(template: EmailTemplate) -> Self {
+        EmailNotifier {
+            template: Some(template),
+            report_limit: None,
+        }
+    }
+
+    /// Send reports longer than `max_bytes` as a short summary (or the rendered
+    /// template) followed by the report gzip compressed and base64 encoded.
+    pub fn limiting_reports(mut self, max_bytes: usize) -> Self {
+        self.report_limit = Some(max_bytes);
+        self
+    }
+}
+
+// What the body says before an oversized report
+fn compressed_summary(summary: &CycleSummary, report: &str) -> String {
+    let mut text = format!("Overall status: {}\n", summary.status);
+    if summary.down_urls.is_empty() {
+        text.push_str("No site is DOWN.\n");
+    } else {
+        text.push_str(&format!("DOWN: {}\n", summary.down_urls.join(", ")));
+    }
+    text.push_str(&format!(
+        "\nThe full report ({} bytes) is too large to send as is. It follows gzip compressed \
+         and base64 encoded, decode it with: base64 -d report.b64 | gunzip\n",
+        report.len()
+    ));
+    text
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    async fn notify(&self, subject: &str, body: &str) -> Result<(), ErrorArrayItem> {
+        mailing::send_email(subject, body.to_string())
+    }
+
+    async fn notify_cycle(
+        &self,
+        subject: &str,
+        body: &str,
+        summary: &CycleSummary,
+    ) -> Result<(), ErrorArrayItem> {
+        let rendered = match (&self.template, &summary.template_data) {
+            (Some(template), Some(data)) => match template.render(subject, data) {
+                Ok(rendered) => Some(rendered),
+                Err(e) => {
+                    log!(
+                        LogLevel::Warn,
+                        "Email template failed to render, sending the built-in format: {}",
+                        e
+                    );
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let oversized = summary.report
+            && self
+                .report_limit
+                .is_some_and(|max_bytes| body.len() > max_bytes);
+        if !oversized {
+            return self
+                .notify(subject, rendered.as_deref().unwrap_or(body))
+                .await;
+        }
+        let text = rendered.unwrap_or_else(|| compressed_summary(summary, body));
+        let compressed = mailing::compressed_report(body)?;
+        self.notify(subject, &format!("{}\n{}\n", text.trim_end(), compressed))
+            .await
+    }
+}
+
+/// Posts messages as JSON to a webhook, shaped by a Slack, Discord or generic
+/// preset or by a custom template.
+pub struct WebhookNotifier {
+    client: Client,
+    url: String,
+    template: String,
+    labels: BTreeMap<String, String>,
+    report_limit: Option<usize>,
+}
+
+impl WebhookNotifier {
+    /// Fails when the template doesn't render to valid JSON.
+    pub fn new(config: &WebhookConfig) -> Result<Self, String> {
+        let template = config
+            .template
+            .clone()
+            .unwrap_or_else(|| webhook::preset_template(config.preset).to_string());
+
+        // Quotes and newlines in the sample values make sure escaping is exercised too
+        let sample = CycleSummary {
+            status: CheckStatus::Down,
+            down_urls: vec![String::from("https://example.com/\"quoted\"")],
+            alert_context: vec![AlertContext {
+                url: String::from("https://example.com/\"quoted\""),
+                runbook_url: Some(String::from("https://wiki.example.com/runbook")),
+                alert_message: Some(String::from("Page \"the\" on-call")),
+            }],
+            template_data: None,
+            report: true,
+        };
+        let rendered = webhook::render(
+            &template,
+            "Website Monitor \"Report\"",
+            "line one\nline two",
+            &sample,
+            &config.labels,
+            None,
+        );
+        serde_json::from_str::<serde_json::Value>(&rendered)
+            .map_err(|e| format!("webhook template does not render to valid JSON: {}", e))?;
+
+        let client = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(WebhookNotifier {
+            client,
+            url: config.url.clone(),
+            template,
+            labels: config.labels.clone(),
+            report_limit: webhook::report_limit(config.preset),
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    async fn notify(&self, subject: &str, body: &str) -> Result<(), ErrorArrayItem> {
+        self.notify_cycle(subject, body, &CycleSummary::default())
+            .await
+    }
+
+    async fn notify_cycle(
+        &self,
+        subject: &str,
+        body: &str,
+        summary: &CycleSummary,
+    ) -> Result<(), ErrorArrayItem> {
+        let payload = webhook::render(
+            &self.template,
+            subject,
+            body,
+            summary,
+            &self.labels,
+            self.report_limit,
+        );
+        self.client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ErrorArrayItem::new(Errors::GeneralError, e.to_string()))?;
+        Ok(())
+    }
+}
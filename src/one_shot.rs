@@ -0,0 +1,38 @@
+use chrono::{DateTime, Utc};
+
+use crate::config::OneShotConfig;
+
+// Configured one-shot checks whose time has come and that haven't fired yet, including
+// ones that fell due while the monitor wasn't running
+pub fn due<'a>(
+    one_shots: &'a [OneShotConfig],
+    fired: &[String],
+    now: DateTime<Utc>,
+) -> Vec<&'a OneShotConfig> {
+    one_shots
+        .iter()
+        .filter(|one_shot| one_shot.at <= now && !fired.contains(&one_shot.id()))
+        .collect()
+}
+
+// Seconds until the next one-shot check still in the future, to wake up for it between
+// cycles. Rounded up so the wait never ends just short of it.
+pub fn seconds_until_next(
+    one_shots: &[OneShotConfig],
+    fired: &[String],
+    now: DateTime<Utc>,
+) -> Option<u64> {
+    one_shots
+        .iter()
+        .filter(|one_shot| one_shot.at > now && !fired.contains(&one_shot.id()))
+        .map(|one_shot| {
+            let millis = (one_shot.at.with_timezone(&Utc) - now).num_milliseconds();
+            (millis as u64).div_ceil(1000)
+        })
+        .min()
+}
+
+// Forget fired checks that were removed from the settings, the list would only grow otherwise
+pub fn prune(fired: &mut Vec<String>, one_shots: &[OneShotConfig]) {
+    fired.retain(|id| one_shots.iter().any(|one_shot| one_shot.id() == *id));
+}
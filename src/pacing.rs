@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use reqwest::Url;
+use tokio::time::Instant;
+
+// Keeps requests to the same domain at least `min_interval` apart, across cycles
+#[derive(Debug, Default)]
+pub struct DomainPacer {
+    min_interval: Duration,
+    last_hit: HashMap<String, Instant>,
+}
+
+impl DomainPacer {
+    pub fn new(min_interval_seconds: u64) -> Self {
+        DomainPacer {
+            min_interval: Duration::from_secs(min_interval_seconds),
+            last_hit: HashMap::new(),
+        }
+    }
+
+    // Wait until the url's domain may be hit again, then record the hit
+    pub async fn wait_for(&mut self, url: &str) {
+        if self.min_interval.is_zero() {
+            return;
+        }
+        let domain = match Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+        {
+            Some(domain) => domain,
+            None => return,
+        };
+
+        if let Some(last_hit) = self.last_hit.get(&domain) {
+            let ready_at = *last_hit + self.min_interval;
+            if ready_at > Instant::now() {
+                log!(
+                    LogLevel::Debug,
+                    "Pacing {} for {} ms",
+                    domain,
+                    (ready_at - Instant::now()).as_millis()
+                );
+                tokio::time::sleep_until(ready_at).await;
+            }
+        }
+        self.last_hit.insert(domain, Instant::now());
+    }
+}
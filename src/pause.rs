@@ -0,0 +1,54 @@
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use artisan_middleware::config::AppConfig;
+use dusa_collection_utils::errors::ErrorArrayItem;
+use dusa_collection_utils::types::PathType;
+use serde::{Deserialize, Serialize};
+
+// What the monitor does while paused
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PauseMode {
+    // Skip checks entirely
+    #[default]
+    Stop,
+    // Keep checking and recording, but send nothing
+    Record,
+}
+
+// Maintenance pause toggled through the status server. A marker file keeps it across restarts.
+#[derive(Debug, Clone)]
+pub struct PauseFlag {
+    paused: Arc<AtomicBool>,
+    path: PathType,
+}
+
+impl PauseFlag {
+    pub fn get_path(config: &AppConfig) -> PathType {
+        PathType::Content(format!("/tmp/.{}.paused", config.app_name))
+    }
+
+    pub fn load(path: PathType) -> Self {
+        let paused = fs::metadata(&path).is_ok();
+        PauseFlag {
+            paused: Arc::new(AtomicBool::new(paused)),
+            path,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn set(&self, paused: bool) -> Result<(), ErrorArrayItem> {
+        if paused {
+            fs::write(&self.path, "")?;
+        } else if fs::metadata(&self.path).is_ok() {
+            fs::remove_file(&self.path)?;
+        }
+        self.paused.store(paused, Ordering::SeqCst);
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use reqwest::Url;
+use tokio::net::lookup_host;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+use crate::config::{DohConfig, SiteConfig};
+use crate::doh;
+
+// A host resolved ahead of its check
+#[derive(Debug, Clone, Copy)]
+pub struct Resolved {
+    pub address: IpAddr,
+    pub time_ms: u128,
+}
+
+pub type ResolvedHosts = HashMap<String, Resolved>;
+
+// The url's host when it's a name that needs resolving
+pub fn host_to_resolve(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()
+        .and_then(|parsed| parsed.host_str().map(|host| host.to_string()))
+        .filter(|host| host.parse::<IpAddr>().is_err())
+}
+
+// The addresses a localhost name stands for, used without asking any resolver
+pub const LOOPBACK_ADDRESSES: [IpAddr; 2] = [
+    IpAddr::V4(Ipv4Addr::LOCALHOST),
+    IpAddr::V6(Ipv6Addr::LOCALHOST),
+];
+
+// localhost and its subdomains always mean this machine (RFC 6761)
+pub fn is_localhost_name(host: &str) -> bool {
+    let host = host.trim_end_matches('.');
+    host.eq_ignore_ascii_case("localhost") || host.to_ascii_lowercase().ends_with(".localhost")
+}
+
+// Whether the url points at this machine: a localhost name or a loopback address
+pub fn is_loopback_url(url: &str) -> bool {
+    let parsed = match Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(_) => return false,
+    };
+    // IPv6 hosts come bracketed
+    match parsed
+        .host_str()
+        .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+    {
+        Some(host) => match host.parse::<IpAddr>() {
+            Ok(address) => address.is_loopback(),
+            Err(_) => is_localhost_name(host),
+        },
+        None => false,
+    }
+}
+
+// Through DoH when configured, the system resolver otherwise
+pub async fn resolve_host(host: &str, doh: Option<&DohConfig>) -> Result<IpAddr, String> {
+    if let Some(doh) = doh {
+        return doh::resolve(&doh.endpoint, host).await;
+    }
+    lookup_host((host, 0))
+        .await
+        .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+        .next()
+        .map(|address| address.ip())
+        .ok_or_else(|| format!("{} has no addresses", host))
+}
+
+// Resolve every distinct host up front, at most `concurrency` at a time. Hosts that
+// fail are left out and resolved again by their own check.
+pub async fn prefetch(
+    sites: &[SiteConfig],
+    doh: Option<&DohConfig>,
+    concurrency: usize,
+) -> ResolvedHosts {
+    let start = Instant::now();
+    let mut hosts: Vec<String> = sites
+        .iter()
+        .filter(|site| !site.fast_path())
+        .filter_map(|site| host_to_resolve(&site.url))
+        .collect();
+    hosts.sort();
+    hosts.dedup();
+    let host_count = hosts.len();
+
+    let mut resolved = ResolvedHosts::new();
+    let mut pending = JoinSet::new();
+    let mut hosts = hosts.into_iter();
+    loop {
+        while pending.len() < concurrency.max(1) {
+            let host = match hosts.next() {
+                Some(host) => host,
+                None => break,
+            };
+            let doh = doh.cloned();
+            pending.spawn(async move {
+                let resolve_start = Instant::now();
+                let address = resolve_host(&host, doh.as_ref()).await;
+                (host, address, resolve_start.elapsed().as_millis())
+            });
+        }
+
+        match pending.join_next().await {
+            Some(Ok((host, Ok(address), time_ms))) => {
+                resolved.insert(host, Resolved { address, time_ms });
+            }
+            Some(Ok((_, Err(e), _))) => log!(LogLevel::Warn, "DNS prefetch: {}", e),
+            Some(Err(e)) => log!(LogLevel::Warn, "DNS prefetch task failed: {}", e),
+            None => break,
+        }
+    }
+
+    log!(
+        LogLevel::Debug,
+        "Prefetched {} of {} hosts in {} ms",
+        resolved.len(),
+        host_count,
+        start.elapsed().as_millis()
+    );
+    resolved
+}
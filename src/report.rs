@@ -0,0 +1,707 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::alerts::Alert;
+use crate::changes::CycleChanges;
+use crate::config::{ErrorGrouping, SiteConfig};
+use crate::health::{CheckStatus, HealthCheckResult};
+use crate::health_score::HealthScore;
+
+pub struct ReportOptions {
+    pub group_by_priority: bool,
+    // List DOWN sites by error in the first part instead of in the site sections
+    pub group_errors: ErrorGrouping,
+    pub timezone: Tz,
+    // Split the site sections over several reports of at most this many sites
+    pub max_sites_per_part: Option<usize>,
+}
+
+// The report, in parts when there are more sites than fit in one. Changes and alerts
+// come with the first part, the summary of all sites with every part.
+pub fn generate_report(
+    sites: &[SiteConfig],
+    results: &HashMap<String, HealthCheckResult>,
+    alerts: &[Alert],
+    changes: &CycleChanges,
+    health_score: Option<&HealthScore>,
+    options: &ReportOptions,
+) -> Vec<String> {
+    let tally = Tally::new(sites, results);
+    let Layout {
+        groups,
+        listed,
+        per_part,
+        part_count,
+    } = tally.layout(options);
+
+    let mut summary = format!(
+        "\nSummary:\n  Total Websites Checked: {}\n  Total UP: {}\n  Total DEGRADED: {}\n  Total DOWN: {}\n",
+        results.len(),
+        tally.up,
+        tally.degraded,
+        tally.down
+    );
+    if tally.stale > 0 {
+        summary.push_str(&format!("  Total STALE: {}\n", tally.stale));
+    }
+    summary.push('\n');
+    if let Some(health_score) = health_score {
+        summary.push_str(&format!(
+            "  Health Score: {:.1}% (rolling {:.1}%)\n\n",
+            health_score.current * 100.0,
+            health_score.rolling * 100.0
+        ));
+    }
+
+    let generated = format_timestamp(Utc::now(), options.timezone);
+
+    let mut parts = Vec::with_capacity(part_count);
+    for part in 0..part_count {
+        let mut report = if part_count == 1 {
+            format!("Website Health Check Report:\nGenerated: {}\n\n", generated)
+        } else {
+            format!(
+                "Website Health Check Report (part {} of {}):\nGenerated: {}\n\n",
+                part + 1,
+                part_count,
+                generated
+            )
+        };
+        if part == 0 {
+            push_changes_section(&mut report, changes);
+            push_error_groups(&mut report, &groups);
+        }
+
+        // Sites are already sorted by priority, so groups are contiguous. Each part
+        // repeats the heading of the group it starts in.
+        let mut current_priority: Option<i32> = None;
+        let chunk = listed.iter().skip(part * per_part).take(per_part);
+        for (site, url, result) in chunk {
+            if options.group_by_priority && current_priority != Some(site.priority) {
+                report.push_str(&format!("== Priority {} ==\n\n", site.priority));
+                current_priority = Some(site.priority);
+            }
+            push_site_section(&mut report, site, url, result, options.timezone);
+        }
+
+        if part == 0 && !alerts.is_empty() {
+            report.push_str("Alerts:\n");
+            for alert in alerts {
+                report.push_str(&format!("  {}\n", alert));
+            }
+        }
+
+        report.push_str(&summary);
+        parts.push(report);
+    }
+
+    parts
+}
+
+// The checked sites and their totals, shared by the text and Markdown reports so
+// the two never count differently
+struct Tally<'a> {
+    checked: Vec<(&'a SiteConfig, String, &'a HealthCheckResult)>,
+    up: usize,
+    degraded: usize,
+    down: usize,
+    stale: usize,
+}
+
+impl<'a> Tally<'a> {
+    fn new(sites: &'a [SiteConfig], results: &'a HashMap<String, HealthCheckResult>) -> Self {
+        let mut tally = Tally {
+            checked: Vec::new(),
+            up: 0,
+            degraded: 0,
+            down: 0,
+            stale: 0,
+        };
+        for site in sites {
+            let url = site.resolved_url();
+            let result = match results.get(&url) {
+                Some(result) => result,
+                None => continue,
+            };
+            match result.status {
+                CheckStatus::Up => tally.up += 1,
+                CheckStatus::Degraded => tally.degraded += 1,
+                CheckStatus::Down => tally.down += 1,
+            }
+            if result.stale_since.is_some() {
+                tally.stale += 1;
+            }
+            tally.checked.push((site, url, result));
+        }
+        tally
+    }
+
+    // How the sites are spread over the report: DOWN sites go in the error groups
+    // when there are any, the rest are listed and split into parts
+    fn layout(&self, options: &ReportOptions) -> Layout<'_> {
+        let groups = group_errors(&self.checked, options.group_errors);
+        let listed: Vec<_> = self
+            .checked
+            .iter()
+            .filter(|(_, _, result)| groups.is_empty() || !result.status.is_down())
+            .collect();
+        let per_part = options
+            .max_sites_per_part
+            .filter(|max| *max > 0)
+            .unwrap_or(listed.len())
+            .max(1);
+        let part_count = listed.len().div_ceil(per_part).max(1);
+        Layout {
+            groups,
+            listed,
+            per_part,
+            part_count,
+        }
+    }
+}
+
+struct Layout<'a> {
+    groups: Vec<ErrorGroup<'a>>,
+    listed: Vec<&'a (&'a SiteConfig, String, &'a HealthCheckResult)>,
+    per_part: usize,
+    part_count: usize,
+}
+
+// DOWN sites that failed the same way
+struct ErrorGroup<'a> {
+    heading: String,
+    sites: Vec<&'a (&'a SiteConfig, String, &'a HealthCheckResult)>,
+    // Grouped by category only, so each site's own message is still worth showing
+    per_site_messages: bool,
+}
+
+// The DOWN sites by error category, or by category and message, largest group first.
+// Empty when grouping is off.
+fn group_errors<'a>(
+    checked: &'a [(&'a SiteConfig, String, &'a HealthCheckResult)],
+    grouping: ErrorGrouping,
+) -> Vec<ErrorGroup<'a>> {
+    let mut groups: Vec<ErrorGroup> = Vec::new();
+    if grouping == ErrorGrouping::Off {
+        return groups;
+    }
+    for entry in checked
+        .iter()
+        .filter(|(_, _, result)| result.status.is_down())
+    {
+        let error = entry.2.error.as_ref();
+        let category = error.map(|error| error.category()).unwrap_or("unknown");
+        // Messages often name the url, which would keep every site in a group of its own
+        let heading = match grouping {
+            ErrorGrouping::Message => format!(
+                "[{}] {}",
+                category,
+                error
+                    .map(|error| error.to_string().replace(entry.1.as_str(), "{url}"))
+                    .unwrap_or_default()
+            ),
+            _ => category.to_string(),
+        };
+        match groups.iter_mut().find(|group| group.heading == heading) {
+            Some(group) => group.sites.push(entry),
+            None => groups.push(ErrorGroup {
+                heading,
+                sites: vec![entry],
+                per_site_messages: grouping == ErrorGrouping::Category,
+            }),
+        }
+    }
+    groups.sort_by(|a, b| {
+        b.sites
+            .len()
+            .cmp(&a.sites.len())
+            .then_with(|| a.heading.cmp(&b.heading))
+    });
+    groups
+}
+
+fn push_error_groups(report: &mut String, groups: &[ErrorGroup]) {
+    if groups.is_empty() {
+        return;
+    }
+
+    report.push_str("DOWN by error:\n");
+    for group in groups {
+        report.push_str(&format!(
+            "  {} ({} {})\n",
+            group.heading,
+            group.sites.len(),
+            if group.sites.len() == 1 {
+                "site"
+            } else {
+                "sites"
+            }
+        ));
+        for (site, url, result) in &group.sites {
+            match result.error.as_ref().filter(|_| group.per_site_messages) {
+                Some(error) => report.push_str(&format!("    {}: {}\n", url, error)),
+                None => report.push_str(&format!("    {}\n", url)),
+            }
+            if let Some(alert_message) = &site.alert_message {
+                report.push_str(&format!("      Alert: {}\n", alert_message));
+            }
+            if let Some(runbook_url) = &site.runbook_url {
+                report.push_str(&format!("      Runbook: {}\n", runbook_url));
+            }
+        }
+    }
+    report.push('\n');
+}
+
+fn push_changes_section(report: &mut String, changes: &CycleChanges) {
+    if changes.is_empty() {
+        return;
+    }
+
+    report.push_str("Changes since last cycle:\n");
+    for url in &changes.newly_down {
+        report.push_str(&format!("  Newly DOWN: {}\n", url));
+    }
+    for url in &changes.recovered {
+        report.push_str(&format!("  Recovered: {}\n", url));
+    }
+    for change in &changes.latency {
+        report.push_str(&format!(
+            "  Response time {:+.0}%: {} ({} ms -> {} ms)\n",
+            change.percent(),
+            change.url,
+            change.previous_ms,
+            change.current_ms
+        ));
+    }
+    report.push('\n');
+}
+
+fn push_site_section(
+    report: &mut String,
+    site: &SiteConfig,
+    url: &str,
+    result: &HealthCheckResult,
+    timezone: Tz,
+) {
+    report.push_str(&format!("URL: {}\n", url));
+    // What the on-call needs first when the site is in trouble
+    if result.status != CheckStatus::Up {
+        if let Some(alert_message) = &site.alert_message {
+            report.push_str(&format!("  Alert: {}\n", alert_message));
+        }
+        if let Some(runbook_url) = &site.runbook_url {
+            report.push_str(&format!("  Runbook: {}\n", runbook_url));
+        }
+    }
+    if result.expect_down {
+        report.push_str(&format!(
+            "  Status: {} (inverted check, the site is expected to be unreachable)\n",
+            result.status
+        ));
+    } else if let Some(stale_since) = result.stale_since {
+        report.push_str(&format!(
+            "  Status: {} (STALE, no answer since {})\n",
+            result.status,
+            format_unix_timestamp(stale_since, timezone)
+        ));
+    } else {
+        report.push_str(&format!("  Status: {}\n", result.status));
+    }
+    if let Some(status_code) = result.status_code {
+        report.push_str(&format!("  HTTP Status: {}\n", status_code));
+    }
+    if let Some(remote_addr) = &result.remote_addr {
+        report.push_str(&format!(
+            "  Connected To: {} as {}\n",
+            remote_addr,
+            result.effective_host.as_deref().unwrap_or("-")
+        ));
+    }
+    if let Some(resolved_address) = &result.resolved_address {
+        report.push_str(&format!("  Resolved To: {}\n", resolved_address));
+    }
+    if !result.sources.is_empty() {
+        report.push_str("  Sources:\n");
+        for source in &result.sources {
+            let detail = match (&source.error, source.response_time_ms) {
+                (Some(error), _) => format!(" - {}", error),
+                (None, Some(response_time)) => format!(" ({} ms)", response_time),
+                (None, None) => String::new(),
+            };
+            report.push_str(&format!(
+                "    {}: {}{}\n",
+                source.local_address, source.status, detail
+            ));
+        }
+    } else if let Some(local_address) = &result.local_address {
+        report.push_str(&format!("  Source Address: {}\n", local_address));
+    }
+    for path in &result.ip_families {
+        let outcome = match (path.status_code, &path.error) {
+            (Some(status_code), _) => format!(
+                "{} ({} ms)",
+                status_code,
+                path.response_time_ms.unwrap_or_default()
+            ),
+            (None, Some(error)) => format!("failed, {}", error),
+            (None, None) => String::from("failed"),
+        };
+        report.push_str(&format!(
+            "  {}: {} via {}\n",
+            path.family, outcome, path.address
+        ));
+    }
+    if !result.redirects.is_empty() {
+        report.push_str(&format!("  Redirects: {}\n", result.redirects.len()));
+        for hop in &result.redirects {
+            report.push_str(&format!(
+                "    {} {} ({} ms)\n",
+                hop.status, hop.url, hop.time_ms
+            ));
+        }
+    }
+    if let Some(sla) = result.sla {
+        report.push_str(&format!(
+            "  SLA Uptime: {:.3}% (target {}%, {})\n",
+            sla.uptime_percent,
+            sla.target_percent,
+            if sla.breached { "BREACHED" } else { "met" }
+        ));
+    }
+    if let Some(slo) = result.slo {
+        report.push_str(&format!(
+            "  Latency SLO: {:.3}% within {} ms (target {}%), {:.1}% of the error budget left, burn rate {:.2}x{}\n",
+            slo.good_percent,
+            slo.threshold_ms,
+            slo.target_percent,
+            slo.budget_remaining * 100.0,
+            slo.burn_rate,
+            slo.exhausted_in_hours
+                .map(|hours| format!(", runs out in {:.1} h", hours))
+                .unwrap_or_default()
+        ));
+    }
+    if let Some(cache_headers) = &result.cache_headers {
+        report.push_str(&format!(
+            "  Cache-Control: {}\n",
+            cache_headers.cache_control.as_deref().unwrap_or("-")
+        ));
+        report.push_str(&format!(
+            "  ETag: {}, Last-Modified: {}, Expires: {}\n",
+            cache_headers.etag.as_deref().unwrap_or("-"),
+            cache_headers.last_modified.as_deref().unwrap_or("-"),
+            cache_headers.expires.as_deref().unwrap_or("-")
+        ));
+    }
+    if let Some(content_range) = &result.content_range {
+        report.push_str(&format!("  Content-Range: {}\n", content_range));
+    }
+    if let Some(retry_waited) = result.retry_waited_ms {
+        report.push_str(&format!(
+            "  Retried After: {} ms (Retry-After {}s)\n",
+            retry_waited,
+            result.retry_after_seconds.unwrap_or_default()
+        ));
+    }
+    if let Some(prefetch_ok) = result.prefetch_ok {
+        report.push_str(&format!(
+            "  Prefetch: {}\n",
+            if prefetch_ok { "ok" } else { "failed" }
+        ));
+    }
+    if let Some(http3) = result.http3 {
+        report.push_str(&format!("  HTTP/3: {}\n", if http3 { "yes" } else { "no" }));
+    }
+    for warning in &result.warnings {
+        report.push_str(&format!("  Warning: {}\n", warning));
+    }
+    if let Some(dnssec) = result.dnssec {
+        report.push_str(&format!("  DNSSEC: {}\n", dnssec));
+    }
+    if let Some(tls_version) = &result.tls_version {
+        report.push_str(&format!(
+            "  TLS: {} ({})\n",
+            tls_version,
+            result.tls_cipher.as_deref().unwrap_or("unknown cipher")
+        ));
+    }
+    for finding in &result.tls_findings {
+        report.push_str(&format!("  TLS Finding: {}\n", finding));
+    }
+
+    if !result.status.is_down() {
+        report.push_str(&format!(
+            "  DNS & Request Time: {} ms\n",
+            result.dns_time_ms.unwrap_or(0)
+        ));
+        if let Some(connect_time) = result.connect_time_ms {
+            report.push_str(&format!("  Connect Time: {} ms\n", connect_time));
+        }
+        if let Some(tls_time) = result.tls_time_ms {
+            report.push_str(&format!("  TLS Handshake Time: {} ms\n", tls_time));
+        }
+        report.push_str(&format!(
+            "  Total Response Time: {} ms\n",
+            result.response_time_ms.unwrap_or(0)
+        ));
+        if let Some(reused) = result.connection_reused {
+            report.push_str(&format!(
+                "  Connection: {}\n",
+                if reused { "reused" } else { "new" }
+            ));
+        }
+        if let Some(body_time) = result.body_time_ms {
+            report.push_str(&format!("  Body Read Time: {} ms\n", body_time));
+        }
+        if let Some(graphql_time) = result.graphql_time_ms {
+            report.push_str(&format!("  GraphQL Query Time: {} ms\n", graphql_time));
+        }
+        if let Some(content_length) = result.content_length {
+            report.push_str(&format!("  Content-Length: {} bytes\n", content_length));
+        }
+        if let Some(body_bytes) = result.body_bytes {
+            report.push_str(&format!("  Body Size: {} bytes\n", body_bytes));
+        }
+        if let Some(decoded_bytes) = result.decoded_bytes {
+            report.push_str(&format!("  Decompressed Size: {} bytes\n", decoded_bytes));
+        }
+        if let Some(ratio) = result.compression_ratio() {
+            report.push_str(&format!("  Compression Ratio: {:.2}\n", ratio));
+        }
+        if let Some(reason) = &result.error {
+            report.push_str(&format!("  Reason: {}\n", reason));
+        }
+    } else {
+        match &result.error {
+            Some(error) => report.push_str(&format!("  Error: {}\n", error)),
+            None => report.push_str("  Error: Unknown error\n"),
+        }
+        if let Some(diagnosis) = &result.diagnosis {
+            report.push_str(&format!("  Diagnosis: {}\n", diagnosis));
+        }
+        if let Some(body_snippet) = &result.body_snippet {
+            report.push_str(&format!("  Body Snippet: {}\n", body_snippet));
+        }
+    }
+
+    report.push('\n');
+}
+
+// The same report as a Markdown table and summary, for incident trackers and wikis.
+// Split into parts like the text report.
+pub fn generate_report_markdown(
+    sites: &[SiteConfig],
+    results: &HashMap<String, HealthCheckResult>,
+    alerts: &[Alert],
+    changes: &CycleChanges,
+    health_score: Option<&HealthScore>,
+    options: &ReportOptions,
+) -> Vec<String> {
+    let tally = Tally::new(sites, results);
+    let Layout {
+        groups,
+        listed,
+        per_part,
+        part_count,
+    } = tally.layout(options);
+
+    let mut summary = format!(
+        "## Summary\n\n- Total Websites Checked: {}\n- Total UP: {}\n- Total DEGRADED: {}\n- Total DOWN: {}\n",
+        results.len(),
+        tally.up,
+        tally.degraded,
+        tally.down
+    );
+    if tally.stale > 0 {
+        summary.push_str(&format!("- Total STALE: {}\n", tally.stale));
+    }
+    if let Some(health_score) = health_score {
+        summary.push_str(&format!(
+            "- Health Score: {:.1}% (rolling {:.1}%)\n",
+            health_score.current * 100.0,
+            health_score.rolling * 100.0
+        ));
+    }
+
+    let generated = format_timestamp(Utc::now(), options.timezone);
+
+    let mut parts = Vec::with_capacity(part_count);
+    for part in 0..part_count {
+        let mut report = if part_count == 1 {
+            String::from("# Website Health Check Report\n\n")
+        } else {
+            format!(
+                "# Website Health Check Report (part {} of {})\n\n",
+                part + 1,
+                part_count
+            )
+        };
+        report.push_str(&format!("Generated: {}\n\n", generated));
+
+        if part == 0 && !changes.is_empty() {
+            report.push_str("## Changes since last cycle\n\n");
+            for url in &changes.newly_down {
+                report.push_str(&format!("- Newly DOWN: {}\n", escape_markdown(url)));
+            }
+            for url in &changes.recovered {
+                report.push_str(&format!("- Recovered: {}\n", escape_markdown(url)));
+            }
+            for change in &changes.latency {
+                report.push_str(&format!(
+                    "- Response time {:+.0}%: {} ({} ms -> {} ms)\n",
+                    change.percent(),
+                    escape_markdown(&change.url),
+                    change.previous_ms,
+                    change.current_ms
+                ));
+            }
+            report.push('\n');
+        }
+        if part == 0 && !groups.is_empty() {
+            report.push_str("## DOWN by error\n\n");
+            for group in &groups {
+                report.push_str(&format!(
+                    "### {} ({} {})\n\n",
+                    escape_markdown(&group.heading),
+                    group.sites.len(),
+                    if group.sites.len() == 1 {
+                        "site"
+                    } else {
+                        "sites"
+                    }
+                ));
+                for (site, url, result) in &group.sites {
+                    let mut line = escape_markdown(url);
+                    if let Some(error) = result.error.as_ref().filter(|_| group.per_site_messages) {
+                        line = format!("{}: {}", line, escape_markdown(&error.to_string()));
+                    }
+                    if let Some(alert_message) = &site.alert_message {
+                        line = format!("{} ({})", line, escape_markdown(alert_message));
+                    }
+                    if let Some(runbook_url) = &site.runbook_url {
+                        line = format!("{} [runbook]({})", line, escape_link(runbook_url));
+                    }
+                    report.push_str(&format!("- {}\n", line));
+                }
+                report.push('\n');
+            }
+        }
+
+        let mut current_priority: Option<i32> = None;
+        let mut table_open = false;
+        let chunk = listed.iter().skip(part * per_part).take(per_part);
+        for (site, url, result) in chunk {
+            if options.group_by_priority && current_priority != Some(site.priority) {
+                if table_open {
+                    report.push('\n');
+                }
+                report.push_str(&format!("## Priority {}\n\n", site.priority));
+                current_priority = Some(site.priority);
+                table_open = false;
+            }
+            if !table_open {
+                report.push_str("| Status | URL | HTTP | Response Time | Error |\n");
+                report.push_str("| --- | --- | --- | --- | --- |\n");
+                table_open = true;
+            }
+            let mut error = result
+                .error
+                .as_ref()
+                .map(|error| error.to_string())
+                .unwrap_or_default();
+            if result.status != CheckStatus::Up {
+                if let Some(alert_message) = &site.alert_message {
+                    error = format!("{} ({})", error, alert_message);
+                }
+                if let Some(runbook_url) = &site.runbook_url {
+                    error = format!("{} [runbook]({})", error, escape_link(runbook_url));
+                }
+            }
+            let status = match result.stale_since {
+                Some(_) => format!("{} (STALE)", result.status),
+                None => result.status.to_string(),
+            };
+            report.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                status,
+                escape_markdown(url),
+                result
+                    .status_code
+                    .map(|code| code.to_string())
+                    .unwrap_or_else(|| String::from("-")),
+                result
+                    .response_time_ms
+                    .map(|time| format!("{} ms", time))
+                    .unwrap_or_else(|| String::from("-")),
+                escape_markdown(error.trim())
+            ));
+        }
+        if table_open {
+            report.push('\n');
+        }
+
+        if part == 0 && !alerts.is_empty() {
+            report.push_str("## Alerts\n\n");
+            for alert in alerts {
+                report.push_str(&format!("- {}\n", escape_markdown(&alert.to_string())));
+            }
+            report.push('\n');
+        }
+
+        report.push_str(&summary);
+        parts.push(report);
+    }
+
+    parts
+}
+
+// Pipes would end a table cell and line breaks the row
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|")
+        .replace("\r\n", " ")
+        .replace(['\n', '\r'], " ")
+}
+
+// Percent-encode what would end a link target early or break the table row
+fn escape_link(url: &str) -> String {
+    let mut escaped = String::with_capacity(url.len());
+    for c in url.chars() {
+        if matches!(c, '(' | ')' | '<' | '>' | '[' | ']' | '|' | '\\')
+            || c.is_whitespace()
+            || c.is_control()
+        {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                escaped.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+fn format_unix_timestamp(timestamp: u64, timezone: Tz) -> String {
+    match DateTime::from_timestamp(timestamp as i64, 0) {
+        Some(time) => format_timestamp(time, timezone),
+        None => timestamp.to_string(),
+    }
+}
+
+// Human readable time in the report timezone, machine output stays RFC3339 UTC
+pub fn format_timestamp(time: DateTime<Utc>, timezone: Tz) -> String {
+    time.with_timezone(&timezone)
+        .format("%Y-%m-%d %H:%M:%S %Z")
+        .to_string()
+}
+
+pub fn generate_digest(entries: &[String]) -> String {
+    let mut digest = String::from("Alerts held back during quiet hours:\n\n");
+    for entry in entries {
+        digest.push_str(&format!("  {}\n", entry));
+    }
+    digest
+}
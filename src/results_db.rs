@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use dusa_collection_utils::errors::{ErrorArrayItem, Errors};
+use rusqlite::{params, Connection};
+
+use crate::health::HealthCheckResult;
+
+// Created on first use, existing databases are left as they are
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS results (
+    id INTEGER PRIMARY KEY,
+    at INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    status TEXT NOT NULL,
+    dns_time_ms INTEGER,
+    response_time_ms INTEGER,
+    body_time_ms INTEGER,
+    status_code INTEGER,
+    body_bytes INTEGER,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS results_url_at ON results (url, at);
+";
+
+// Every cycle's results as rows of a local SQLite database, for ad-hoc SQL over
+// uptime and latency. Unlike the history file nothing is sampled away.
+pub struct ResultsDb {
+    path: String,
+}
+
+impl ResultsDb {
+    pub fn new(path: &str) -> Self {
+        ResultsDb {
+            path: path.to_string(),
+        }
+    }
+
+    // The connection is opened per cycle so nothing is held between them
+    pub fn record(
+        &self,
+        results: &HashMap<String, HealthCheckResult>,
+        now: u64,
+    ) -> Result<(), ErrorArrayItem> {
+        let mut connection = Connection::open(&self.path).map_err(sqlite_error)?;
+        connection.execute_batch(SCHEMA).map_err(sqlite_error)?;
+
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        {
+            let mut insert = transaction
+                .prepare(
+                    "INSERT INTO results (at, url, status, dns_time_ms, response_time_ms,
+                        body_time_ms, status_code, body_bytes, error)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                )
+                .map_err(sqlite_error)?;
+            for (url, result) in results {
+                insert
+                    .execute(params![
+                        now as i64,
+                        url,
+                        result.status.to_string(),
+                        result.dns_time_ms.map(|ms| ms as i64),
+                        result.response_time_ms.map(|ms| ms as i64),
+                        result.body_time_ms.map(|ms| ms as i64),
+                        result.status_code,
+                        result.body_bytes.map(|bytes| bytes as i64),
+                        result.error.as_ref().map(|error| error.to_string()),
+                    ])
+                    .map_err(sqlite_error)?;
+            }
+        }
+        transaction.commit().map_err(sqlite_error)
+    }
+}
+
+fn sqlite_error(e: rusqlite::Error) -> ErrorArrayItem {
+    ErrorArrayItem::new(Errors::GeneralError, format!("sqlite: {}", e))
+}
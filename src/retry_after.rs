@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+
+// Seconds a Retry-After header asks for, in either the delay-seconds or the
+// HTTP-date form. A date in the past means retry right away.
+pub fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(seconds);
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        date.with_timezone(&Utc)
+            .signed_duration_since(now)
+            .num_seconds()
+            .max(0) as u64,
+    )
+}
+
+// The requested wait capped at `max_seconds`, plus up to `jitter_ms` so monitors
+// told the same thing don't all come back at the same instant
+pub fn retry_delay(requested_seconds: u64, max_seconds: u64, jitter_ms: u64) -> Duration {
+    let jitter = if jitter_ms > 0 {
+        rand::thread_rng().gen_range(0..=jitter_ms)
+    } else {
+        0
+    };
+    Duration::from_secs(requested_seconds.min(max_seconds)) + Duration::from_millis(jitter)
+}
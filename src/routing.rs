@@ -0,0 +1,95 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::alerts::Alert;
+use crate::changes::CycleChanges;
+use crate::config::SiteConfig;
+use crate::health::HealthCheckResult;
+
+// The slice of a cycle that concerns some of the sites
+pub struct ScopedCycle {
+    pub results: HashMap<String, HealthCheckResult>,
+    pub alerts: Vec<Alert>,
+    pub changes: CycleChanges,
+}
+
+// Sites that stay in the report sent through the global channels
+pub fn global_sites(sites: &[SiteConfig]) -> Vec<SiteConfig> {
+    sites
+        .iter()
+        .filter(|site| site.in_global_report())
+        .cloned()
+        .collect()
+}
+
+// The sites routed to each recipient group, so each group gets one report with all of
+// its sites and nobody receives another team's results
+pub fn routed_sites(sites: &[SiteConfig]) -> BTreeMap<String, Vec<SiteConfig>> {
+    let mut routed: BTreeMap<String, Vec<SiteConfig>> = BTreeMap::new();
+    for site in sites {
+        for name in &site.recipients {
+            let group = routed.entry(name.clone()).or_default();
+            if !group
+                .iter()
+                .any(|other| other.resolved_url() == site.resolved_url())
+            {
+                group.push(site.clone());
+            }
+        }
+    }
+    routed
+}
+
+// Only the results, alerts and changes of `sites`
+pub fn scope(
+    sites: &[SiteConfig],
+    results: &HashMap<String, HealthCheckResult>,
+    alerts: &[Alert],
+    changes: &CycleChanges,
+) -> ScopedCycle {
+    let urls: Vec<String> = sites.iter().map(|site| site.resolved_url()).collect();
+    let included = |url: &String| urls.contains(url);
+    ScopedCycle {
+        results: results
+            .iter()
+            .filter(|(url, _)| included(url))
+            .map(|(url, result)| (url.clone(), result.clone()))
+            .collect(),
+        alerts: alerts
+            .iter()
+            .filter(|alert| included(&alert.url))
+            .cloned()
+            .collect(),
+        changes: CycleChanges {
+            newly_down: changes
+                .newly_down
+                .iter()
+                .filter(|url| included(url))
+                .cloned()
+                .collect(),
+            recovered: changes
+                .recovered
+                .iter()
+                .filter(|url| included(url))
+                .cloned()
+                .collect(),
+            latency: changes
+                .latency
+                .iter()
+                .filter(|change| included(&change.url))
+                .cloned()
+                .collect(),
+            pending_recovery: changes
+                .pending_recovery
+                .iter()
+                .filter(|pending| included(&pending.url))
+                .cloned()
+                .collect(),
+            transitions: changes
+                .transitions
+                .iter()
+                .filter(|transition| included(&transition.url))
+                .cloned()
+                .collect(),
+        },
+    }
+}
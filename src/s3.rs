@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::S3Config;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// A report that couldn't be uploaded, kept on disk until the next attempt
+#[derive(Debug, Serialize, Deserialize)]
+struct SpooledReport {
+    key: String,
+    body: String,
+}
+
+// Uploads cycle reports to an S3-compatible bucket with SigV4 signed PUTs
+pub struct S3Uploader {
+    config: S3Config,
+    access_key: String,
+    secret_key: String,
+    client: Client,
+}
+
+impl S3Uploader {
+    // Credentials come from the standard AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY
+    pub fn new(config: S3Config) -> Result<Self, String> {
+        let access_key = std::env::var("AWS_ACCESS_KEY_ID")
+            .map_err(|_| String::from("AWS_ACCESS_KEY_ID is not set"))?;
+        let secret_key = std::env::var("AWS_SECRET_ACCESS_KEY")
+            .map_err(|_| String::from("AWS_SECRET_ACCESS_KEY is not set"))?;
+        let client = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(S3Uploader {
+            config,
+            access_key,
+            secret_key,
+            client,
+        })
+    }
+
+    // Upload this cycle's report, retrying anything spooled earlier first.
+    // Failures are spooled, never returned, so the loop carries on.
+    pub async fn upload_report(&self, body: String) {
+        self.retry_spooled().await;
+
+        let now = Utc::now();
+        let key = format!(
+            "{}{}.json",
+            self.config.key_prefix,
+            now.format("%Y/%m/%d/%Y%m%dT%H%M%SZ")
+        );
+        if let Err(e) = self.put_object(&key, body.as_bytes(), now).await {
+            log!(
+                LogLevel::Warn,
+                "S3 upload of {} failed, spooling: {}",
+                key,
+                e
+            );
+            self.spool(SpooledReport { key, body });
+        }
+    }
+
+    async fn retry_spooled(&self) {
+        let entries = match fs::read_dir(&self.config.spool_dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let spooled: SpooledReport = match fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| serde_json::from_str(&content).ok())
+            {
+                Some(spooled) => spooled,
+                None => continue,
+            };
+
+            match self
+                .put_object(&spooled.key, spooled.body.as_bytes(), Utc::now())
+                .await
+            {
+                Ok(_) => {
+                    log!(LogLevel::Info, "Uploaded spooled report {}", spooled.key);
+                    let _ = fs::remove_file(&path);
+                }
+                Err(e) => {
+                    log!(LogLevel::Debug, "Spooled report still failing: {}", e);
+                    // The endpoint is likely still down, try the rest next cycle
+                    return;
+                }
+            }
+        }
+    }
+
+    fn spool(&self, report: SpooledReport) {
+        let result = fs::create_dir_all(&self.config.spool_dir).and_then(|_| {
+            let name = report.key.replace('/', "_");
+            let data = serde_json::to_string(&report).unwrap_or_default();
+            fs::write(Path::new(&self.config.spool_dir).join(name), data)
+        });
+        if let Err(e) = result {
+            log!(LogLevel::Error, "Failed to spool S3 report: {}", e);
+        }
+    }
+
+    async fn put_object(&self, key: &str, body: &[u8], now: DateTime<Utc>) -> Result<(), String> {
+        let path = format!("/{}/{}", self.config.bucket, uri_encode(key));
+        let url = Url::parse(&self.config.endpoint)
+            .and_then(|endpoint| endpoint.join(&path))
+            .map_err(|e| e.to_string())?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(format!("S3 endpoint {} has no host", url)),
+        };
+
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(body));
+
+        let canonical_request = format!(
+            "PUT\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            path, host, payload_hash, amz_date, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.config.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut signing_key = hmac(
+            format!("AWS4{}", self.secret_key).as_bytes(),
+            date.as_bytes(),
+        );
+        for part in [self.config.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac(&signing_key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+            self.access_key, scope, signature
+        );
+
+        let response = self
+            .client
+            .put(url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("authorization", authorization)
+            .header("content-type", "application/json")
+            .body(body.to_vec())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("S3 responded with {}", response.status()))
+        }
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // HMAC accepts keys of any length, so this can't fail
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// SigV4 encoding: everything but unreserved characters and the path separator
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
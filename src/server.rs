@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use tokio::net::TcpListener;
+
+use crate::health::HealthCheckResult;
+use crate::latency::Histogram;
+use crate::pause::PauseFlag;
+use crate::status_page::StatusPage;
+
+// The latest cycle, as served by the embedded status server
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StatusSnapshot {
+    pub updated_at: Option<String>,
+    // Profile the cycle ran under, None for the base settings
+    pub profile: Option<String>,
+    // Whether this instance held the notification lease, None without leader election
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leader: Option<bool>,
+    // Why the latest cycle was skipped, the site results are then from an earlier one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+    pub health_score: Option<f64>,
+    pub rolling_health_score: Option<f64>,
+    pub sites: HashMap<String, HealthCheckResult>,
+    // Percentage of recent cycles each site was up, over health_score_window
+    pub uptime_percent: HashMap<String, f64>,
+    // Response time histograms since startup, only exposed through /metrics
+    #[serde(skip)]
+    pub histogram_buckets: Vec<u64>,
+    #[serde(skip)]
+    pub histograms: HashMap<String, Histogram>,
+    // Results the FIFO output dropped since startup, None without one
+    #[serde(skip)]
+    pub fifo_dropped: Option<u64>,
+}
+
+pub type SharedStatus = Arc<RwLock<StatusSnapshot>>;
+
+// Serve the status and control endpoints until the process exits
+pub async fn serve(
+    listen: SocketAddr,
+    status: SharedStatus,
+    pause: PauseFlag,
+    config: Arc<serde_json::Value>,
+    status_page: Option<StatusPage>,
+) {
+    let listener = match TcpListener::bind(listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            log!(
+                LogLevel::Error,
+                "Failed to start status server on {}: {}",
+                listen,
+                e
+            );
+            return;
+        }
+    };
+    log!(LogLevel::Info, "Status server listening on {}", listen);
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                log!(LogLevel::Warn, "Failed to accept status connection: {}", e);
+                continue;
+            }
+        };
+
+        let status = status.clone();
+        let pause = pause.clone();
+        let config = config.clone();
+        let status_page = status_page.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                handle(
+                    request,
+                    status.clone(),
+                    pause.clone(),
+                    config.clone(),
+                    status_page.clone(),
+                )
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                log!(LogLevel::Debug, "Status connection closed: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle(
+    request: Request<Incoming>,
+    status: SharedStatus,
+    pause: PauseFlag,
+    config: Arc<serde_json::Value>,
+    status_page: Option<StatusPage>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let snapshot = match status.read() {
+        Ok(snapshot) => snapshot.clone(),
+        Err(_) => return Ok(respond(StatusCode::INTERNAL_SERVER_ERROR, "text/plain", "")),
+    };
+
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/") if status_page.is_some() => {
+            let page = status_page.as_ref().map(|page| page.render(&snapshot));
+            respond(
+                StatusCode::OK,
+                "text/html; charset=utf-8",
+                page.unwrap_or_default(),
+            )
+        }
+        (&Method::GET, "/status") => match serde_json::to_string_pretty(&snapshot) {
+            Ok(json) => respond(StatusCode::OK, "application/json", json),
+            Err(e) => respond(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "text/plain",
+                e.to_string(),
+            ),
+        },
+        (&Method::GET, "/config") => match serde_json::to_string_pretty(config.as_ref()) {
+            Ok(json) => respond(StatusCode::OK, "application/json", json),
+            Err(e) => respond(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "text/plain",
+                e.to_string(),
+            ),
+        },
+        (&Method::GET, "/metrics") => respond(
+            StatusCode::OK,
+            "text/plain; version=0.0.4",
+            render_metrics(&snapshot),
+        ),
+        // Liveness only needs the process to answer, readiness a completed cycle
+        (&Method::GET, "/livez") => respond(StatusCode::OK, "text/plain", "ok"),
+        (&Method::GET, "/readyz") if snapshot.updated_at.is_some() => {
+            respond(StatusCode::OK, "text/plain", "ready")
+        }
+        (&Method::GET, "/readyz") => respond(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "text/plain",
+            "no check cycle completed yet",
+        ),
+        (&Method::POST, "/pause") => set_paused(&pause, true),
+        (&Method::POST, "/resume") => set_paused(&pause, false),
+        _ => respond(StatusCode::NOT_FOUND, "text/plain", "not found"),
+    };
+    Ok(response)
+}
+
+fn set_paused(pause: &PauseFlag, paused: bool) -> Response<Full<Bytes>> {
+    if let Err(e) = pause.set(paused) {
+        log!(LogLevel::Error, "Failed to persist pause flag: {}", e);
+        return respond(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "text/plain",
+            e.to_string(),
+        );
+    }
+    log!(
+        LogLevel::Info,
+        "Monitoring {} via the control endpoint",
+        if paused { "paused" } else { "resumed" }
+    );
+    respond(
+        StatusCode::OK,
+        "application/json",
+        format!("{{\"paused\":{}}}", paused),
+    )
+}
+
+fn respond(
+    status: StatusCode,
+    content_type: &str,
+    body: impl Into<Bytes>,
+) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(body.into()));
+    *response.status_mut() = status;
+    if let Ok(value) = content_type.parse() {
+        response.headers_mut().insert(CONTENT_TYPE, value);
+    }
+    response
+}
+
+// Prometheus text exposition of the latest cycle
+pub fn render_metrics(snapshot: &StatusSnapshot) -> String {
+    let mut metrics = String::new();
+
+    metrics.push_str("# HELP website_monitor_up Whether the site was up on the last check\n");
+    metrics.push_str("# TYPE website_monitor_up gauge\n");
+    for (url, result) in &snapshot.sites {
+        let up = if result.status.is_down() { 0 } else { 1 };
+        metrics.push_str(&format!(
+            "website_monitor_up{{url=\"{}\"}} {}\n",
+            escape_label(url),
+            up
+        ));
+    }
+
+    metrics.push_str("# HELP website_monitor_response_time_ms Response time of the last check\n");
+    metrics.push_str("# TYPE website_monitor_response_time_ms gauge\n");
+    for (url, result) in &snapshot.sites {
+        if let Some(response_time) = result.response_time_ms {
+            metrics.push_str(&format!(
+                "website_monitor_response_time_ms{{url=\"{}\"}} {}\n",
+                escape_label(url),
+                response_time
+            ));
+        }
+    }
+
+    let slos: Vec<_> = snapshot
+        .sites
+        .iter()
+        .filter_map(|(url, result)| result.slo.map(|slo| (escape_label(url), slo)))
+        .collect();
+    if !slos.is_empty() {
+        metrics.push_str(
+            "# HELP website_monitor_slo_burn_rate Error budget burn rate over slo_burn_window_hours\n",
+        );
+        metrics.push_str("# TYPE website_monitor_slo_burn_rate gauge\n");
+        for (url, slo) in &slos {
+            metrics.push_str(&format!(
+                "website_monitor_slo_burn_rate{{url=\"{}\"}} {}\n",
+                url, slo.burn_rate
+            ));
+        }
+        metrics.push_str(
+            "# HELP website_monitor_slo_budget_remaining Share of the latency_slo error budget left\n",
+        );
+        metrics.push_str("# TYPE website_monitor_slo_budget_remaining gauge\n");
+        for (url, slo) in &slos {
+            metrics.push_str(&format!(
+                "website_monitor_slo_budget_remaining{{url=\"{}\"}} {}\n",
+                url, slo.budget_remaining
+            ));
+        }
+    }
+
+    if !snapshot.histograms.is_empty() {
+        metrics.push_str(
+            "# HELP website_monitor_response_duration_ms Response times since the monitor started\n",
+        );
+        metrics.push_str("# TYPE website_monitor_response_duration_ms histogram\n");
+    }
+    for (url, histogram) in &snapshot.histograms {
+        let url = escape_label(url);
+        let mut cumulative = 0;
+        for (bound, count) in snapshot.histogram_buckets.iter().zip(&histogram.counts) {
+            cumulative += count;
+            metrics.push_str(&format!(
+                "website_monitor_response_duration_ms_bucket{{url=\"{}\",le=\"{}\"}} {}\n",
+                url, bound, cumulative
+            ));
+        }
+        metrics.push_str(&format!(
+            "website_monitor_response_duration_ms_bucket{{url=\"{}\",le=\"+Inf\"}} {}\n",
+            url, histogram.count
+        ));
+        metrics.push_str(&format!(
+            "website_monitor_response_duration_ms_sum{{url=\"{}\"}} {}\n",
+            url, histogram.sum
+        ));
+        metrics.push_str(&format!(
+            "website_monitor_response_duration_ms_count{{url=\"{}\"}} {}\n",
+            url, histogram.count
+        ));
+    }
+
+    if let Some(health_score) = snapshot.health_score {
+        metrics.push_str(
+            "# HELP website_monitor_health_score Weighted share of sites up this cycle\n",
+        );
+        metrics.push_str("# TYPE website_monitor_health_score gauge\n");
+        metrics.push_str(&format!("website_monitor_health_score {}\n", health_score));
+    }
+    if let Some(rolling) = snapshot.rolling_health_score {
+        metrics.push_str(
+            "# HELP website_monitor_rolling_health_score Weighted uptime over the rolling window\n",
+        );
+        metrics.push_str("# TYPE website_monitor_rolling_health_score gauge\n");
+        metrics.push_str(&format!(
+            "website_monitor_rolling_health_score {}\n",
+            rolling
+        ));
+    }
+    if let Some(dropped) = snapshot.fifo_dropped {
+        metrics.push_str(
+            "# HELP website_monitor_fifo_dropped_total Results not written to the FIFO since startup\n",
+        );
+        metrics.push_str("# TYPE website_monitor_fifo_dropped_total counter\n");
+        metrics.push_str(&format!("website_monitor_fifo_dropped_total {}\n", dropped));
+    }
+
+    metrics
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,88 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, Url};
+use sha2::{Sha256, Sha512};
+
+use crate::config::{SignatureEncoding, SignedPart, SigningAlgorithm, SigningConfig};
+
+// The url the request is sent to, with the site's path and query parameters in place
+pub fn signed_url(url: &str) -> Result<Url, String> {
+    let mut url = Url::parse(url).map_err(|e| format!("invalid url {}: {}", url, e))?;
+    if url.query() == Some("") {
+        url.set_query(None);
+    }
+    Ok(url)
+}
+
+// The signature header, and the timestamp header when the timestamp is signed. The
+// Host is the host_header when one is set, as that's what the gateway sees.
+pub fn signature_headers(
+    signing: &SigningConfig,
+    method: &Method,
+    url: &Url,
+    host_header: Option<&str>,
+    body: &[u8],
+    timestamp: u64,
+) -> Vec<(String, String)> {
+    let host = match (host_header, url.host_str(), url.port()) {
+        (Some(host_header), _, _) => host_header.to_string(),
+        (None, Some(host), Some(port)) => format!("{}:{}", host, port),
+        (None, host, _) => host.unwrap_or_default().to_string(),
+    };
+    let string_to_sign = string_to_sign(signing, method.as_str(), url, &host, body, timestamp);
+
+    let mut headers = vec![(signing.header.clone(), signature(signing, &string_to_sign))];
+    if signing.sign.contains(&SignedPart::Timestamp) {
+        headers.push((signing.timestamp_header.clone(), timestamp.to_string()));
+    }
+    headers
+}
+
+// The signed parts in the configured order, one per line
+fn string_to_sign(
+    signing: &SigningConfig,
+    method: &str,
+    url: &Url,
+    host: &str,
+    body: &[u8],
+    timestamp: u64,
+) -> String {
+    signing
+        .sign
+        .iter()
+        .map(|part| match part {
+            SignedPart::Method => method.to_string(),
+            SignedPart::Host => host.to_string(),
+            SignedPart::Path => url.path().to_string(),
+            SignedPart::Query => url.query().unwrap_or_default().to_string(),
+            SignedPart::Timestamp => timestamp.to_string(),
+            SignedPart::Body => String::from_utf8_lossy(body).into_owned(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// The prefix, then the encoded MAC of the string to sign
+fn signature(signing: &SigningConfig, string_to_sign: &str) -> String {
+    // HMAC accepts keys of any length, so neither can fail
+    let mac = match signing.algorithm {
+        SigningAlgorithm::HmacSha256 => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(signing.secret.as_bytes())
+                .expect("HMAC accepts any key length");
+            mac.update(string_to_sign.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        }
+        SigningAlgorithm::HmacSha512 => {
+            let mut mac = Hmac::<Sha512>::new_from_slice(signing.secret.as_bytes())
+                .expect("HMAC accepts any key length");
+            mac.update(string_to_sign.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        }
+    };
+    let encoded = match signing.encoding {
+        SignatureEncoding::Hex => hex::encode(mac),
+        SignatureEncoding::Base64 => BASE64.encode(mac),
+    };
+    format!("{}{}", signing.prefix, encoded)
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::fs;
+
+use artisan_middleware::config::AppConfig;
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use dusa_collection_utils::errors::{ErrorArrayItem, Errors};
+use dusa_collection_utils::types::PathType;
+use serde::{Deserialize, Serialize};
+
+use crate::at_rest::AtRest;
+use crate::coalesce::PendingEvent;
+use crate::doh::DnssecStatus;
+use crate::health::CheckStatus;
+use crate::sla::UptimeDay;
+use crate::slo::SloHour;
+
+// Data remembered about a single url between cycles
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct SiteState {
+    pub baseline_body_bytes: Option<u64>,
+    #[serde(default)]
+    pub canary_value: Option<String>,
+    // When the canary value was first seen or last changed
+    #[serde(default)]
+    pub canary_changed_at: Option<u64>,
+    // Last value of the watch_header
+    #[serde(default)]
+    pub watched_header: Option<String>,
+    // The previous cycle's outcome, for the changes section of the report
+    #[serde(default)]
+    pub last_status: Option<CheckStatus>,
+    #[serde(default)]
+    pub last_response_time_ms: Option<u128>,
+    // Consecutive cycles up since going DOWN, until recovery_confirm_cycles (or the
+    // site's resolve_after_successes) is reached
+    #[serde(default)]
+    pub recovery_up_cycles: u32,
+    // Failure category of the announced DOWN, for alert deduplication
+    #[serde(default)]
+    pub last_error_category: Option<String>,
+    // Sampling bookkeeping for the history file
+    #[serde(default)]
+    pub history_last_status: Option<CheckStatus>,
+    #[serde(default)]
+    pub history_last_recorded_at: Option<u64>,
+    #[serde(default)]
+    pub history_cycles_skipped: u32,
+    // Recent response times of successful checks, oldest first
+    #[serde(default)]
+    pub latency_window: Vec<u128>,
+    // Whether the site was up in each of the last health_score_window cycles, oldest first
+    #[serde(default)]
+    pub uptime_window: Vec<bool>,
+    // Last DNSSEC status seen, to alert when a secure host stops being secure
+    #[serde(default)]
+    pub dnssec_status: Option<DnssecStatus>,
+    // When the site last answered at all, for stale detection
+    #[serde(default)]
+    pub last_checked_at: Option<u64>,
+    // Daily check tallies over the sla_window_days, oldest first
+    #[serde(default)]
+    pub sla_days: Vec<UptimeDay>,
+    // Below the sla_target and not yet recovered past the hysteresis
+    #[serde(default)]
+    pub sla_breached: bool,
+    // Hourly latency_slo tallies over the slo_window_days, oldest first
+    #[serde(default)]
+    pub slo_hours: Vec<SloHour>,
+    // The error budget is on course to run out, alerted once until it isn't
+    #[serde(default)]
+    pub slo_burning: bool,
+    #[serde(default)]
+    pub slo_exhausted: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct SiteStates {
+    pub sites: HashMap<String, SiteState>,
+    // Alerts held back during quiet hours, sent as a digest afterwards
+    #[serde(default)]
+    pub quiet_digest: Vec<String>,
+    // Weighted health score of recent cycles, oldest first
+    #[serde(default)]
+    pub health_scores: Vec<f64>,
+    // DOWN and recovery events waiting for the coalescing window to close
+    #[serde(default)]
+    pub pending_events: Vec<PendingEvent>,
+    // One-shot checks that already ran, by OneShotConfig::id
+    #[serde(default)]
+    pub fired_one_shots: Vec<String>,
+}
+
+impl SiteStates {
+    pub fn get_path(config: &AppConfig) -> PathType {
+        PathType::Content(format!("/tmp/.{}.sites.json", config.app_name))
+    }
+
+    // Load the per-site state, starting fresh if it's missing or unreadable
+    pub fn load(path: &PathType, at_rest: &AtRest) -> Self {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(_) => {
+                log!(LogLevel::Info, "No previous site state found, starting fresh");
+                return Self::default();
+            }
+        };
+
+        let content = match at_rest.decode(&content) {
+            Ok(content) => content,
+            Err(e) => {
+                log!(LogLevel::Warn, "Discarding unreadable site state: {}", e);
+                return Self::default();
+            }
+        };
+
+        match serde_json::from_slice(&content) {
+            Ok(states) => states,
+            Err(e) => {
+                log!(LogLevel::Warn, "Discarding unreadable site state: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &PathType, at_rest: &AtRest) -> Result<(), ErrorArrayItem> {
+        let data = serde_json::to_string_pretty(self)
+            .map_err(|e| ErrorArrayItem::new(Errors::JsonCreation, e.to_string()))?;
+        fs::write(path, at_rest.encode(data.as_bytes())?)?;
+        Ok(())
+    }
+
+    pub fn entry(&mut self, url: &str) -> &mut SiteState {
+        self.sites.entry(url.to_string()).or_default()
+    }
+
+    // Hold something back for the quiet hours digest, a condition that lasts all
+    // night is listed once with the time it was first seen
+    pub fn hold_for_digest(&mut self, timestamp: &str, entry: &str) {
+        let suffix = format!(" {}", entry);
+        if !self.quiet_digest.iter().any(|held| held.ends_with(&suffix)) {
+            self.quiet_digest.push(format!("{} {}", timestamp, entry));
+        }
+    }
+}
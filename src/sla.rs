@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::{Alert, Severity};
+use crate::config::SiteConfig;
+use crate::health::HealthCheckResult;
+use crate::site_state::SiteStates;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+// Checks of one UTC day, the sampled history file can't be counted for uptime
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UptimeDay {
+    pub day: u64,
+    pub up: u32,
+    pub total: u32,
+}
+
+// Rolling uptime of a site with an sla_target
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SlaStatus {
+    pub uptime_percent: f64,
+    pub target_percent: f64,
+    pub breached: bool,
+}
+
+// Count this cycle into the daily tallies of each site with an sla_target and work
+// out its uptime over the last window_days. A site is breached once it drops below
+// the target and only recovers once it is hysteresis_percent above it again, so it
+// can't flap around the threshold. Alerts on both transitions.
+pub fn update_sla(
+    sites: &[SiteConfig],
+    results: &mut HashMap<String, HealthCheckResult>,
+    site_states: &mut SiteStates,
+    window_days: u64,
+    hysteresis_percent: f64,
+    now: u64,
+) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+    let today = now / SECONDS_PER_DAY;
+    let first_day = today.saturating_sub(window_days.max(1) - 1);
+
+    for site in sites {
+        let target = match site.sla_target {
+            Some(target) => target,
+            None => continue,
+        };
+        let url = site.resolved_url();
+        let result = match results.get_mut(&url) {
+            Some(result) => result,
+            None => continue,
+        };
+
+        let state = site_states.entry(&url);
+        match state.sla_days.last_mut() {
+            Some(last) if last.day == today => {}
+            _ => state.sla_days.push(UptimeDay {
+                day: today,
+                ..UptimeDay::default()
+            }),
+        }
+        state.sla_days.retain(|day| day.day >= first_day);
+        if let Some(day) = state.sla_days.last_mut() {
+            day.total += 1;
+            if !result.status.is_down() {
+                day.up += 1;
+            }
+        }
+
+        let up: u64 = state.sla_days.iter().map(|day| day.up as u64).sum();
+        let total: u64 = state.sla_days.iter().map(|day| day.total as u64).sum();
+        let uptime = up as f64 * 100.0 / total.max(1) as f64;
+
+        let recovered_at = (target + hysteresis_percent).min(100.0);
+        if !state.sla_breached && uptime < target {
+            state.sla_breached = true;
+            alerts.push(Alert {
+                url: url.clone(),
+                severity: Severity::Critical,
+                message: format!(
+                    "Uptime over {} days fell to {:.3}%, below the SLA target of {}%",
+                    window_days, uptime, target
+                ),
+            });
+        } else if state.sla_breached && uptime >= recovered_at {
+            state.sla_breached = false;
+            alerts.push(Alert {
+                url: url.clone(),
+                severity: Severity::Info,
+                message: format!(
+                    "Uptime over {} days is back to {:.3}%, meeting the SLA target of {}%",
+                    window_days, uptime, target
+                ),
+            });
+        }
+
+        result.sla = Some(SlaStatus {
+            uptime_percent: uptime,
+            target_percent: target,
+            breached: state.sla_breached,
+        });
+    }
+
+    alerts
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::{Alert, Severity};
+use crate::config::SiteConfig;
+use crate::health::HealthCheckResult;
+use crate::site_state::SiteStates;
+
+const SECONDS_PER_HOUR: u64 = 3_600;
+
+// Checks of one UTC hour against a site's latency_slo
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SloHour {
+    pub hour: u64,
+    pub good: u32,
+    pub total: u32,
+}
+
+// Error budget of a site with a latency_slo
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SloStatus {
+    // Share of checks over the window that were UP and within the threshold
+    pub good_percent: f64,
+    pub target_percent: f64,
+    pub threshold_ms: u64,
+    // Share of the window's error budget left, negative once overspent
+    pub budget_remaining: f64,
+    // Bad checks over the burn window relative to the budget, 1.0 spends exactly the
+    // whole budget over the whole window
+    pub burn_rate: f64,
+    // When the budget runs out at the current burn rate, if that's within the window
+    pub exhausted_in_hours: Option<f64>,
+}
+
+// Count this cycle into the hourly tallies of each site with a latency_slo, a check is
+// good when the site isn't DOWN and answered within the threshold. The budget is the
+// share of bad checks the target allows over window_days, the burn rate how fast the
+// last burn_window_hours spend it. Alerts once when the budget would run out before
+// the bad checks age out of the window, and again when that's no longer the case.
+pub fn update_slo(
+    sites: &[SiteConfig],
+    results: &mut HashMap<String, HealthCheckResult>,
+    site_states: &mut SiteStates,
+    window_days: u64,
+    burn_window_hours: u64,
+    now: u64,
+) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+    let window_hours = window_days.max(1) * 24;
+    let this_hour = now / SECONDS_PER_HOUR;
+    let first_hour = this_hour.saturating_sub(window_hours - 1);
+    let first_burn_hour = this_hour.saturating_sub(burn_window_hours.clamp(1, window_hours) - 1);
+
+    for site in sites {
+        let slo = match &site.latency_slo {
+            Some(slo) => slo,
+            None => continue,
+        };
+        // A 100% target leaves no budget to burn
+        let allowed_bad = 1.0 - slo.target_percent / 100.0;
+        if allowed_bad <= 0.0 {
+            continue;
+        }
+        let url = site.resolved_url();
+        let result = match results.get_mut(&url) {
+            Some(result) => result,
+            None => continue,
+        };
+
+        let state = site_states.entry(&url);
+        match state.slo_hours.last_mut() {
+            Some(last) if last.hour == this_hour => {}
+            _ => state.slo_hours.push(SloHour {
+                hour: this_hour,
+                ..SloHour::default()
+            }),
+        }
+        state.slo_hours.retain(|hour| hour.hour >= first_hour);
+        if let Some(hour) = state.slo_hours.last_mut() {
+            hour.total += 1;
+            let good = !result.status.is_down()
+                && result
+                    .response_time_ms
+                    .is_some_and(|response_time| response_time <= slo.threshold_ms as u128);
+            if good {
+                hour.good += 1;
+            }
+        }
+
+        let tally = |from: u64| {
+            state
+                .slo_hours
+                .iter()
+                .filter(|hour| hour.hour >= from)
+                .fold((0u64, 0u64), |(good, total), hour| {
+                    (good + hour.good as u64, total + hour.total as u64)
+                })
+        };
+        let (good, total) = tally(first_hour);
+        let (burn_good, burn_total) = tally(first_burn_hour);
+        let bad_share = (total - good) as f64 / total.max(1) as f64;
+        let burn_bad_share = (burn_total - burn_good) as f64 / burn_total.max(1) as f64;
+
+        let budget_remaining = 1.0 - bad_share / allowed_bad;
+        let burn_rate = burn_bad_share / allowed_bad;
+        // At burn rate 1 the whole budget lasts the whole window
+        let exhausted_in_hours = if budget_remaining <= 0.0 {
+            Some(0.0)
+        } else if burn_rate > 0.0 {
+            Some(budget_remaining * window_hours as f64 / burn_rate)
+                .filter(|hours| *hours < window_hours as f64)
+        } else {
+            None
+        };
+
+        // Spending it is a warning, having spent it critical
+        let burning = exhausted_in_hours.is_some();
+        let exhausted = budget_remaining <= 0.0;
+        if exhausted && !state.slo_exhausted {
+            alerts.push(Alert {
+                url: url.clone(),
+                severity: Severity::Critical,
+                message: format!(
+                    "Error budget for {}% of checks within {} ms over {} days is spent ({:.3}% good)",
+                    slo.target_percent,
+                    slo.threshold_ms,
+                    window_days,
+                    (1.0 - bad_share) * 100.0
+                ),
+            });
+        } else if burning && !state.slo_burning {
+            alerts.push(Alert {
+                url: url.clone(),
+                severity: Severity::Warning,
+                message: format!(
+                    "Burn rate {:.2}x over the last {} h, the error budget ({:.1}% left) runs out in {:.1} h",
+                    burn_rate,
+                    burn_window_hours,
+                    budget_remaining * 100.0,
+                    exhausted_in_hours.unwrap_or_default()
+                ),
+            });
+        } else if !burning && state.slo_burning {
+            alerts.push(Alert {
+                url: url.clone(),
+                severity: Severity::Info,
+                message: format!(
+                    "Burn rate is down to {:.2}x, the error budget ({:.1}% left) lasts the {} day window",
+                    burn_rate,
+                    budget_remaining * 100.0,
+                    window_days
+                ),
+            });
+        }
+        state.slo_burning = burning;
+        state.slo_exhausted = exhausted;
+
+        result.slo = Some(SloStatus {
+            good_percent: (1.0 - bad_share) * 100.0,
+            target_percent: slo.target_percent,
+            threshold_ms: slo.threshold_ms,
+            budget_remaining,
+            burn_rate,
+            exhausted_in_hours,
+        });
+    }
+
+    alerts
+}
@@ -0,0 +1,38 @@
+use regex::Regex;
+
+use crate::config::REDACTED;
+
+// Extra bytes redacted past the cut, so a secret straddling it isn't half shown
+const REDACT_LOOKAHEAD_BYTES: usize = 256;
+
+// The start of a failing response's body for the alert: control characters and runs
+// of whitespace flattened to single spaces, matches of the secret patterns replaced,
+// then cut to `max_bytes`. None when off or nothing was received.
+pub fn body_snippet(body: &[u8], max_bytes: usize, redact_patterns: &[String]) -> Option<String> {
+    if max_bytes == 0 || body.is_empty() {
+        return None;
+    }
+
+    let window = &body[..body.len().min(max_bytes + REDACT_LOOKAHEAD_BYTES)];
+    let mut text = String::from_utf8_lossy(window)
+        .split(|c: char| c.is_whitespace() || c.is_control())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    // Invalid patterns were already warned about when the settings were loaded
+    for pattern in redact_patterns {
+        if let Ok(pattern) = Regex::new(pattern) {
+            text = pattern.replace_all(&text, REDACTED).into_owned();
+        }
+    }
+
+    if text.len() > max_bytes {
+        let mut cut = max_bytes;
+        while !text.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        text.truncate(cut);
+        text.push_str("...");
+    }
+    Some(text)
+}
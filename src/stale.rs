@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+
+use crate::health::HealthCheckResult;
+use crate::site_state::SiteStates;
+
+// Remember when each site last gave an answer (any HTTP status), and flag results
+// without one as stale once that is longer ago than stale_after_seconds. A timeout
+// or refused connection doesn't confirm anything about the site itself.
+pub fn mark_stale(
+    results: &mut HashMap<String, HealthCheckResult>,
+    states: &mut SiteStates,
+    stale_after_seconds: Option<u64>,
+    now: u64,
+) {
+    for (url, result) in results.iter_mut() {
+        let state = states.sites.entry(url.clone()).or_default();
+        // Unreachable is the expected answer of an inverted check
+        if result.status_code.is_some() || result.expect_down {
+            state.last_checked_at = Some(now);
+            continue;
+        }
+
+        // Never answered: count from the first attempt
+        let last_checked_at = *state.last_checked_at.get_or_insert(now);
+        if let Some(stale_after) = stale_after_seconds {
+            if now.saturating_sub(last_checked_at) > stale_after {
+                result.stale_since = Some(last_checked_at);
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use artisan_middleware::config::AppConfig;
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use artisan_middleware::state_persistence::{AppState, StatePersistence};
+use artisan_middleware::timestamp::current_timestamp;
+use dusa_collection_utils::errors::{ErrorArrayItem, Errors};
+use dusa_collection_utils::types::PathType;
+
+use crate::exit_code::ExitCode;
+
+// Load initial state, creating a new state if necessary
+pub fn load_initial_state(config: &AppConfig, state_path: &PathType) -> AppState {
+    match StatePersistence::load_state(state_path) {
+        Ok(loaded_data) => {
+            log!(LogLevel::Info, "Previous state data loaded");
+            loaded_data
+        }
+        Err(_) => {
+            log!(
+                LogLevel::Warn,
+                "No previous state file found, creating a new one"
+            );
+            let state = get_initial_state(config);
+            if let Err(err) = StatePersistence::save_state(&state, state_path) {
+                log!(
+                    LogLevel::Error,
+                    "Error occurred while saving new state: {}",
+                    err
+                );
+                ExitCode::StateSave.exit();
+            }
+            state
+        }
+    }
+}
+
+// Create an initial state
+pub fn get_initial_state(config: &AppConfig) -> AppState {
+    AppState {
+        data: String::new(),
+        last_updated: current_timestamp(),
+        event_counter: 0,
+        is_active: false,
+        error_log: vec![],
+        config: config.clone(),
+    }
+}
+
+// Update state and persist it to disk, false when it couldn't be saved
+pub fn update_state(state: &mut AppState, path: &PathType) -> bool {
+    state.last_updated = current_timestamp();
+    if let Err(err) = StatePersistence::save_state(state, path) {
+        log!(LogLevel::Error, "Failed to save state: {}", err);
+        state.is_active = false;
+        state.error_log.push(ErrorArrayItem::new(
+            Errors::GeneralError,
+            format!("{}", err),
+        ));
+        return false;
+    }
+    true
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::net::UdpSocket;
+
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use dusa_collection_utils::errors::ErrorArrayItem;
+
+use crate::health::HealthCheckResult;
+
+// Pushes per-site gauges and timers to a StatsD / DogStatsD agent over UDP
+pub struct StatsdEmitter {
+    socket: UdpSocket,
+    addr: String,
+    prefix: String,
+    dogstatsd: bool,
+}
+
+impl StatsdEmitter {
+    pub fn new(addr: &str, prefix: &str, dogstatsd: bool) -> Result<Self, ErrorArrayItem> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        Ok(StatsdEmitter {
+            socket,
+            addr: addr.to_string(),
+            prefix: prefix.to_string(),
+            dogstatsd,
+        })
+    }
+
+    pub fn emit(&self, results: &HashMap<String, HealthCheckResult>) {
+        for (url, result) in results {
+            let packet = self.format_site(url, result);
+            if let Err(e) = self.socket.send_to(packet.as_bytes(), &self.addr) {
+                log!(LogLevel::Warn, "Failed to push statsd metrics for {}: {}", url, e);
+            }
+        }
+    }
+
+    fn format_site(&self, url: &str, result: &HealthCheckResult) -> String {
+        let up = if result.status.is_down() { 0 } else { 1 };
+        let mut lines = vec![self.metric("up", up.to_string(), "g", url)];
+
+        if let Some(response_time) = result.response_time_ms {
+            lines.push(self.metric("response_time_ms", response_time.to_string(), "ms", url));
+        }
+        if let Some(dns_time) = result.dns_time_ms {
+            lines.push(self.metric("dns_time_ms", dns_time.to_string(), "ms", url));
+        }
+
+        lines.join("\n")
+    }
+
+    // DogStatsD carries the url as a tag, plain StatsD has to fold it into the name
+    fn metric(&self, name: &str, value: String, kind: &str, url: &str) -> String {
+        if self.dogstatsd {
+            format!(
+                "{}.{}:{}|{}|#url:{}",
+                self.prefix,
+                name,
+                value,
+                kind,
+                tag_value(url)
+            )
+        } else {
+            format!(
+                "{}.{}.{}:{}|{}",
+                self.prefix,
+                sanitize(url),
+                name,
+                value,
+                kind
+            )
+        }
+    }
+}
+
+// Commas split tags, `|` sections and `:` the tag's name from its value, a newline the
+// packet's metrics, so none of them may appear in the url tag
+fn tag_value(url: &str) -> String {
+    url.chars()
+        .map(|c| match c {
+            ',' | '|' | ':' | '#' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+fn sanitize(url: &str) -> String {
+    url.trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
@@ -0,0 +1,135 @@
+use std::fs;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use dusa_collection_utils::errors::ErrorArrayItem;
+
+use crate::config::{redact_url, StatusPageConfig};
+use crate::health::CheckStatus;
+use crate::report::format_timestamp;
+use crate::server::StatusSnapshot;
+
+// Renders the latest cycle as a standalone HTML page, no scripts or external assets.
+// Urls are shown with credentials masked since the page is often public.
+#[derive(Debug, Clone)]
+pub struct StatusPage {
+    path: Option<String>,
+    title: String,
+    refresh_seconds: u64,
+    timezone: Tz,
+}
+
+impl StatusPage {
+    pub fn new(config: &StatusPageConfig, timezone: Tz) -> Self {
+        StatusPage {
+            path: config.path.clone(),
+            title: config.title.clone(),
+            refresh_seconds: config.refresh_seconds.max(1),
+            timezone,
+        }
+    }
+
+    // Write the page to its file, if it has one. Renamed into place so a web server
+    // never hands out half a page.
+    pub fn write(&self, snapshot: &StatusSnapshot) -> Result<(), ErrorArrayItem> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let temp_path = format!("{}.tmp", path);
+        fs::write(&temp_path, self.render(snapshot))?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    pub fn render(&self, snapshot: &StatusSnapshot) -> String {
+        let mut urls: Vec<&String> = snapshot.sites.keys().collect();
+        urls.sort();
+        let down = snapshot
+            .sites
+            .values()
+            .filter(|result| result.status.is_down())
+            .count();
+
+        let updated = snapshot
+            .updated_at
+            .as_deref()
+            .and_then(|updated| DateTime::parse_from_rfc3339(updated).ok())
+            .map(|updated| format_timestamp(updated.with_timezone(&Utc), self.timezone))
+            .unwrap_or_else(|| String::from("no check completed yet"));
+        let overall = match (snapshot.sites.is_empty(), down) {
+            (true, _) => String::from("Waiting for the first check"),
+            (false, 0) => String::from("All systems operational"),
+            (false, down) => format!("{} of {} sites down", down, snapshot.sites.len()),
+        };
+
+        let mut page = format!(
+            concat!(
+                "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n",
+                "<meta charset=\"utf-8\">\n",
+                "<meta http-equiv=\"refresh\" content=\"{refresh}\">\n",
+                "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n",
+                "<title>{title}</title>\n<style>\n",
+                "body {{ font-family: sans-serif; max-width: 960px; margin: 2em auto; padding: 0 1em; color: #222; }}\n",
+                "table {{ width: 100%; border-collapse: collapse; }}\n",
+                "th, td {{ text-align: left; padding: 0.5em; border-bottom: 1px solid #ddd; }}\n",
+                ".up {{ color: #1a7f37; }} .degraded {{ color: #9a6700; }} .down {{ color: #cf222e; }}\n",
+                ".status {{ font-weight: bold; }}\n",
+                "</style>\n</head>\n<body>\n",
+                "<h1>{title}</h1>\n",
+                "<p class=\"{overall_class}\"><strong>{overall}</strong></p>\n",
+                "<p>Last updated: {updated}</p>\n",
+                "<table>\n<tr><th>Site</th><th>Status</th><th>Uptime</th><th>Latency</th></tr>\n"
+            ),
+            refresh = self.refresh_seconds,
+            title = escape_html(&self.title),
+            overall_class = if down > 0 { "down" } else { "up" },
+            overall = overall,
+            updated = escape_html(&updated),
+        );
+
+        for url in urls {
+            let result = &snapshot.sites[url];
+            let class = match result.status {
+                CheckStatus::Up => "up",
+                CheckStatus::Degraded => "degraded",
+                CheckStatus::Down => "down",
+            };
+            let uptime = snapshot
+                .uptime_percent
+                .get(url)
+                .map(|uptime| format!("{:.2}%", uptime))
+                .unwrap_or_else(|| String::from("-"));
+            let latency = result
+                .response_time_ms
+                .map(|ms| format!("{} ms", ms))
+                .unwrap_or_else(|| String::from("-"));
+            page.push_str(&format!(
+                "<tr><td>{}</td><td class=\"status {}\">{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(&redact_url(url)),
+                class,
+                result.status,
+                uptime,
+                latency
+            ));
+        }
+
+        page.push_str("</table>\n");
+        if let Some(skipped) = &snapshot.skipped {
+            page.push_str(&format!(
+                "<p class=\"degraded\">Latest check skipped ({}), showing earlier results</p>\n",
+                escape_html(skipped)
+            ));
+        }
+        page.push_str("</body>\n</html>\n");
+        page
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,189 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use artisan_middleware::config::AppConfig;
+use artisan_middleware::log;
+use artisan_middleware::logger::LogLevel;
+use artisan_middleware::state_persistence::{AppState, StatePersistence};
+use dusa_collection_utils::errors::{ErrorArrayItem, Errors};
+use dusa_collection_utils::types::PathType;
+
+use crate::at_rest::AtRest;
+use crate::exit_code::ExitCode;
+use crate::history::HistoryRecord;
+use crate::site_state::SiteStates;
+use crate::state::get_initial_state;
+
+/// Where the monitor keeps what it needs across cycles and restarts: the
+/// application state, the per-site state and the sampled result history.
+///
+/// [`FileStore`] is used unless another store is registered with
+/// [`MonitorBuilder::store`](crate::monitor::MonitorBuilder::store). A clustered
+/// deployment can share state between instances by implementing this trait over
+/// e.g. Redis:
+///
+/// ```ignore
+/// struct RedisStore { client: redis::Client }
+///
+/// impl StateStore for RedisStore {
+///     fn name(&self) -> &str { "redis" }
+///     fn load_state(&self) -> Result<Option<AppState>, ErrorArrayItem> { /* GET, deserialize */ }
+///     fn save_state(&self, state: &AppState) -> Result<(), ErrorArrayItem> { /* SET */ }
+///     fn load_site_states(&self) -> Result<Option<SiteStates>, ErrorArrayItem> { /* GET */ }
+///     fn save_site_states(&self, states: &SiteStates) -> Result<(), ErrorArrayItem> { /* SET */ }
+///     fn append_history(&self, records: &[HistoryRecord]) -> Result<(), ErrorArrayItem> { /* RPUSH */ }
+/// }
+///
+/// MonitorBuilder::new(config, settings)
+///     .store(Box::new(RedisStore { client }))
+///     .build()?
+///     .run()
+///     .await;
+/// ```
+///
+/// Every method is called from the monitor loop and should return promptly. A
+/// failed save is logged, recorded in the application state's error log and
+/// counts towards `max_consecutive_self_errors`; the monitor keeps running on
+/// what it has in memory.
+pub trait StateStore: Send + Sync {
+    /// Short name used when logging failures.
+    fn name(&self) -> &str;
+
+    /// The application state of a previous run, `None` when there is none yet.
+    fn load_state(&self) -> Result<Option<AppState>, ErrorArrayItem>;
+
+    /// Persist the application state, called at least once per cycle.
+    fn save_state(&self, state: &AppState) -> Result<(), ErrorArrayItem>;
+
+    /// The per-site state of a previous run, `None` when there is none yet.
+    fn load_site_states(&self) -> Result<Option<SiteStates>, ErrorArrayItem>;
+
+    /// Persist the per-site state, called once per cycle.
+    fn save_site_states(&self, states: &SiteStates) -> Result<(), ErrorArrayItem>;
+
+    /// Append this cycle's sampled results to the history. Stores without a
+    /// history can do nothing.
+    fn append_history(&self, records: &[HistoryRecord]) -> Result<(), ErrorArrayItem>;
+}
+
+/// The default store: the artisan_middleware state file, the site state file and
+/// the `history_path` JSON lines file, the latter two encoded per `at_rest`.
+pub struct FileStore {
+    state_path: PathType,
+    site_state_path: PathType,
+    history_path: Option<String>,
+    at_rest: AtRest,
+}
+
+impl FileStore {
+    /// No history is written without a `history_path`.
+    pub fn new(
+        state_path: PathType,
+        site_state_path: PathType,
+        history_path: Option<String>,
+        at_rest: AtRest,
+    ) -> Self {
+        FileStore {
+            state_path,
+            site_state_path,
+            history_path,
+            at_rest,
+        }
+    }
+
+    /// Every history record written so far, skipping lines that can't be read.
+    pub fn load_history(&self) -> Vec<HistoryRecord> {
+        let content = match self
+            .history_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+        {
+            Some(content) => content,
+            None => return Vec::new(),
+        };
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| self.at_rest.decode_line(line).ok())
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect()
+    }
+}
+
+impl StateStore for FileStore {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    // A missing or unreadable state file means starting over, as before stores
+    fn load_state(&self) -> Result<Option<AppState>, ErrorArrayItem> {
+        Ok(StatePersistence::load_state(&self.state_path).ok())
+    }
+
+    fn save_state(&self, state: &AppState) -> Result<(), ErrorArrayItem> {
+        StatePersistence::save_state(state, &self.state_path)
+            .map_err(|e| ErrorArrayItem::new(Errors::GeneralError, e.to_string()))
+    }
+
+    fn load_site_states(&self) -> Result<Option<SiteStates>, ErrorArrayItem> {
+        Ok(Some(SiteStates::load(&self.site_state_path, &self.at_rest)))
+    }
+
+    fn save_site_states(&self, states: &SiteStates) -> Result<(), ErrorArrayItem> {
+        states.save(&self.site_state_path, &self.at_rest)
+    }
+
+    fn append_history(&self, records: &[HistoryRecord]) -> Result<(), ErrorArrayItem> {
+        let path = match &self.history_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = String::new();
+        for record in records {
+            let line = serde_json::to_string(record)
+                .map_err(|e| ErrorArrayItem::new(Errors::JsonCreation, e.to_string()))?;
+            lines.push_str(&self.at_rest.encode_line(&line)?);
+            lines.push('\n');
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(lines.as_bytes())?;
+        Ok(())
+    }
+}
+
+// The stored application state, or a new one saved right away so a store that
+// can't be written to is noticed at startup
+pub fn load_or_create_state(config: &AppConfig, store: &dyn StateStore) -> AppState {
+    match store.load_state() {
+        Ok(Some(state)) => {
+            log!(LogLevel::Info, "Previous state data loaded");
+            return state;
+        }
+        Ok(None) => log!(
+            LogLevel::Warn,
+            "No previous state found in the {} store, creating a new one",
+            store.name()
+        ),
+        Err(err) => log!(
+            LogLevel::Warn,
+            "Failed to load state from the {} store, creating a new one: {}",
+            store.name(),
+            err
+        ),
+    }
+
+    let state = get_initial_state(config);
+    if let Err(err) = store.save_state(&state) {
+        log!(
+            LogLevel::Error,
+            "Error occurred while saving new state: {}",
+            err
+        );
+        ExitCode::StateSave.exit();
+    }
+    state
+}
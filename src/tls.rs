@@ -0,0 +1,320 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use openssl::error::ErrorStack;
+use openssl::pkey::PKey;
+use openssl::ssl::{SslConnector, SslMethod, SslRef, SslVerifyMode, SslVersion};
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::{X509Ref, X509StoreContext, X509VerifyResult, X509};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tokio::net::{lookup_host, TcpSocket};
+
+// X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT(_LOCALLY): the chain stops short of a trusted root
+const VERIFY_ERR_MISSING_ISSUER: [i32; 2] = [2, 20];
+
+// Protocol versions in the order they were introduced, so newer compares greater
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TlsVersion {
+    #[serde(rename = "1.0")]
+    Tls10,
+    #[serde(rename = "1.1")]
+    Tls11,
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+impl TlsVersion {
+    // Parse the protocol name OpenSSL reports, e.g. "TLSv1.3"
+    pub fn from_protocol(protocol: &str) -> Option<Self> {
+        match protocol {
+            "TLSv1" => Some(TlsVersion::Tls10),
+            "TLSv1.1" => Some(TlsVersion::Tls11),
+            "TLSv1.2" => Some(TlsVersion::Tls12),
+            "TLSv1.3" => Some(TlsVersion::Tls13),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsVersion::Tls10 => write!(f, "TLSv1"),
+            TlsVersion::Tls11 => write!(f, "TLSv1.1"),
+            TlsVersion::Tls12 => write!(f, "TLSv1.2"),
+            TlsVersion::Tls13 => write!(f, "TLSv1.3"),
+        }
+    }
+}
+
+// Something wrong with the served certificates that a browser may paper over
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TlsFinding {
+    // The server doesn't send the intermediates needed to reach a trusted root
+    IncompleteChain { detail: String },
+    // The leaf certificate's SAN doesn't cover the host
+    HostnameMismatch { host: String, names: Vec<String> },
+    // The chain is complete but fails verification for another reason
+    InvalidChain { detail: String },
+}
+
+impl fmt::Display for TlsFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsFinding::IncompleteChain { detail } => {
+                write!(f, "incomplete certificate chain ({})", detail)
+            }
+            TlsFinding::HostnameMismatch { host, names } if names.is_empty() => {
+                write!(f, "certificate has no SAN names, {} is not covered", host)
+            }
+            TlsFinding::HostnameMismatch { host, names } => write!(
+                f,
+                "certificate does not cover {} (SAN: {})",
+                host,
+                names.join(", ")
+            ),
+            TlsFinding::InvalidChain { detail } => {
+                write!(f, "certificate chain does not verify ({})", detail)
+            }
+        }
+    }
+}
+
+pub struct NegotiatedTls {
+    pub version: String,
+    pub cipher: String,
+    // Phases of this handshake's connection, not the one the request went over
+    pub connect_ms: u128,
+    pub handshake_ms: u128,
+    // What auditing the served certificates found, empty when not audited
+    pub findings: Vec<TlsFinding>,
+}
+
+// How the check's request reached the server, so the separate handshake takes the same
+// path: the address it connected to, the source address, the client certificate
+pub struct ProbeTarget {
+    // The request url, its host is the name sent as SNI
+    pub url: String,
+    // Resolved again when the request's address isn't known
+    pub address: Option<IpAddr>,
+    pub local_address: Option<IpAddr>,
+    // PEM encoded client certificate and key, for servers requiring one
+    pub identity: Option<(Vec<u8>, Vec<u8>)>,
+    pub timeout: Duration,
+}
+
+// reqwest doesn't expose what was negotiated, so do a separate handshake and ask OpenSSL.
+// Certificates aren't verified during it, the request itself already covers that, but
+// with `audit` the served chain and the SAN are checked on their own afterwards.
+pub async fn probe(target: ProbeTarget, audit: bool) -> Result<NegotiatedTls, String> {
+    let parsed = Url::parse(&target.url).map_err(|e| e.to_string())?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("no host in {}", target.url))?
+        .to_string();
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    let address = match target.address {
+        Some(address) => SocketAddr::new(address, port),
+        None => resolve(&host, port, target.timeout).await?,
+    };
+
+    let (stream, connect_ms) = connect(address, target.local_address, target.timeout).await?;
+    let identity = target.identity;
+    tokio::task::spawn_blocking(move || {
+        handshake(stream, &host, false, audit, identity.as_ref(), connect_ms)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// A handshake that checks the certificate chain and hostname like a client would
+pub async fn verified_handshake(host: &str, port: u16) -> Result<NegotiatedTls, String> {
+    let timeout = Duration::from_secs(10);
+    let address = resolve(host, port, timeout).await?;
+    let (stream, connect_ms) = connect(address, None, timeout).await?;
+    let host = host.to_string();
+    tokio::task::spawn_blocking(move || handshake(stream, &host, true, false, None, connect_ms))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+async fn resolve(host: &str, port: u16, timeout: Duration) -> Result<SocketAddr, String> {
+    match tokio::time::timeout(timeout, lookup_host((host, port))).await {
+        Ok(Ok(mut addresses)) => addresses
+            .next()
+            .ok_or_else(|| format!("{} did not resolve", host)),
+        Ok(Err(e)) => Err(format!("cannot resolve {}: {}", host, e)),
+        Err(_) => Err(format!("resolving {} timed out", host)),
+    }
+}
+
+// Connect asynchronously, then hand over a blocking stream for OpenSSL to handshake on.
+// Its read and write timeouts bound the handshake.
+async fn connect(
+    address: SocketAddr,
+    local_address: Option<IpAddr>,
+    timeout: Duration,
+) -> Result<(std::net::TcpStream, u128), String> {
+    let socket = match address {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    }
+    .map_err(|e| e.to_string())?;
+    if let Some(local_address) = local_address {
+        socket
+            .bind(SocketAddr::new(local_address, 0))
+            .map_err(|e| format!("cannot bind to {}: {}", local_address, e))?;
+    }
+    let connect_start = Instant::now();
+    let stream = match tokio::time::timeout(timeout, socket.connect(address)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return Err(format!("cannot connect to {}: {}", address, e)),
+        Err(_) => return Err(format!("connecting to {} timed out", address)),
+    };
+    let connect_ms = connect_start.elapsed().as_millis();
+
+    let stream = stream.into_std().map_err(|e| e.to_string())?;
+    stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .map_err(|e| e.to_string())?;
+    Ok((stream, connect_ms))
+}
+
+fn handshake(
+    stream: std::net::TcpStream,
+    host: &str,
+    verify: bool,
+    audit: bool,
+    identity: Option<&(Vec<u8>, Vec<u8>)>,
+    connect_ms: u128,
+) -> Result<NegotiatedTls, String> {
+    let mut connector =
+        SslConnector::builder(SslMethod::tls_client()).map_err(|e| e.to_string())?;
+    if !verify {
+        connector.set_verify(SslVerifyMode::NONE);
+    }
+    if let Some((cert, key)) = identity {
+        let cert = X509::from_pem(cert).map_err(|e| format!("invalid client_cert: {}", e))?;
+        let key =
+            PKey::private_key_from_pem(key).map_err(|e| format!("invalid client_key: {}", e))?;
+        connector
+            .set_certificate(&cert)
+            .and_then(|_| connector.set_private_key(&key))
+            .map_err(|e| e.to_string())?;
+    }
+    // Allow the old versions too, otherwise a weak server just looks unreachable
+    connector
+        .set_min_proto_version(Some(SslVersion::TLS1))
+        .map_err(|e| e.to_string())?;
+    let handshake_start = Instant::now();
+    let ssl = connector
+        .build()
+        .connect(host, stream)
+        .map_err(|e| format!("TLS handshake with {} failed: {}", host, e))?;
+    let handshake_ms = handshake_start.elapsed().as_millis();
+
+    let session = ssl.ssl();
+    let findings = if audit {
+        audit_certificates(host, session)?
+    } else {
+        Vec::new()
+    };
+    Ok(NegotiatedTls {
+        version: session.version_str().to_string(),
+        cipher: session
+            .current_cipher()
+            .map(|cipher| cipher.name().to_string())
+            .unwrap_or_default(),
+        connect_ms,
+        handshake_ms,
+        findings,
+    })
+}
+
+fn audit_certificates(host: &str, session: &SslRef) -> Result<Vec<TlsFinding>, String> {
+    let leaf = session
+        .peer_certificate()
+        .ok_or_else(|| format!("{} sent no certificate", host))?;
+    let mut findings = Vec::new();
+
+    let names = san_names(&leaf);
+    if !names.iter().any(|name| name_covers(name, host)) {
+        findings.push(TlsFinding::HostnameMismatch {
+            host: host.to_string(),
+            names,
+        });
+    }
+
+    // Verify with only what the server sent plus the system roots, no AIA fetching,
+    // which is where an incomplete chain shows up
+    let error = verify_served_chain(&leaf, session).map_err(|e| e.to_string())?;
+    if error != X509VerifyResult::OK {
+        let detail = error.error_string().to_string();
+        findings.push(if VERIFY_ERR_MISSING_ISSUER.contains(&error.as_raw()) {
+            TlsFinding::IncompleteChain { detail }
+        } else {
+            TlsFinding::InvalidChain { detail }
+        });
+    }
+
+    Ok(findings)
+}
+
+fn verify_served_chain(leaf: &X509Ref, session: &SslRef) -> Result<X509VerifyResult, ErrorStack> {
+    let mut store = X509StoreBuilder::new()?;
+    store.set_default_paths()?;
+    let store = store.build();
+
+    let mut served = Stack::<X509>::new()?;
+    for cert in session.peer_cert_chain().into_iter().flatten() {
+        served.push(cert.to_owned())?;
+    }
+
+    let mut context = X509StoreContext::new()?;
+    context.init(&store, leaf, &served, |context| {
+        context.verify_cert()?;
+        Ok(context.error())
+    })
+}
+
+// DNS and IP entries of the subject alternative name
+fn san_names(cert: &X509Ref) -> Vec<String> {
+    cert.subject_alt_names()
+        .into_iter()
+        .flatten()
+        .filter_map(|name| {
+            if let Some(dns) = name.dnsname() {
+                return Some(dns.to_string());
+            }
+            match name.ipaddress()? {
+                [a, b, c, d] => Some(IpAddr::from([*a, *b, *c, *d]).to_string()),
+                octets => <[u8; 16]>::try_from(octets)
+                    .ok()
+                    .map(|octets| IpAddr::from(octets).to_string()),
+            }
+        })
+        .collect()
+}
+
+// RFC 6125 matching: exact, or a wildcard standing in for exactly the left-most label
+fn name_covers(name: &str, host: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    if let (Ok(name), Ok(host)) = (name.parse::<IpAddr>(), host.parse::<IpAddr>()) {
+        return name == host;
+    }
+    match name.strip_prefix("*.") {
+        Some(suffix) => host
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest == suffix),
+        None => name == host,
+    }
+}
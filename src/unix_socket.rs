@@ -0,0 +1,103 @@
+use http_body_util::{BodyExt, Empty};
+use hyper::body::{Body, Bytes};
+use hyper::header::{HeaderMap, HOST, USER_AGENT};
+use hyper::{Method, Request};
+use hyper_util::rt::TokioIo;
+use tokio::net::UnixStream;
+use tokio::time::Instant;
+
+use crate::health::CheckError;
+
+pub const UNIX_SCHEME: &str = "unix://";
+
+// Split `unix:///run/app.sock:/health` into the socket path and the request target
+pub fn split_unix_url(url: &str) -> (&str, &str) {
+    let rest = url.strip_prefix(UNIX_SCHEME).unwrap_or(url);
+    match rest.rfind(":/") {
+        Some(index) => (&rest[..index], &rest[index + 1..]),
+        None => (rest, "/"),
+    }
+}
+
+// What came back over the socket, for the status and body checks of the caller
+pub struct UnixResponse {
+    pub status_code: u16,
+    pub headers: HeaderMap,
+    pub response_time_ms: u128,
+    pub body: Vec<u8>,
+    pub body_time_ms: Option<u128>,
+    // The body broke off, `body` holds what arrived before
+    pub body_error: Option<String>,
+}
+
+// Issue one request over a unix domain socket, timed like a regular http check. The
+// caller bounds it with the site's timeout.
+pub async fn request(
+    url: &str,
+    method: Method,
+    host_header: Option<&str>,
+    read_body: bool,
+) -> Result<UnixResponse, CheckError> {
+    let (socket_path, target) = split_unix_url(url);
+    let start_time = Instant::now();
+
+    let stream = UnixStream::connect(socket_path)
+        .await
+        .map_err(|e| CheckError::Connect(format!("could not connect to {}: {}", socket_path, e)))?;
+
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(|e| {
+            CheckError::Connect(format!("http handshake over {} failed: {}", socket_path, e))
+        })?;
+    tokio::spawn(connection);
+
+    let request = Request::builder()
+        .method(method)
+        .uri(target)
+        .header(HOST, host_header.unwrap_or("localhost"))
+        .header(USER_AGENT, "HealthChecker/1.0")
+        .body(Empty::<Bytes>::new())
+        .map_err(|e| CheckError::Client(e.to_string()))?;
+
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(|e| CheckError::Connect(e.to_string()))?;
+    let response_time_ms: u128 = start_time.elapsed().as_millis();
+
+    let status_code = response.status().as_u16();
+    let headers = response.headers().clone();
+    let mut body: Vec<u8> = Vec::new();
+    let mut body_error: Option<String> = None;
+    let mut body_time_ms: Option<u128> = None;
+    // With read_body off the connection is dropped after the headers
+    if read_body {
+        let body_start: Instant = Instant::now();
+        let mut incoming = response.into_body();
+        while !incoming.is_end_stream() {
+            match incoming.frame().await {
+                Some(Ok(frame)) => {
+                    if let Some(chunk) = frame.data_ref() {
+                        body.extend_from_slice(chunk);
+                    }
+                }
+                Some(Err(e)) => {
+                    body_error = Some(e.to_string());
+                    break;
+                }
+                None => break,
+            }
+        }
+        body_time_ms = Some(body_start.elapsed().as_millis());
+    }
+
+    Ok(UnixResponse {
+        status_code,
+        headers,
+        response_time_ms,
+        body,
+        body_time_ms,
+        body_error,
+    })
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+
+use crate::config::WebhookPreset;
+use crate::notifier::CycleSummary;
+
+// Slack and Discord render the report as a code block
+const SLACK_TEMPLATE: &str = r#"{"text": "*{{subject}}*\n```{{report}}```"}"#;
+const DISCORD_TEMPLATE: &str = r#"{"content": "**{{subject}}**\n```{{report}}```"}"#;
+const GENERIC_TEMPLATE: &str = r#"{"subject": "{{subject}}", "status": "{{status}}", "down_count": {{down_count}}, "url": "{{url}}", "labels": {{labels}}, "alerts": {{alerts}}, "report": "{{report}}"}"#;
+
+// Discord rejects messages over 2000 characters, leave room for the rest of the template
+const DISCORD_REPORT_LIMIT: usize = 1800;
+
+pub fn preset_template(preset: WebhookPreset) -> &'static str {
+    match preset {
+        WebhookPreset::Generic => GENERIC_TEMPLATE,
+        WebhookPreset::Slack => SLACK_TEMPLATE,
+        WebhookPreset::Discord => DISCORD_TEMPLATE,
+    }
+}
+
+pub fn report_limit(preset: WebhookPreset) -> Option<usize> {
+    match preset {
+        WebhookPreset::Discord => Some(DISCORD_REPORT_LIMIT),
+        _ => None,
+    }
+}
+
+// Fill in the placeholders in one pass, so text inside a value is never expanded again.
+// String values are JSON escaped to sit inside quotes, down_count, labels and alerts
+// (runbook_url / alert_message of each DOWN site) are raw JSON.
+// Unknown placeholders are left as they are.
+pub fn render(
+    template: &str,
+    subject: &str,
+    report: &str,
+    summary: &CycleSummary,
+    labels: &BTreeMap<String, String>,
+    report_limit: Option<usize>,
+) -> String {
+    let report = match report_limit {
+        Some(limit) if report.chars().count() > limit => {
+            format!(
+                "{}\n... (truncated)",
+                report.chars().take(limit).collect::<String>()
+            )
+        }
+        _ => report.to_string(),
+    };
+
+    let mut rendered = String::with_capacity(template.len() + report.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = match after.find("}}") {
+            Some(end) => end,
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+        let value = match after[..end].trim() {
+            "subject" => escape(subject),
+            "report" => escape(&report),
+            "status" => escape(&summary.status.to_string()),
+            "url" => escape(&summary.down_urls.join(", ")),
+            "down_count" => summary.down_urls.len().to_string(),
+            "labels" => serde_json::to_string(labels).unwrap_or_else(|_| String::from("{}")),
+            "alerts" => {
+                serde_json::to_string(&summary.alert_context).unwrap_or_else(|_| String::from("[]"))
+            }
+            _ => rest[start..start + end + 4].to_string(),
+        };
+        rendered.push_str(&value);
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+// The contents of a JSON string literal, without the quotes
+fn escape(value: &str) -> String {
+    let quoted = serde_json::Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}